@@ -1,10 +1,42 @@
-use std::{fs, process};
+use std::path::PathBuf;
+use std::{fmt, fs, process};
 
 use anyhow::{bail, Result};
 use lexopt::Parser;
 
+use crate::model::CaptureResolver;
+use crate::replace::backup;
+use crate::replace::report::ReportOutput;
+use crate::replace::stale::OnStale;
+
 pub const ENV_JSON_FILE: &str = "RGR_JSON_FILE";
 
+/// ripgrep's flags which change what's matched, see `RgArgs::rerun_args`.
+const MATCHING_FLAGS: &[&str] = &[
+    "-i",
+    "--ignore-case",
+    "-S",
+    "--smart-case",
+    "-s",
+    "--case-sensitive",
+    "-w",
+    "--word-regexp",
+    "-x",
+    "--line-regexp",
+    "-U",
+    "--multiline",
+    "--multiline-dotall",
+    "-P",
+    "--pcre2",
+    "--auto-hybrid-regex",
+    "--crlf",
+    "--no-unicode",
+    "--null-data",
+];
+
+/// ripgrep's options (with a value) which change what's matched, see `RgArgs::rerun_args`.
+const MATCHING_OPTIONS: &[&str] = &["--engine=", "--regex-size-limit=", "--dfa-size-limit="];
+
 pub fn print_help() {
     println!(
         "{}",
@@ -19,8 +51,83 @@ and replace across files on the command line.
 Project home page: {crate_homepage}
 
 USAGE:
-    {bin} <RG_ARGS>...
-    {env_file}=path/to/rg.json rgr [REGEX]
+    {bin} [RGR_OPTIONS] <RG_ARGS>...
+    {env_file}=path/to/rg.json rgr [RGR_OPTIONS] [REGEX]
+
+RGR_OPTIONS:
+    These options are handled by {bin} itself and are not forwarded to rg.
+
+    --dry-run[=PATCH_FILE]
+        Don't write any changes to disk. Instead, print a unified diff of every file that would
+        have been changed to stdout (or write it to PATCH_FILE). The diff can be applied later
+        with `git apply` or `patch -p1`.
+
+    --undo
+        Restore all the files that were changed by the last replacement session, and exit.
+        This refuses to restore anything if any of those files have changed since. Very large
+        files that were streamed rather than read into memory are not journaled, so they can't
+        be restored.
+
+    --replace <TEXT>
+        Don't start the interface, instead replace every match with TEXT and print a summary of
+        the files and matches changed. This must be used with either --yes (to write the changes)
+        or --dry-run (to print them instead). Capturing groups and templates work in the same way
+        as they do in the interface. Note that rg's own -r/--replace is not available.
+
+    --yes
+        Write the replacements made with --replace without asking for confirmation.
+
+    --highlight
+        Highlight the syntax of the lines in the interface, with the language picked from the
+        extension of each file.
+
+    --session <FILE>
+        Save the session to FILE instead of the default location. The session holds the results
+        of the search along with which matches are selected and the replacement text, and is saved
+        as the interface is used.
+
+    --resume
+        Resume the last saved session (or the one in the file given with --session) instead of
+        searching. This refuses to resume if any of the files with matches have changed since they
+        were searched.
+
+    --on-stale <ACTION>
+        What to do with a file that has changed since it was searched, when the replacements are
+        about to be written. ACTION is one of:
+            ask     ask for each file (the default, unless --replace is used)
+            skip    leave the file unchanged (the default with --replace)
+            rerun   search the file again with rg, and keep the selection for the matches that
+                    are found again
+            force   replace the matches anyway, skipping any that are no longer in the same place
+
+    --replace-threads <NUM>
+        The number of threads used to rewrite files. Defaults to the number of CPUs.
+
+    --replace-max-bytes <SIZE>
+        The limit of the total size of the files being rewritten at once, so that several large
+        files aren't loaded into memory together. A file larger than this is rewritten on its own.
+        SIZE is a number of bytes with an optional K, M or G suffix. Defaults to 256M.
+
+    --transaction
+        Replace every file or none of them. The replaced contents of each file are written beside
+        it first, and only once every file is ready are they renamed over the originals. If any of
+        those renames fail, the files that were already replaced are restored.
+
+    --report[=REPORT_FILE]
+        Once the replacements have been made, write a report of them as JSON to stdout (or to
+        REPORT_FILE). The report lists each file with the number of matches replaced, the matches
+        that were skipped and why, the file's BOM and encoding, and any error. With --dry-run it
+        describes the replacements that would have been made.
+
+    --backup[=SUFFIX]
+        Keep the original of each file before it's replaced, with SUFFIX appended to its name
+        (".orig" by default). The backup keeps the original's permissions and timestamps. SUFFIX
+        may only be empty with --backup-dir.
+
+    --backup-dir <DIR>
+        Keep the original of each file in DIR rather than beside it, at the same absolute path
+        inside DIR (e.g., "/etc/hosts" is kept as "DIR/etc/hosts"). Without --backup, the name of
+        each file is left unchanged.
 
 EXAMPLES:
     There are different ways to invoke {bin}:
@@ -65,6 +172,231 @@ EXAMPLES:
     );
 }
 
+/// Where the diff should be written when running in dry-run mode.
+#[derive(Debug, PartialEq, Eq)]
+pub enum DryRun {
+    Stdout,
+    File(PathBuf),
+}
+
+/// Options that are specific to rgr, and are never forwarded to ripgrep.
+#[derive(Debug, Default)]
+pub struct RgrOptions {
+    /// If set, no files are written and a diff of the replacements is output instead.
+    pub dry_run: Option<DryRun>,
+    /// If true, undo the last replacement session rather than searching.
+    pub undo: bool,
+    /// If set, the replacements are made with this text without starting the interface.
+    pub replace: Option<String>,
+    /// If true, the replacements are written without asking for confirmation.
+    pub yes: bool,
+    /// If true, the lines in the interface are highlighted according to the language of each file.
+    pub highlight: bool,
+    /// If set, the session is saved to this file rather than the default one.
+    pub session: Option<PathBuf>,
+    /// If true, the saved session is resumed rather than searching.
+    pub resume: bool,
+    /// If set, what to do with files that have changed since they were searched. Otherwise the user
+    /// is asked, unless there's no interface.
+    pub on_stale: Option<OnStale>,
+    /// If set, the number of threads used to rewrite files.
+    pub replace_threads: Option<usize>,
+    /// If set, the limit of the total size of the files being rewritten at once.
+    pub replace_max_bytes: Option<u64>,
+    /// If set, where a JSON report of the replacements is written.
+    pub report: Option<ReportOutput>,
+    /// If true, either every file is replaced or none of them are.
+    pub transaction: bool,
+    /// If set, the original of each file is kept with this suffix before it's replaced.
+    pub backup: Option<String>,
+    /// If set, the originals of the replaced files are kept in this directory.
+    pub backup_dir: Option<PathBuf>,
+}
+
+impl RgrOptions {
+    /// Returns true if the given long option name is one of rgr's own options.
+    fn is_rgr_option(name: &str) -> bool {
+        matches!(
+            name,
+            "dry-run"
+                | "undo"
+                | "replace"
+                | "yes"
+                | "highlight"
+                | "session"
+                | "resume"
+                | "on-stale"
+                | "replace-threads"
+                | "replace-max-bytes"
+                | "report"
+                | "transaction"
+                | "backup"
+                | "backup-dir"
+        )
+    }
+
+    /// Checks the options which depend on each other, once they've all been parsed.
+    fn validate(&self) -> Result<()> {
+        // without a directory, the backup would be the file itself
+        if self.backup.as_deref() == Some("") && self.backup_dir.is_none() {
+            bail!("--backup needs a suffix unless --backup-dir is also passed");
+        }
+
+        Ok(())
+    }
+
+    fn parse_option(&mut self, name: &str, parser: &mut Parser) -> Result<()> {
+        use lexopt::ValueExt;
+
+        match name {
+            "dry-run" => {
+                self.dry_run = Some(match parser.optional_value() {
+                    Some(path) => DryRun::File(PathBuf::from(path)),
+                    None => DryRun::Stdout,
+                });
+            }
+            "undo" => self.undo = true,
+            "replace" => self.replace = Some(parser.value()?.string()?),
+            "yes" => self.yes = true,
+            "highlight" => self.highlight = true,
+            "session" => self.session = Some(PathBuf::from(parser.value()?)),
+            "resume" => self.resume = true,
+            "on-stale" => self.on_stale = Some(parser.value()?.string()?.parse()?),
+            "replace-threads" => match parser.value()?.parse()? {
+                0 => bail!("--replace-threads must be at least 1"),
+                threads => self.replace_threads = Some(threads),
+            },
+            "transaction" => self.transaction = true,
+            "report" => {
+                self.report = Some(match parser.optional_value() {
+                    Some(path) => ReportOutput::File(PathBuf::from(path)),
+                    None => ReportOutput::Stdout,
+                });
+            }
+            "backup" => {
+                self.backup = Some(match parser.optional_value() {
+                    Some(suffix) => suffix.string()?,
+                    None => backup::DEFAULT_SUFFIX.to_string(),
+                });
+            }
+            "backup-dir" => self.backup_dir = Some(PathBuf::from(parser.value()?)),
+            "replace-max-bytes" => {
+                self.replace_max_bytes = Some(parse_size(&parser.value()?.string()?)?)
+            }
+            _ => bail!("unknown rgr option: --{}", name),
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses a number of bytes, with an optional K, M or G suffix (in the same way as ripgrep's
+/// `--max-filesize`).
+fn parse_size(s: &str) -> Result<u64> {
+    let (digits, multiplier) = match s.char_indices().last() {
+        Some((i, 'K')) => (&s[..i], 1 << 10),
+        Some((i, 'M')) => (&s[..i], 1 << 20),
+        Some((i, 'G')) => (&s[..i], 1 << 30),
+        _ => (s, 1),
+    };
+
+    match digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+    {
+        Some(size) => Ok(size),
+        None => bail!(
+            "invalid size '{}', expected a number of bytes such as 512K or 64M",
+            s
+        ),
+    }
+}
+
+/// Flags which ask for information to be printed rather than for a search to be run.
+#[derive(Debug)]
+enum InfoFlag {
+    Help,
+    Version,
+}
+
+impl fmt::Display for InfoFlag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InfoFlag::Help => write!(f, "--help is not supported here"),
+            InfoFlag::Version => write!(f, "--version is not supported here"),
+        }
+    }
+}
+
+impl std::error::Error for InfoFlag {}
+
+/// Splits a line of text into arguments in the same way a (simple) shell would: arguments are
+/// separated by whitespace, and may be quoted with single or double quotes or escaped with `\`.
+pub fn split_args(line: &str) -> Result<Vec<String>> {
+    let mut args = vec![];
+    let mut current: Option<String> = None;
+    let mut chars = line.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            ch if ch.is_whitespace() => {
+                if let Some(arg) = current.take() {
+                    args.push(arg);
+                }
+            }
+            '\'' => {
+                let arg = current.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(ch) => arg.push(ch),
+                        None => bail!("unterminated single quote"),
+                    }
+                }
+            }
+            '"' => {
+                let arg = current.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(ch @ ('"' | '\\')) => arg.push(ch),
+                            Some(ch) => {
+                                arg.push('\\');
+                                arg.push(ch);
+                            }
+                            None => bail!("unterminated double quote"),
+                        },
+                        Some(ch) => arg.push(ch),
+                        None => bail!("unterminated double quote"),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some(ch) => current.get_or_insert_with(String::new).push(ch),
+                None => bail!("trailing backslash"),
+            },
+            ch => current.get_or_insert_with(String::new).push(ch),
+        }
+    }
+
+    if let Some(arg) = current.take() {
+        args.push(arg);
+    }
+
+    Ok(args)
+}
+
+/// Quotes an argument so it's split back into the same argument by `split_args`.
+pub fn quote_arg(arg: &str) -> String {
+    let is_safe = |ch: char| ch.is_alphanumeric() || "-_=./:,@%+".contains(ch);
+    if !arg.is_empty() && arg.chars().all(is_safe) {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
+
 #[derive(Debug, PartialEq, Eq)]
 enum ExecStyle {
     Normal,
@@ -79,11 +411,12 @@ pub struct RgArgs {
     /// we perform any replacements ourselves.
     pub encoding: Option<String>,
     /// Whether fixed strings was enabled - means we only need to substring search rather than
-    /// regular expression searching.
-    /// TODO: this is currently unused, we need to update `replace.rs` to use it
+    /// regular expression searching, and that the replacement is always inserted literally.
     pub fixed_strings: bool,
     /// All other args that were passed will be forwarded to ripgrep.
     pub other_args: Vec<String>,
+    /// Options which configure rgr itself.
+    pub options: RgrOptions,
 
     exec_style: ExecStyle,
 }
@@ -96,6 +429,36 @@ impl RgArgs {
         }
     }
 
+    /// Returns the args used to search a single file again (e.g., if it changed since it was
+    /// searched), or `None` if there aren't any patterns to search with. Only the args which change
+    /// what's matched are kept, so the file's path can be passed on its own.
+    pub fn rerun_args(&self) -> Option<Vec<String>> {
+        if self.patterns.is_empty() {
+            return None;
+        }
+
+        let mut args = self
+            .other_args
+            .iter()
+            .filter(|arg| {
+                MATCHING_FLAGS.contains(&arg.as_str())
+                    || MATCHING_OPTIONS.iter().any(|opt| arg.starts_with(opt))
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        if self.fixed_strings {
+            args.push("--fixed-strings".into());
+        }
+        if let Some(encoding) = &self.encoding {
+            args.push(format!("--encoding={}", encoding));
+        }
+        for pattern in &self.patterns {
+            args.push(format!("--regexp={}", pattern));
+        }
+
+        Some(args)
+    }
+
     pub fn rg_args(&self) -> Vec<String> {
         let mut args = self.other_args.clone();
         if self.fixed_strings {
@@ -111,6 +474,18 @@ impl RgArgs {
         args
     }
 
+    /// Parses the patterns into a `CaptureResolver` if they should be used for capturing groups
+    /// when replacing. Returns `None` if none of the patterns have capturing groups, or if they're
+    /// fixed strings rather than regular expressions.
+    pub fn capture_pattern(&self) -> Result<Option<CaptureResolver>, regex::Error> {
+        if self.fixed_strings {
+            return Ok(None);
+        }
+
+        let resolver = CaptureResolver::new(&self.patterns)?;
+        Ok(resolver.has_capturing_groups().then_some(resolver))
+    }
+
     pub fn parse_pattern() -> Result<RgArgs> {
         RgArgs::parse_pattern_impl(Parser::from_env())
     }
@@ -119,27 +494,67 @@ impl RgArgs {
         use lexopt::prelude::*;
 
         let mut patterns = vec![];
+        let mut options = RgrOptions::default();
 
         while let Some(arg) = parser.next()? {
             match arg {
                 Value(pat) if patterns.is_empty() => patterns.push(pat.string()?),
+                Long(name) if RgrOptions::is_rgr_option(name) => {
+                    let name = name.to_string();
+                    options.parse_option(&name, &mut parser)?;
+                }
                 _ => {
                     bail!("{}\nSee --help for usage", arg.unexpected())
                 }
             }
         }
+        options.validate()?;
 
         Ok(RgArgs {
             patterns,
             encoding: None,
             fixed_strings: false,
             other_args: vec![],
+            options,
             exec_style: ExecStyle::Json,
         })
     }
 
     pub fn parse_rg_args() -> Result<RgArgs> {
-        RgArgs::parse_rg_args_impl(Parser::from_env())
+        let result = RgArgs::parse_rg_args_impl(Parser::from_env());
+        match result.as_ref().map_err(|e| e.downcast_ref::<InfoFlag>()) {
+            Err(Some(InfoFlag::Help)) => {
+                print_help();
+                process::exit(0);
+            }
+            Err(Some(InfoFlag::Version)) => {
+                println!(
+                    "{crate_name} {crate_version}",
+                    crate_name = env!("CARGO_PKG_NAME"),
+                    crate_version = env!("CARGO_PKG_VERSION")
+                );
+                process::exit(0);
+            }
+            _ => result,
+        }
+    }
+
+    /// Parses ripgrep arguments from a single line of text, as entered when editing the search.
+    pub fn parse_search(cmdline: &str) -> Result<RgArgs> {
+        let args = split_args(cmdline)?;
+        RgArgs::parse_rg_args_impl(Parser::from_iter(
+            std::iter::once(env!("CARGO_BIN_NAME").to_string()).chain(args),
+        ))
+    }
+
+    /// The arguments passed to ripgrep as a single line of text that can be edited and then parsed
+    /// again with `RgArgs::parse_search`.
+    pub fn search_cmdline(&self) -> String {
+        self.rg_args()
+            .iter()
+            .map(|arg| quote_arg(arg))
+            .collect::<Vec<_>>()
+            .join(" ")
     }
 
     // TODO: this implementation assumes UTF-8 (via `String`) for all arguments, but in reality it
@@ -153,6 +568,7 @@ impl RgArgs {
         let mut encoding: Option<String> = None;
         let mut fixed_strings = false;
         let mut other_args: Vec<String> = vec![];
+        let mut options = RgrOptions::default();
 
         // as per ripgrep's documentation:
         // > When -f/--file or -e/--regexp is used, then ripgrep treats all positional arguments as
@@ -193,20 +609,16 @@ impl RgArgs {
                     fixed_strings = false;
                 }
 
+                // rgr: options for rgr itself
+                Long(name) if RgrOptions::is_rgr_option(name) => {
+                    let name = name.to_string();
+                    options.parse_option(&name, &mut parser)?;
+                }
+
                 // capture help to display our help
                 // also important to capture these since they make `rg` not output JSON!
-                Short('h') | Long("help") => {
-                    print_help();
-                    process::exit(0);
-                }
-                Short('v') | Long("version") => {
-                    println!(
-                        "{crate_name} {crate_version}",
-                        crate_name = env!("CARGO_PKG_NAME"),
-                        crate_version = env!("CARGO_PKG_VERSION")
-                    );
-                    process::exit(0);
-                }
+                Short('h') | Long("help") => return Err(InfoFlag::Help.into()),
+                Short('v') | Long("version") => return Err(InfoFlag::Version.into()),
 
                 // ripgrep: all other arguments and flags
                 Short(ch) => other_args.push(format!("-{}", ch)),
@@ -246,12 +658,14 @@ impl RgArgs {
                 patterns.push(pattern);
             }
         }
+        options.validate()?;
 
         Ok(RgArgs {
             patterns,
             fixed_strings,
             encoding,
             other_args,
+            options,
             exec_style: ExecStyle::Normal,
         })
     }
@@ -296,6 +710,13 @@ mod tests {
         parse_pattern!["pattern", "--flag"];
     }
 
+    #[test]
+    fn pattern_dry_run() {
+        let args = parse_pattern!["--dry-run", "pattern"];
+        assert_eq!(args.patterns, ["pattern"]);
+        assert_eq!(args.options.dry_run, Some(DryRun::Stdout));
+    }
+
     macro_rules! parse_rg {
         [$($arg:expr$(,)?)*] => {
             RgArgs::parse_rg_args_impl(Parser::from_iter(["rgr".to_string(), $($arg.into(),)*])).unwrap()
@@ -426,6 +847,229 @@ mod tests {
         );
     }
 
+    #[test]
+    fn split_and_quote_args() {
+        assert_eq!(split_args("").unwrap(), Vec::<String>::new());
+        assert_eq!(
+            split_args("  -i  foo\tsrc/ ").unwrap(),
+            ["-i", "foo", "src/"]
+        );
+        assert_eq!(
+            split_args(r#"'foo bar' "baz \"qux\" \d" a\ b c'd'"#).unwrap(),
+            ["foo bar", r#"baz "qux" \d"#, "a b", "cd"]
+        );
+        assert_eq!(split_args("''").unwrap(), [""]);
+        assert!(split_args("'foo").is_err());
+        assert!(split_args("\"foo").is_err());
+        assert!(split_args("foo\\").is_err());
+
+        for arg in ["foo", "--regexp=(\\w+) bar", "it's", "", "$1 \"x\""] {
+            assert_eq!(split_args(&quote_arg(arg)).unwrap(), [arg]);
+        }
+    }
+
+    #[test]
+    fn rg_parse_search() {
+        let args = RgArgs::parse_search("-i 'foo bar' src").unwrap();
+        assert_eq!(args.patterns, ["foo bar"]);
+        assert_eq!(args.other_args, ["-i", "src"]);
+        assert_eq!(args.search_cmdline(), "-i src '--regexp=foo bar'");
+
+        let args = RgArgs::parse_search(&args.search_cmdline()).unwrap();
+        assert_eq!(args.patterns, ["foo bar"]);
+        assert_eq!(args.other_args, ["-i", "src"]);
+
+        assert!(RgArgs::parse_search("--help").is_err());
+        assert!(RgArgs::parse_search("foo -v").is_err());
+    }
+
+    #[test]
+    fn rg_undo() {
+        let args = parse_rg![];
+        assert!(!args.options.undo);
+
+        let args = parse_rg!["--undo"];
+        assert!(args.options.undo);
+        assert!(args.patterns.is_empty());
+        assert!(args.other_args.is_empty());
+    }
+
+    #[test]
+    fn rg_highlight() {
+        let args = parse_rg!["foo"];
+        assert!(!args.options.highlight);
+
+        let args = parse_rg!["--highlight", "foo"];
+        assert!(args.options.highlight);
+        assert_eq!(args.patterns, vec!["foo"]);
+    }
+
+    #[test]
+    fn rg_session() {
+        let args = parse_rg!["foo"];
+        assert_eq!(args.options.session, None);
+        assert!(!args.options.resume);
+
+        let args = parse_rg!["--session", "review.json", "foo"];
+        assert_eq!(args.options.session, Some(PathBuf::from("review.json")));
+        assert_eq!(args.patterns, vec!["foo"]);
+
+        let args = parse_rg!["--resume"];
+        assert!(args.options.resume);
+        assert!(args.patterns.is_empty());
+    }
+
+    #[test]
+    fn rg_replace_pool() {
+        let args = parse_rg!["foo"];
+        assert_eq!(args.options.replace_threads, None);
+        assert_eq!(args.options.replace_max_bytes, None);
+
+        let args = parse_rg!["--replace-threads", "4", "--replace-max-bytes=64M", "foo"];
+        assert_eq!(args.options.replace_threads, Some(4));
+        assert_eq!(args.options.replace_max_bytes, Some(64 * 1024 * 1024));
+
+        assert_eq!(parse_size("100").unwrap(), 100);
+        assert_eq!(parse_size("2K").unwrap(), 2048);
+        assert_eq!(parse_size("1G").unwrap(), 1 << 30);
+        assert!(parse_size("M").is_err());
+        assert!(parse_size("1T").is_err());
+        assert!(
+            RgArgs::parse_rg_args_impl(Parser::from_iter(["rgr", "--replace-threads", "0"]))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn rg_transaction() {
+        assert!(!parse_rg!["foo"].options.transaction);
+        assert!(parse_rg!["--transaction", "foo"].options.transaction);
+    }
+
+    #[test]
+    fn rg_backup() {
+        let args = parse_rg!["foo"];
+        assert_eq!(args.options.backup, None);
+        assert_eq!(args.options.backup_dir, None);
+
+        let args = parse_rg!["--backup", "foo"];
+        assert_eq!(args.options.backup.as_deref(), Some(".orig"));
+        assert_eq!(args.patterns, ["foo"]);
+
+        let args = parse_rg!["--backup=.bak", "--backup-dir", "/var/backups", "foo"];
+        assert_eq!(args.options.backup.as_deref(), Some(".bak"));
+        assert_eq!(args.options.backup_dir, Some(PathBuf::from("/var/backups")));
+        assert_eq!(args.patterns, ["foo"]);
+
+        // the backup can only have the same name as the file in another directory
+        let parse = |args: &[&str]| {
+            RgArgs::parse_rg_args_impl(Parser::from_iter(
+                ["rgr"].iter().chain(args).map(|s| s.to_string()),
+            ))
+        };
+        assert!(parse(&["--backup=", "foo"]).is_err());
+        assert!(parse(&["--backup=", "--backup-dir", "/var/backups", "foo"]).is_ok());
+        assert!(parse(&["--backup-dir", "/var/backups", "foo"]).is_ok());
+    }
+
+    #[test]
+    fn rg_report() {
+        let args = parse_rg!["foo"];
+        assert_eq!(args.options.report, None);
+
+        let args = parse_rg!["--report", "foo"];
+        assert_eq!(args.options.report, Some(ReportOutput::Stdout));
+        assert_eq!(args.patterns, ["foo"]);
+
+        let args = parse_rg!["--report=report.json", "foo"];
+        assert_eq!(
+            args.options.report,
+            Some(ReportOutput::File(PathBuf::from("report.json")))
+        );
+    }
+
+    #[test]
+    fn rg_on_stale() {
+        let args = parse_rg!["foo"];
+        assert_eq!(args.options.on_stale, None);
+
+        let args = parse_rg!["--on-stale", "rerun", "foo"];
+        assert_eq!(args.options.on_stale, Some(OnStale::Rerun));
+        assert!(RgArgs::parse_rg_args_impl(Parser::from_iter(["rgr", "--on-stale", "x"])).is_err());
+    }
+
+    #[test]
+    fn rg_rerun_args() {
+        let args = parse_rg![
+            "-i",
+            "--after-context",
+            "2",
+            "--engine",
+            "auto",
+            "-F",
+            "foo",
+            "src",
+            "-E",
+            "utf-8"
+        ];
+        assert_eq!(
+            args.rerun_args().unwrap(),
+            [
+                "-i",
+                "--engine=auto",
+                "--fixed-strings",
+                "--encoding=utf-8",
+                "--regexp=foo"
+            ]
+        );
+
+        assert_eq!(parse_pattern![].rerun_args(), None);
+    }
+
+    #[test]
+    fn rg_replace() {
+        let args = parse_rg!["foo"];
+        assert_eq!(args.options.replace, None);
+        assert!(!args.options.yes);
+
+        let args = parse_rg!["--replace", "bar", "--yes", "foo", "src"];
+        assert_eq!(args.options.replace.as_deref(), Some("bar"));
+        assert!(args.options.yes);
+        assert_eq!(args.rg_args(), ["src", "--regexp=foo"]);
+
+        let args = parse_rg!["-i", "--replace=$1", "(foo)"];
+        assert_eq!(args.options.replace.as_deref(), Some("$1"));
+        assert_eq!(args.rg_args(), ["-i", "--regexp=(foo)"]);
+    }
+
+    #[test]
+    fn rg_capture_pattern() {
+        assert!(parse_rg!["foo"].capture_pattern().unwrap().is_none());
+        assert!(parse_rg!["(foo)"].capture_pattern().unwrap().is_some());
+        assert!(parse_rg!["-F", "(foo)"]
+            .capture_pattern()
+            .unwrap()
+            .is_none());
+        assert!(parse_rg!["(foo"].capture_pattern().is_err());
+    }
+
+    #[test]
+    fn rg_dry_run() {
+        let args = parse_rg!["foo"];
+        assert_eq!(args.options.dry_run, None);
+
+        let args = parse_rg!["--dry-run", "foo"];
+        assert_eq!(args.options.dry_run, Some(DryRun::Stdout));
+        assert_eq!(args.rg_args(), ["--regexp=foo"]);
+
+        let args = parse_rg!["foo", "--dry-run=changes.patch"];
+        assert_eq!(
+            args.options.dry_run,
+            Some(DryRun::File(PathBuf::from("changes.patch")))
+        );
+        assert_eq!(args.rg_args(), ["--regexp=foo"]);
+    }
+
     #[test]
     fn rg_case1() {
         let args = parse_rg!["--sort", "path", "--sort=modified", "foo"];
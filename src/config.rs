@@ -0,0 +1,208 @@
+//! The user's config file, which is read from `$XDG_CONFIG_HOME/repgrep/config.toml` (or the
+//! platform's equivalent) if it exists.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::ui::keymap::Keymap;
+use crate::ui::theme::{StyleConfig, Theme};
+
+/// Returns the default path of the config file.
+pub fn default_config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join(env!("CARGO_PKG_NAME")).join("config.toml"))
+}
+
+/// Keys can be given as a single key or as a list of keys.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Keys {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// The config file as it's written, e.g.:
+///
+/// ```toml
+/// theme = "light"
+///
+/// [styles]
+/// match_selected = { fg = "white", bg = "blue", modifiers = ["bold"] }
+///
+/// [keys.select]
+/// next_line = ["n", "down"]
+/// prev_line = "p"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    theme: Option<String>,
+    styles: HashMap<String, StyleConfig>,
+    keys: HashMap<String, HashMap<String, Keys>>,
+}
+
+#[derive(Debug)]
+pub struct Config {
+    pub keymap: Keymap,
+    pub theme: Theme,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config::parse("").expect("the default config is valid")
+    }
+}
+
+/// Returns true if colours shouldn't be used, see https://no-color.org.
+fn no_color() -> bool {
+    std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty())
+}
+
+impl Config {
+    /// Reads the config from the file at `path`. The default config is returned if there's no file.
+    pub fn load(path: &Path) -> Result<Config> {
+        match fs::read_to_string(path) {
+            Ok(contents) => Config::parse(&contents)
+                .with_context(|| format!("Invalid config file {}", path.display())),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        }
+    }
+
+    pub fn parse(contents: &str) -> Result<Config> {
+        Config::parse_with_env(contents, no_color())
+    }
+
+    /// Parses the config. If `no_color` is set and the config doesn't name a theme, then the
+    /// `no-color` theme is used.
+    fn parse_with_env(contents: &str, no_color: bool) -> Result<Config> {
+        let file: ConfigFile = toml::from_str(contents)?;
+        let preset = file
+            .theme
+            .as_deref()
+            .unwrap_or_else(|| Theme::default_preset_name(no_color));
+        let theme = Theme::preset(preset)?.with_styles(&file.styles)?;
+
+        let keys = file
+            .keys
+            .into_iter()
+            .map(|(mode, actions)| {
+                let actions = actions
+                    .into_iter()
+                    .map(|(action, keys)| match keys {
+                        Keys::One(key) => (action, vec![key]),
+                        Keys::Many(keys) => (action, keys),
+                    })
+                    .collect();
+                (mode, actions)
+            })
+            .collect();
+
+        Ok(Config {
+            keymap: Keymap::with_overrides(&keys)?,
+            theme,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+    use pretty_assertions::assert_eq;
+    use ratatui::style::{Color, Modifier, Style};
+
+    use super::*;
+    use crate::ui::keymap::{Action, KeymapMode};
+
+    #[test]
+    fn it_parses_keys() {
+        let config = Config::parse(
+            r#"
+            [keys.select]
+            next_line = ["n", "down"]
+            prev_line = "p"
+
+            [keys.all]
+            page_down = "ctrl+d"
+            "#,
+        )
+        .unwrap();
+
+        let action =
+            |mode, code, modifiers| config.keymap.action(mode, &KeyEvent::new(code, modifiers));
+        assert_eq!(
+            action(
+                KeymapMode::Select,
+                KeyCode::Char('n'),
+                KeyModifiers::empty()
+            ),
+            Some(Action::NextLine)
+        );
+        assert_eq!(
+            action(
+                KeymapMode::Select,
+                KeyCode::Char('p'),
+                KeyModifiers::empty()
+            ),
+            Some(Action::PrevLine)
+        );
+        assert_eq!(
+            action(KeymapMode::All, KeyCode::Char('d'), KeyModifiers::CONTROL),
+            Some(Action::PageDown)
+        );
+
+        assert!(Config::parse("").is_ok());
+        assert!(Config::parse("[keys.select]\nnext_line = 1").is_err());
+        assert!(Config::parse("[unknown]").is_err());
+        assert!(Config::parse("[keys.select]\nnext = \"n\"").is_err());
+    }
+
+    #[test]
+    fn it_parses_the_theme() {
+        let config = Config::parse_with_env("", false).unwrap();
+        assert_eq!(config.theme, Theme::dark());
+        let config = Config::parse_with_env("", true).unwrap();
+        assert_eq!(config.theme, Theme::no_color());
+
+        // a theme in the config is used even if NO_COLOR is set
+        let config = Config::parse_with_env("theme = \"light\"", true).unwrap();
+        assert_eq!(config.theme, Theme::light());
+
+        let config = Config::parse_with_env(
+            "theme = \"high-contrast\"\n[styles]\nhint = { fg = \"blue\", modifiers = [\"italic\"] }",
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            config.theme.hint,
+            Style::default()
+                .fg(Color::Blue)
+                .add_modifier(Modifier::ITALIC)
+        );
+        assert_eq!(config.theme.error, Theme::high_contrast().error);
+
+        assert!(Config::parse("theme = \"solarized\"").is_err());
+        assert!(Config::parse("[styles]\nhint = { colour = \"blue\" }").is_err());
+    }
+
+    #[test]
+    fn it_uses_the_default_config_without_a_file() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(Config::load(&dir.path().join("config.toml")).is_ok());
+
+        let path = dir.path().join("config.toml");
+        fs::write(&path, "[keys.select]\nquit = \"x\"\n").unwrap();
+        let config = Config::load(&path).unwrap();
+        assert_eq!(
+            config.keymap.action(
+                KeymapMode::Select,
+                &KeyEvent::new(KeyCode::Char('x'), KeyModifiers::empty())
+            ),
+            Some(Action::Quit)
+        );
+    }
+}
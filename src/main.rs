@@ -93,22 +93,32 @@
 //! [`ripgrep` installation instructions]: https://github.com/BurntSushi/ripgrep/#installation
 
 mod cli;
+mod config;
 mod encoding;
 mod model;
 mod replace;
 mod rg;
+mod session;
 mod ui;
 mod util;
 
-use std::fs::File;
-use std::{env, process};
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::{env, mem, process};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use cli::DryRun;
 use flexi_logger::{opt_format, FileSpec, Logger};
+use model::ReplacementCriteria;
+use replace::backup::Backups;
+use replace::report::ReportOutput;
+use replace::stale::OnStale;
+use replace::ReplacementSummary;
 use rg::exec::run_ripgrep;
+use session::SessionSaver;
 use ui::tui::Tui;
 
-use crate::rg::read::read_messages;
+use crate::rg::read::{read_messages, replay_messages};
 
 fn init_logging() -> Result<::std::path::PathBuf> {
     let log_dir = env::temp_dir().join(format!(".{}", env!("CARGO_PKG_NAME")));
@@ -151,50 +161,153 @@ fn main() {
         };
     }
 
-    let (args, rg_json) = {
-        match env::var_os(cli::ENV_JSON_FILE) {
-            // check if JSON is being passed as an environment file
-            Some(path) => {
-                log::debug!(
-                    "{} set to {}; Reading messages from file",
-                    cli::ENV_JSON_FILE,
-                    path.to_string_lossy()
-                );
-                match File::open(&path) {
-                    Ok(json_file) => {
-                        let args = match cli::RgArgs::parse_pattern() {
-                            Ok(args) => args,
-                            Err(e) => {
-                                exit_with_error!("Failed to parse arguments: {}", e);
-                            }
-                        };
-
-                        (args, read_messages(json_file))
-                    }
-                    Err(e) => {
-                        exit_with_error!("Failed to open {}: {}", path.to_string_lossy(), e);
-                    }
-                }
+    // check if JSON is being passed as an environment file
+    let json_file_path = env::var_os(cli::ENV_JSON_FILE);
+    let args = match &json_file_path {
+        Some(_) => cli::RgArgs::parse_pattern(),
+        None => cli::RgArgs::parse_rg_args(),
+    };
+    let mut args = match args {
+        Ok(args) => args,
+        Err(e) => {
+            exit_with_error!("Failed to parse arguments: {}", e);
+        }
+    };
+
+    if args.options.replace.is_some() && !args.options.yes && args.options.dry_run.is_none() {
+        exit_with_error!("--replace must be used with either --yes or --dry-run");
+    }
+
+    if args.options.report == Some(ReportOutput::Stdout)
+        && args.options.dry_run == Some(DryRun::Stdout)
+    {
+        exit_with_error!(
+            "--report and --dry-run can't both write to stdout, pass a file to one of them"
+        );
+    }
+
+    if args.options.undo {
+        let result = replace::journal::default_journal_dir()
+            .context("Failed to find a directory for the journal")
+            .and_then(replace::journal::undo_last_session);
+
+        match result {
+            Ok(count) => {
+                eprintln!("Restored {} file(s)", count);
+                process::exit(0);
             }
-            // normal execution, parse rg arguments and call it ourselves
-            None => {
-                let args = match cli::RgArgs::parse_rg_args() {
-                    Ok(args) => args,
-                    Err(e) => {
-                        exit_with_error!("Failed to parse arguments: {}", e);
-                    }
-                };
+            Err(err) => {
+                exit_with_error!("Failed to undo: {}", err);
+            }
+        }
+    }
 
-                let rg_args = args.rg_args();
-                (args, run_ripgrep(rg_args))
+    if args.options.resume && args.options.replace.is_some() {
+        exit_with_error!("--resume can't be used with --replace");
+    }
+
+    let session_path = args
+        .options
+        .session
+        .clone()
+        .or_else(session::default_session_path);
+    let mut resumed_session = if args.options.resume {
+        let result = session_path
+            .as_deref()
+            .context("Failed to find a directory for the session")
+            .and_then(session::Session::load)
+            .and_then(|session| session.check_unchanged().map(|_| session));
+        match result {
+            Ok(session) => Some(session),
+            Err(err) => {
+                exit_with_error!("Failed to resume: {:#}", err);
+            }
+        }
+    } else {
+        None
+    };
+
+    // the search is replaced by the one the session was saved with
+    if let Some(session) = &resumed_session {
+        let options = mem::take(&mut args.options);
+        args = match cli::RgArgs::parse_search(&session.search_cmdline) {
+            Ok(args) => args,
+            Err(err) => {
+                exit_with_error!("Failed to resume: {}", err);
+            }
+        };
+        args.options = options;
+    }
+
+    let rg_json = match (&mut resumed_session, json_file_path) {
+        (Some(session), _) => Ok(replay_messages(mem::take(&mut session.rg_messages))),
+        (None, Some(path)) => {
+            log::debug!(
+                "{} set to {}; Reading messages from file",
+                cli::ENV_JSON_FILE,
+                path.to_string_lossy()
+            );
+            match File::open(&path) {
+                Ok(json_file) => Ok(read_messages(json_file)),
+                Err(e) => {
+                    exit_with_error!("Failed to open {}: {}", path.to_string_lossy(), e);
+                }
             }
         }
+        // normal execution, call ripgrep ourselves
+        (None, None) => run_ripgrep(args.rg_args()),
     };
 
-    match rg_json {
-        Ok(rg_messages) => {
-            let result = Tui::new()
-                .and_then(|tui| tui.start(args.rg_cmdline(), rg_messages, &args.patterns));
+    let rg_messages = match rg_json {
+        Ok(rg_messages) => rg_messages,
+        Err(e) => {
+            exit_with_error!("{}", e);
+        }
+    };
+
+    let headless = args.options.replace.is_some();
+    let replacement_criteria = match &args.options.replace {
+        // run without the interface, replacing every match
+        Some(replacement) => {
+            let rg_messages = match rg_messages.iter().collect::<Result<Vec<_>>>() {
+                Ok(rg_messages) => rg_messages,
+                Err(err) => {
+                    exit_with_error!("{}", err);
+                }
+            };
+            let capture_pattern = match args.capture_pattern() {
+                Ok(capture_pattern) => capture_pattern,
+                Err(err) => {
+                    exit_with_error!("Failed to parse patterns: {}", err);
+                }
+            };
+
+            let mut replacement_criteria =
+                ReplacementCriteria::from_rg_messages(capture_pattern, replacement, rg_messages);
+            replacement_criteria.literal = args.fixed_strings;
+            replacement_criteria.dry_run = args.options.dry_run.is_some();
+            replacement_criteria
+        }
+        None => {
+            let config = match config::default_config_path() {
+                Some(path) => config::Config::load(&path),
+                None => Ok(config::Config::default()),
+            };
+            let config = match config {
+                Ok(config) => config,
+                Err(err) => {
+                    exit_with_error!("Failed to load config: {:#}", err);
+                }
+            };
+
+            let session = match (resumed_session, session_path.clone()) {
+                (Some(session), Some(path)) => SessionSaver::resume(path, session),
+                (None, Some(path)) => SessionSaver::new(path),
+                // there's nowhere to save the session
+                (_, None) => SessionSaver::disabled(),
+            };
+            let result =
+                Tui::new().and_then(|tui| tui.start(&mut args, config, rg_messages, session));
 
             // Restore terminal.
             if let Err(err) = Tui::restore_terminal() {
@@ -207,32 +320,89 @@ fn main() {
 
             // Handle application result.
             match result {
-                Ok(Some(mut replacement_criteria)) => {
-                    // use an encoding if one was passed to `rg`
-                    if let Some(encoding) = args.encoding {
-                        replacement_criteria.set_encoding(encoding);
-                    }
-
-                    // if we're running in fixed strings mode, then we shouldn't treat the patterns as regexes
-                    if args.fixed_strings {
-                        replacement_criteria.capture_pattern = None;
-                    }
-
-                    match replace::perform_replacements(replacement_criteria) {
-                        Ok(_) => {}
-                        Err(err) => {
-                            exit_with_error!("An error occurred during replacement: {}", err);
-                        }
-                    }
+                Ok(Some(replacement_criteria)) => replacement_criteria,
+                Ok(None) => {
+                    eprintln!("Cancelled");
+                    return;
                 }
-                Ok(None) => eprintln!("Cancelled"),
                 Err(err) => {
                     exit_with_error!("An app error occurred: {}", err);
                 }
             }
         }
-        Err(e) => {
-            exit_with_error!("{}", e);
+    };
+
+    let dry_run = replacement_criteria.dry_run;
+    match perform_replacements(&args, replacement_criteria) {
+        Ok(summary) if headless => eprintln!(
+            "{} {} match(es) in {} file(s)",
+            if dry_run { "Would replace" } else { "Replaced" },
+            summary.matches,
+            summary.files
+        ),
+        Ok(_) => {
+            // the session can't be resumed once its files have been changed
+            if let (false, Some(path)) = (dry_run, &session_path) {
+                match fs::remove_file(path) {
+                    Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                        log::warn!("Failed to remove session {}: {}", path.display(), err)
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Err(err) => {
+            exit_with_error!("An error occurred during replacement: {}", err);
         }
     }
 }
+
+/// Writes the replacements to disk, or prints a diff of them if running in dry-run mode.
+fn perform_replacements(
+    args: &cli::RgArgs,
+    mut replacement_criteria: ReplacementCriteria,
+) -> Result<ReplacementSummary> {
+    // use an encoding if one was passed to `rg`
+    if let Some(encoding) = &args.encoding {
+        replacement_criteria.set_encoding(encoding);
+    }
+
+    // journal the replacements so they can be undone with `--undo`
+    replacement_criteria.journal_dir = replace::journal::default_journal_dir();
+
+    // there's no one to ask about stale files without the interface
+    replacement_criteria.on_stale = match args.options.on_stale {
+        Some(on_stale) => on_stale,
+        None if args.options.replace.is_some() => OnStale::Skip,
+        None => OnStale::Ask,
+    };
+    replacement_criteria.rerun_args = args.rerun_args();
+
+    if let Some(threads) = args.options.replace_threads {
+        replacement_criteria.threads = threads;
+    }
+    if let Some(max_bytes) = args.options.replace_max_bytes {
+        replacement_criteria.max_bytes_in_flight = max_bytes;
+    }
+    replacement_criteria.report = args.options.report.clone();
+    replacement_criteria.transaction = args.options.transaction;
+    if args.options.backup.is_some() || args.options.backup_dir.is_some() {
+        replacement_criteria.backups = Some(Backups {
+            suffix: args.options.backup.clone().unwrap_or_default(),
+            dir: args.options.backup_dir.clone(),
+        });
+    }
+
+    if replacement_criteria.dry_run {
+        match &args.options.dry_run {
+            Some(DryRun::File(path)) => File::create(path)
+                .with_context(|| format!("Failed to create {}", path.display()))
+                .and_then(|file| {
+                    replace::perform_dry_run(replacement_criteria, BufWriter::new(file))
+                }),
+            _ => replace::perform_dry_run(replacement_criteria, io::stdout().lock()),
+        }
+    } else {
+        replace::perform_replacements(replacement_criteria)
+    }
+}
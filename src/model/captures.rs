@@ -0,0 +1,109 @@
+use regex::bytes::{Captures, Regex, RegexSet};
+
+/// Resolves the capturing groups for a match when one or more patterns were passed to ripgrep.
+///
+/// Since ripgrep searches for all the patterns at once, we don't know which one produced each
+/// match. This works out which pattern it was (with a `RegexSet`), so that capturing groups in the
+/// replacement are expanded against the right pattern.
+#[derive(Debug, Clone)]
+pub struct CaptureResolver {
+    set: RegexSet,
+    patterns: Vec<Regex>,
+}
+
+impl CaptureResolver {
+    pub fn new<S: AsRef<str>>(patterns: &[S]) -> Result<CaptureResolver, regex::Error> {
+        Ok(CaptureResolver {
+            set: RegexSet::new(patterns)?,
+            patterns: patterns
+                .iter()
+                .map(|p| Regex::new(p.as_ref()))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    /// Returns true if any of the patterns have capturing groups.
+    pub fn has_capturing_groups(&self) -> bool {
+        // all regex's have at least one capturing group, see: https://docs.rs/regex/1.8.4/regex/struct.Captures.html#method.len
+        self.patterns.iter().any(|re| re.captures_len() > 1)
+    }
+
+    /// Returns the index of the pattern which produced `matched`.
+    ///
+    /// If more than one pattern matches, then the first one which matches all of `matched` is
+    /// preferred, since that's the one ripgrep would have reported.
+    pub fn pattern_index(&self, matched: &[u8]) -> Option<usize> {
+        let candidates = self.set.matches(matched);
+        candidates
+            .iter()
+            .find(|&i| {
+                self.patterns[i]
+                    .find(matched)
+                    .map(|m| m.start() == 0 && m.end() == matched.len())
+                    .unwrap_or(false)
+            })
+            .or_else(|| candidates.iter().next())
+    }
+
+    /// Returns the captures of the pattern which produced `matched`, or `None` if none of the
+    /// patterns match it.
+    pub fn captures<'t>(&self, matched: &'t [u8]) -> Option<Captures<'t>> {
+        self.patterns[self.pattern_index(matched)?].captures(matched)
+    }
+}
+
+impl From<Regex> for CaptureResolver {
+    fn from(re: Regex) -> Self {
+        CaptureResolver {
+            set: RegexSet::new([re.as_str()]).expect("a valid Regex is a valid RegexSet"),
+            patterns: vec![re],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn expand(resolver: &CaptureResolver, matched: &str, replacement: &str) -> Option<String> {
+        resolver.captures(matched.as_bytes()).map(|captures| {
+            let mut buf = vec![];
+            captures.expand(replacement.as_bytes(), &mut buf);
+            String::from_utf8(buf).unwrap()
+        })
+    }
+
+    #[test]
+    fn it_resolves_the_pattern_for_each_match() {
+        let resolver = CaptureResolver::new(&[r"foo(\d+)", r"bar_(\w+)", r"baz"]).unwrap();
+        assert!(resolver.has_capturing_groups());
+
+        assert_eq!(resolver.pattern_index(b"foo123"), Some(0));
+        assert_eq!(resolver.pattern_index(b"bar_qux"), Some(1));
+        assert_eq!(resolver.pattern_index(b"baz"), Some(2));
+        assert_eq!(resolver.pattern_index(b"nothing"), None);
+
+        assert_eq!(expand(&resolver, "foo123", "<$1>"), Some("<123>".into()));
+        assert_eq!(expand(&resolver, "bar_qux", "<$1>"), Some("<qux>".into()));
+        // groups that the pattern doesn't have are expanded to nothing
+        assert_eq!(expand(&resolver, "baz", "<$1>"), Some("<>".into()));
+        assert_eq!(expand(&resolver, "nothing", "<$1>"), None);
+    }
+
+    #[test]
+    fn it_prefers_patterns_that_match_all_of_the_text() {
+        let resolver = CaptureResolver::new(&[r"(o)", r"f(o+)"]).unwrap();
+        assert_eq!(resolver.pattern_index(b"o"), Some(0));
+        assert_eq!(resolver.pattern_index(b"foo"), Some(1));
+        assert_eq!(expand(&resolver, "foo", "$1"), Some("oo".into()));
+    }
+
+    #[test]
+    fn it_has_no_capturing_groups() {
+        let resolver = CaptureResolver::new(&["foo", "bar"]).unwrap();
+        assert!(!resolver.has_capturing_groups());
+        assert_eq!(expand(&resolver, "foo", "<$0>"), Some("<foo>".into()));
+    }
+}
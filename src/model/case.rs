@@ -0,0 +1,192 @@
+/// The casing style of a piece of text, used when preserving case during replacement.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum CaseStyle {
+    /// `foobar`
+    Lower,
+    /// `FOOBAR`
+    Upper,
+    /// `Foobar`
+    Title,
+    /// `fooBar`
+    Camel,
+    /// `FooBar`
+    Pascal,
+    /// `foo_bar`
+    Snake,
+    /// `FOO_BAR`
+    ScreamingSnake,
+    /// `foo-bar`
+    Kebab,
+}
+
+impl CaseStyle {
+    /// Detects the casing style of `text`. Returns `None` if `text` doesn't have a (single)
+    /// recognisable style, e.g., if it contains characters other than letters, digits, `_` or `-`.
+    pub fn detect(text: &str) -> Option<CaseStyle> {
+        if !text.chars().any(char::is_alphabetic)
+            || !text
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+        {
+            return None;
+        }
+
+        let has_lower = text.chars().any(char::is_lowercase);
+        let has_upper = text.chars().any(char::is_uppercase);
+        match (text.contains('_'), text.contains('-')) {
+            (true, true) => None,
+            (true, false) if !has_upper => Some(CaseStyle::Snake),
+            (true, false) if !has_lower => Some(CaseStyle::ScreamingSnake),
+            (false, true) if !has_upper => Some(CaseStyle::Kebab),
+            (true, false) | (false, true) => None,
+            (false, false) if !has_upper => Some(CaseStyle::Lower),
+            (false, false) if !has_lower => Some(CaseStyle::Upper),
+            (false, false) => {
+                let mut chars = text.chars();
+                if chars.next().is_some_and(char::is_uppercase) {
+                    if chars.any(char::is_uppercase) {
+                        Some(CaseStyle::Pascal)
+                    } else {
+                        Some(CaseStyle::Title)
+                    }
+                } else {
+                    Some(CaseStyle::Camel)
+                }
+            }
+        }
+    }
+
+    /// Converts `text` into this casing style.
+    ///
+    /// The single word styles (lower, UPPER and Title) keep any separators in `text`, and the
+    /// others rebuild `text` from its words.
+    pub fn apply(self, text: &str) -> String {
+        match self {
+            CaseStyle::Lower => text.to_lowercase(),
+            CaseStyle::Upper => text.to_uppercase(),
+            CaseStyle::Title => capitalize(text),
+            CaseStyle::Camel => words(text)
+                .iter()
+                .enumerate()
+                .map(|(i, w)| {
+                    if i == 0 {
+                        w.to_lowercase()
+                    } else {
+                        capitalize(&w.to_lowercase())
+                    }
+                })
+                .collect(),
+            CaseStyle::Pascal => words(text)
+                .iter()
+                .map(|w| capitalize(&w.to_lowercase()))
+                .collect(),
+            CaseStyle::Snake => join(text, "_", str::to_lowercase),
+            CaseStyle::ScreamingSnake => join(text, "_", str::to_uppercase),
+            CaseStyle::Kebab => join(text, "-", str::to_lowercase),
+        }
+    }
+}
+
+/// Converts `replacement` into the casing style of `matched`. If the style of `matched` can't be
+/// detected then `replacement` is returned unchanged.
+pub fn preserve_case(matched: &str, replacement: &str) -> String {
+    match CaseStyle::detect(matched) {
+        Some(style) => style.apply(replacement),
+        None => replacement.to_owned(),
+    }
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+fn join(text: &str, separator: &str, f: fn(&str) -> String) -> String {
+    words(text)
+        .iter()
+        .map(|w| f(w))
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+/// Splits `text` into words, at any non-alphanumeric characters and at changes in case, e.g.,
+/// `fooBar`, `foo_bar` and `HTTPServer` become `[foo, Bar]`, `[foo, bar]` and `[HTTP, Server]`.
+fn words(text: &str) -> Vec<&str> {
+    let mut words = vec![];
+    for part in text.split(|c: char| !c.is_alphanumeric()) {
+        let chars = part.char_indices().collect::<Vec<_>>();
+        let mut start = 0;
+        for i in 1..chars.len() {
+            let (pos, c) = chars[i];
+            let prev = chars[i - 1].1;
+            let next_is_lower = chars.get(i + 1).is_some_and(|(_, c)| c.is_lowercase());
+            if c.is_uppercase() && (!prev.is_uppercase() || next_is_lower) {
+                words.push(&part[start..pos]);
+                start = pos;
+            }
+        }
+
+        if start < part.len() {
+            words.push(&part[start..]);
+        }
+    }
+
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn it_detects_case_styles() {
+        assert_eq!(CaseStyle::detect("foobar"), Some(CaseStyle::Lower));
+        assert_eq!(CaseStyle::detect("FOOBAR"), Some(CaseStyle::Upper));
+        assert_eq!(CaseStyle::detect("Foobar"), Some(CaseStyle::Title));
+        assert_eq!(CaseStyle::detect("fooBar"), Some(CaseStyle::Camel));
+        assert_eq!(CaseStyle::detect("FooBar"), Some(CaseStyle::Pascal));
+        assert_eq!(CaseStyle::detect("foo_bar"), Some(CaseStyle::Snake));
+        assert_eq!(
+            CaseStyle::detect("FOO_BAR"),
+            Some(CaseStyle::ScreamingSnake)
+        );
+        assert_eq!(CaseStyle::detect("foo-bar"), Some(CaseStyle::Kebab));
+        assert_eq!(CaseStyle::detect("foo2"), Some(CaseStyle::Lower));
+
+        assert_eq!(CaseStyle::detect("Foo_bar"), None);
+        assert_eq!(CaseStyle::detect("foo_bar-baz"), None);
+        assert_eq!(CaseStyle::detect("foo bar"), None);
+        assert_eq!(CaseStyle::detect("123"), None);
+        assert_eq!(CaseStyle::detect(""), None);
+    }
+
+    #[test]
+    fn it_splits_words() {
+        assert_eq!(words("fooBar"), ["foo", "Bar"]);
+        assert_eq!(words("FooBar"), ["Foo", "Bar"]);
+        assert_eq!(words("foo_bar-baz qux"), ["foo", "bar", "baz", "qux"]);
+        assert_eq!(words("HTTPServer"), ["HTTP", "Server"]);
+        assert_eq!(words("FOO_BAR"), ["FOO", "BAR"]);
+        assert_eq!(words("foo2Bar"), ["foo2", "Bar"]);
+    }
+
+    #[test]
+    fn it_preserves_case() {
+        let replace = |matched| preserve_case(matched, "bazQux");
+        assert_eq!(replace("foobar"), "bazqux");
+        assert_eq!(replace("FOOBAR"), "BAZQUX");
+        assert_eq!(replace("Foobar"), "BazQux");
+        assert_eq!(replace("fooBar"), "bazQux");
+        assert_eq!(replace("FooBar"), "BazQux");
+        assert_eq!(replace("foo_bar"), "baz_qux");
+        assert_eq!(replace("FOO_BAR"), "BAZ_QUX");
+        assert_eq!(replace("foo-bar"), "baz-qux");
+        // unknown styles are left alone
+        assert_eq!(replace("Foo_bar"), "bazQux");
+    }
+}
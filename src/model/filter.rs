@@ -0,0 +1,68 @@
+use regex::bytes::{Regex, RegexBuilder};
+
+use crate::ui::line::Item;
+
+/// Narrows down the matches list to the items whose path or line text match a pattern.
+#[derive(Debug, Clone)]
+pub struct ListFilter {
+    re: Regex,
+}
+
+impl ListFilter {
+    /// Creates a filter from what the user entered. Returns `None` if `input` is empty.
+    ///
+    /// The input is used as a regular expression, or as a plain substring if it isn't a valid one.
+    /// In the same way as ripgrep's `--smart-case`, it only matches case-insensitively if it's all
+    /// lowercase.
+    pub fn new(input: &str) -> Option<ListFilter> {
+        if input.is_empty() {
+            return None;
+        }
+
+        let case_insensitive = !input.chars().any(char::is_uppercase);
+        let re = RegexBuilder::new(input)
+            .case_insensitive(case_insensitive)
+            .build()
+            .or_else(|_| {
+                RegexBuilder::new(&regex::escape(input))
+                    .case_insensitive(case_insensitive)
+                    .build()
+            })
+            .ok()?;
+
+        Some(ListFilter { re })
+    }
+
+    /// Returns true if the path or the line text of `item` matches the filter.
+    pub fn is_match(&self, item: &Item) -> bool {
+        item.path().is_some_and(|p| self.re.is_match(&p.to_vec()))
+            || item.lines().is_some_and(|l| self.re.is_match(&l.to_vec()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rg::de::test_utilities::*;
+    use crate::rg::de::*;
+    use crate::ui::line::Item;
+
+    use super::*;
+
+    #[test]
+    fn it_matches_the_path_or_the_line_text() {
+        // "    Item::new(rg_msg)" in "src/model/item.rs"
+        let item = Item::new(0, RgMessage::from_str(RG_JSON_MATCH));
+        let is_match = |input| ListFilter::new(input).unwrap().is_match(&item);
+        assert!(is_match("model/item"));
+        assert!(is_match("rg_msg"));
+        assert!(is_match(r"new\(\w+\)"));
+        assert!(!is_match("foo"));
+        // smart case
+        assert!(is_match("item::new"));
+        assert!(!is_match("Item::New"));
+        // invalid regular expressions are used as substrings
+        assert!(is_match("new("));
+
+        assert!(ListFilter::new("").is_none());
+    }
+}
@@ -0,0 +1,246 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs::{self, File, Metadata};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::rg::de::ArbitraryData;
+use crate::util::hash_reader;
+
+/// Files modified this recently when they're fingerprinted may be written again without their
+/// modification time changing (since some filesystems only store it to the second), so they're
+/// hashed as well.
+const AMBIGUOUS_MTIME: Duration = Duration::from_secs(2);
+
+/// The hash of a file which is computed on a background thread, so that files aren't read on the
+/// thread that's receiving ripgrep's results.
+#[derive(Clone)]
+pub struct PendingHash {
+    slot: Arc<(Mutex<Option<Option<String>>>, Condvar)>,
+}
+
+impl PendingHash {
+    fn done(hash: Option<String>) -> PendingHash {
+        PendingHash {
+            slot: Arc::new((Mutex::new(Some(hash)), Condvar::new())),
+        }
+    }
+
+    /// Queues the file at `path` to be hashed. Files are hashed one at a time, in order. If the
+    /// file's size or modification time no longer match `metadata` when it's hashed, then it's
+    /// already changed and there's no hash.
+    fn spawn(path: PathBuf, metadata: &Metadata) -> PendingHash {
+        type Job = (PathBuf, (Option<u64>, u64), PendingHash);
+        static HASHER: OnceLock<Sender<Job>> = OnceLock::new();
+
+        let pending = PendingHash {
+            slot: Arc::new((Mutex::new(None), Condvar::new())),
+        };
+        let hasher = HASHER.get_or_init(|| {
+            let (tx, rx) = mpsc::channel::<Job>();
+            thread::spawn(move || {
+                for (path, expected, pending) in rx {
+                    let unchanged = fs::metadata(&path)
+                        .is_ok_and(|metadata| (mtime(&metadata), metadata.len()) == expected);
+                    let hash = if unchanged {
+                        hash_file(&path)
+                            .map_err(|e| log::warn!("Failed to hash {}: {}", path.display(), e))
+                            .ok()
+                    } else {
+                        None
+                    };
+                    let (lock, cvar) = &*pending.slot;
+                    *lock.lock().unwrap() = Some(hash);
+                    cvar.notify_all();
+                }
+            });
+            tx
+        });
+        let expected = (mtime(metadata), metadata.len());
+        if hasher.send((path, expected, pending.clone())).is_err() {
+            return PendingHash::done(None);
+        }
+
+        pending
+    }
+
+    /// Waits for the hash, which is `None` if the file couldn't be read or had already changed.
+    pub fn wait(&self) -> Option<String> {
+        let (lock, cvar) = &*self.slot;
+        let guard = cvar
+            .wait_while(lock.lock().unwrap(), |hash| hash.is_none())
+            .unwrap();
+        guard.clone().flatten()
+    }
+}
+
+impl fmt::Debug for PendingHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("PendingHash")
+            .field(&*self.slot.0.lock().unwrap())
+            .finish()
+    }
+}
+
+impl PartialEq for PendingHash {
+    fn eq(&self, other: &PendingHash) -> bool {
+        self.wait() == other.wait()
+    }
+}
+
+impl Eq for PendingHash {}
+
+/// NOTE: a file that couldn't be hashed is written as an empty hash, so that it's still seen as
+/// changed once it's read back.
+impl Serialize for PendingHash {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.wait().unwrap_or_default().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for PendingHash {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<PendingHash, D::Error> {
+        let hash = String::deserialize(deserializer)?;
+        Ok(PendingHash::done(Some(hash).filter(|h| !h.is_empty())))
+    }
+}
+
+/// The state of a file when ripgrep's results for it were read, so that changes made to it since
+/// then (e.g., by an editor or a formatter) are noticed before any replacements are written.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileFingerprint {
+    /// The modification time in nanoseconds since the Unix epoch, if the platform reports one.
+    pub mtime: Option<u64>,
+    pub size: u64,
+    /// The SHA-256 hash of the file's contents. This is only taken when the modification time
+    /// can't be relied on (see `AMBIGUOUS_MTIME`), and it's taken in the background so that files
+    /// aren't read while searching.
+    #[serde(default)]
+    pub hash: Option<PendingHash>,
+}
+
+fn mtime(metadata: &Metadata) -> Option<u64> {
+    let since_epoch = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    u64::try_from(since_epoch.as_nanos()).ok()
+}
+
+/// Returns true if the file's modification time may not change if it's written again.
+fn is_mtime_ambiguous(metadata: &Metadata) -> bool {
+    match metadata.modified() {
+        Ok(modified) => SystemTime::now()
+            .duration_since(modified)
+            .map_or(true, |age| age < AMBIGUOUS_MTIME),
+        Err(_) => true,
+    }
+}
+
+fn hash_file(path: &Path) -> Result<String> {
+    Ok(hash_reader(BufReader::new(File::open(path)?))?)
+}
+
+impl FileFingerprint {
+    pub fn read(path: &ArbitraryData) -> Result<FileFingerprint> {
+        let path_buf = path.to_path_buf()?;
+        let metadata = fs::metadata(&path_buf)?;
+        let hash = if is_mtime_ambiguous(&metadata) {
+            Some(PendingHash::spawn(path_buf, &metadata))
+        } else {
+            None
+        };
+
+        Ok(FileFingerprint {
+            mtime: mtime(&metadata),
+            size: metadata.len(),
+            hash,
+        })
+    }
+
+    /// Returns true if the file at `path` has changed since the fingerprint was taken (or if it
+    /// can't be read any more). The file is only hashed if the fingerprint has a hash, i.e., if
+    /// its modification time couldn't be relied on.
+    pub fn is_stale(&self, path: &Path) -> bool {
+        let metadata = match fs::metadata(path) {
+            Ok(metadata) => metadata,
+            Err(_) => return true,
+        };
+        if metadata.len() != self.size || mtime(&metadata) != self.mtime {
+            return true;
+        }
+
+        match &self.hash {
+            Some(pending) => match (pending.wait(), hash_file(path)) {
+                (Some(hash), Ok(current)) => hash != current,
+                _ => true,
+            },
+            None => self.mtime.is_none(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use filetime::FileTime;
+
+    use super::*;
+
+    fn set_modified(path: &Path, secs: i64) {
+        filetime::set_file_mtime(path, FileTime::from_unix_time(secs, 0)).unwrap();
+    }
+
+    fn fingerprint(path: &Path) -> FileFingerprint {
+        FileFingerprint::read(&ArbitraryData::new_with_text(path.display().to_string())).unwrap()
+    }
+
+    #[test]
+    fn it_detects_changed_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file.txt");
+        fs::write(&path, "foo").unwrap();
+        set_modified(&path, 1_000);
+        let fingerprint = fingerprint(&path);
+        // the file isn't read while it's fingerprinted
+        assert_eq!(fingerprint.hash, None);
+        assert!(!fingerprint.is_stale(&path));
+
+        // a change which keeps the same size is noticed by the modification time
+        fs::write(&path, "bar").unwrap();
+        set_modified(&path, 2_000);
+        assert!(fingerprint.is_stale(&path));
+
+        fs::write(&path, "foobar").unwrap();
+        set_modified(&path, 1_000);
+        assert!(fingerprint.is_stale(&path));
+
+        fs::remove_file(&path).unwrap();
+        assert!(fingerprint.is_stale(&path));
+    }
+
+    #[test]
+    fn it_hashes_files_that_were_just_modified() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file.txt");
+        fs::write(&path, "foo").unwrap();
+        let fingerprint = fingerprint(&path);
+        assert!(fingerprint.hash.is_some());
+        assert!(!fingerprint.is_stale(&path));
+
+        // the hash is kept when it's saved in a session
+        let json = serde_json::to_string(&fingerprint).unwrap();
+        let restored: FileFingerprint = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, fingerprint);
+        assert!(!restored.is_stale(&path));
+
+        // the modification time may not change, so the contents are checked
+        let mtime = FileTime::from_last_modification_time(&fs::metadata(&path).unwrap());
+        fs::write(&path, "bar").unwrap();
+        filetime::set_file_mtime(&path, mtime).unwrap();
+        assert!(fingerprint.is_stale(&path));
+    }
+}
@@ -1,7 +1,21 @@
+pub mod captures;
+pub mod case;
+pub mod filter;
+pub mod fingerprint;
 pub mod movement;
 pub mod printable;
 pub mod replacement;
+pub mod selection;
+pub mod template;
+pub mod tree;
 
+pub use captures::*;
+pub use case::*;
+pub use filter::*;
+pub use fingerprint::*;
 pub use movement::*;
 pub use printable::*;
 pub use replacement::*;
+pub use selection::*;
+pub use template::*;
+pub use tree::*;
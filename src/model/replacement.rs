@@ -1,37 +1,184 @@
+use std::borrow::Cow;
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
+use std::path::PathBuf;
 
-use regex::bytes::Regex;
+use crate::model::{
+    expand_template, preserve_case, CaptureResolver, FileFingerprint, MatchContext, MatchCounts,
+};
+use crate::replace::backup::Backups;
+use crate::replace::report::ReportOutput;
+use crate::replace::stale::OnStale;
+use crate::replace::{pool, stream};
+use crate::rg::de::{ArbitraryData, RgMessage, RgMessageKind};
+use crate::ui::line::{Item, SubItem};
 
-use crate::rg::de::{ArbitraryData, RgMessageKind};
-use crate::ui::line::Item;
+/// Returns the text which should replace `matched`.
+///
+/// If `literal` is true then the replacement is always used as-is, otherwise it's expanded as a
+/// template (see `expand_template`), where capturing groups (e.g., `$1`) are resolved using
+/// whichever pattern produced the match (or against the lines of the match, if the match alone
+/// isn't matched by any pattern). If `keep_case` is true then the result is converted into
+/// the casing style of `matched`.
+pub fn expand_replacement<'a>(
+    capture_pattern: Option<&CaptureResolver>,
+    literal: bool,
+    keep_case: bool,
+    matched: &[u8],
+    replacement: &'a [u8],
+    ctx: &MatchContext,
+) -> Cow<'a, [u8]> {
+    let expanded = if literal || !replacement.contains(&b'$') {
+        // just use raw replacement
+        Cow::Borrowed(replacement)
+    } else {
+        let captures = capture_pattern.and_then(|re| re.captures(matched));
+        let lines = match (&captures, capture_pattern, ctx.lines) {
+            (None, Some(_), Some(lines)) => lines.to_vec(),
+            _ => vec![],
+        };
+        let captures = captures.or_else(|| capture_pattern.and_then(|re| re.captures(&lines)));
+        Cow::Owned(expand_template(replacement, captures.as_ref(), ctx))
+    };
+
+    if !keep_case {
+        return expanded;
+    }
+
+    // casing can only be detected and converted in valid UTF-8
+    match (std::str::from_utf8(matched), std::str::from_utf8(&expanded)) {
+        (Ok(matched), Ok(text)) => Cow::Owned(preserve_case(matched, text).into_bytes()),
+        _ => expanded,
+    }
+}
 
 #[derive(Debug)]
 pub struct ReplacementCriteria {
-    pub capture_pattern: Option<Regex>,
+    pub capture_pattern: Option<CaptureResolver>,
+    /// If true, the replacement is always inserted literally (`$` is never expanded). This is the
+    /// case when ripgrep was run with `--fixed-strings`.
+    pub literal: bool,
+    /// If true, the replacement is converted into the casing style of each match (e.g., replacing
+    /// `fooBar` and `FOO_BAR` with `baz` gives `baz` and `BAZ`).
+    pub preserve_case: bool,
     pub items: Vec<Item>,
     pub user_replacement: Vec<u8>,
     pub encoding: Option<String>,
+    /// If true, a diff of the replacements should be produced rather than writing them to disk.
+    pub dry_run: bool,
+    /// If set, the original contents of each file are journaled here before they're replaced, so
+    /// that the replacements can be undone later.
+    pub journal_dir: Option<PathBuf>,
+    /// The state of each file when its results were read, see `FileFingerprint`.
+    pub fingerprints: HashMap<ArbitraryData, FileFingerprint>,
+    /// What to do with files that have changed since they were searched.
+    pub on_stale: OnStale,
+    /// The args used to search a file again with ripgrep, see `RgArgs::rerun_args`. If this isn't
+    /// set then files can't be searched again.
+    pub rerun_args: Option<Vec<String>>,
+    /// The number of threads used to rewrite files.
+    pub threads: usize,
+    /// The limit of the total size of the files being rewritten at once, see `pool::run_in_order`.
+    pub max_bytes_in_flight: u64,
+    /// Files at least this large are streamed rather than replaced in memory, if their encoding
+    /// allows it (see `stream::can_stream`).
+    pub streaming_threshold: u64,
+    /// If set, a report of every file's replacements is written here, see `Report`.
+    pub report: Option<ReportOutput>,
+    /// If true, files are only replaced once all of them have been prepared, and none are replaced
+    /// if any of them fail (see `transaction`).
+    pub transaction: bool,
+    /// If set, the original of each file is kept before it's replaced, see `backup`.
+    pub backups: Option<Backups>,
 }
 
 impl ReplacementCriteria {
     pub fn new<S: AsRef<str>>(
-        capture_pattern: Option<Regex>,
+        capture_pattern: Option<CaptureResolver>,
         user_replacement: S,
         items: Vec<Item>,
     ) -> ReplacementCriteria {
         ReplacementCriteria {
             capture_pattern,
+            literal: false,
+            preserve_case: false,
             user_replacement: user_replacement.as_ref().as_bytes().to_vec(),
             items,
             encoding: None,
+            dry_run: false,
+            journal_dir: None,
+            fingerprints: HashMap::new(),
+            on_stale: OnStale::Ask,
+            rerun_args: None,
+            threads: pool::default_threads(),
+            max_bytes_in_flight: pool::DEFAULT_MAX_BYTES_IN_FLIGHT,
+            streaming_threshold: stream::DEFAULT_STREAMING_THRESHOLD,
+            report: None,
+            transaction: false,
+            backups: None,
         }
     }
 
+    /// Creates the criteria to replace every match in `rg_messages`, without any of them having
+    /// been selected by the user.
+    pub fn from_rg_messages<S: AsRef<str>>(
+        capture_pattern: Option<CaptureResolver>,
+        user_replacement: S,
+        rg_messages: impl IntoIterator<Item = RgMessage>,
+    ) -> ReplacementCriteria {
+        let mut fingerprints = HashMap::new();
+        let items = rg_messages
+            .into_iter()
+            .inspect(|rg_message| {
+                if let RgMessage::Begin { path } = rg_message {
+                    if let Ok(fingerprint) = FileFingerprint::read(path) {
+                        fingerprints.insert(path.clone(), fingerprint);
+                    }
+                }
+            })
+            // NOTE: the summary isn't an item, in the same way as it's not added to the app's list
+            .filter(|rg_message| !matches!(rg_message, RgMessage::Summary { .. }))
+            .enumerate()
+            .map(|(i, rg_message)| Item::new(i, rg_message))
+            .collect();
+
+        let mut criteria = ReplacementCriteria::new(capture_pattern, user_replacement, items);
+        criteria.fingerprints = fingerprints;
+        criteria
+    }
+
     pub fn set_encoding(&mut self, encoding: impl AsRef<str>) {
         self.encoding = Some(encoding.as_ref().to_owned());
     }
 
+    /// Returns the text which should replace `sub_item`, see `expand_replacement`. If the match has
+    /// its own replacement then that's used as-is instead of `user_replacement`, and its case
+    /// isn't changed.
+    pub fn replacement_for<'a>(
+        &'a self,
+        sub_item: &'a SubItem,
+        ctx: &MatchContext,
+    ) -> Cow<'a, [u8]> {
+        let (replacement, keep_case) = match &sub_item.replacement {
+            Some(replacement) => (replacement.as_bytes(), false),
+            None => (self.user_replacement.as_slice(), self.preserve_case),
+        };
+
+        expand_replacement(
+            self.capture_pattern.as_ref(),
+            self.literal,
+            keep_case,
+            &sub_item.sub_match.text.to_vec(),
+            replacement,
+            ctx,
+        )
+    }
+
+    /// Counts the matches which will be replaced, see `MatchCounts`.
+    pub fn match_counts(&self) -> MatchCounts {
+        MatchCounts::new(&self.items)
+    }
+
     pub fn as_map(&self) -> HashMap<&ArbitraryData, Vec<&Item>> {
         self.items
             .iter()
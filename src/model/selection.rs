@@ -0,0 +1,110 @@
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+
+use crate::rg::de::RgMessageKind;
+use crate::ui::line::Item;
+
+/// What a `SelectionRule` does to the matches in the files it matches.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SelectionAction {
+    /// Select the matches in the matching files.
+    Include,
+    /// Deselect the matches in the matching files.
+    Exclude,
+    /// Select the matches in the matching files, and deselect all others.
+    Only,
+}
+
+/// A command which selects or deselects matches in bulk, by matching globs against their paths,
+/// e.g., `exclude tests/** vendor/**` or `only *.rs`.
+#[derive(Debug)]
+pub struct SelectionRule {
+    action: SelectionAction,
+    globs: GlobSet,
+}
+
+impl SelectionRule {
+    pub fn parse(command: &str) -> Result<SelectionRule> {
+        let mut words = command.split_whitespace();
+        let action = match words.next() {
+            Some("include") => SelectionAction::Include,
+            Some("exclude") => SelectionAction::Exclude,
+            Some("only") => SelectionAction::Only,
+            Some(other) => bail!(
+                "unknown command '{}', expected one of: include, exclude, only",
+                other
+            ),
+            None => bail!("expected a command: include, exclude or only"),
+        };
+
+        let mut builder = GlobSetBuilder::new();
+        let mut has_globs = false;
+        for glob in words {
+            builder.add(Glob::new(glob)?);
+            has_globs = true;
+        }
+        if !has_globs {
+            bail!("expected at least one glob after '{}'", command.trim());
+        }
+
+        Ok(SelectionRule {
+            action,
+            globs: builder.build()?,
+        })
+    }
+
+    /// Returns true if `path` matches any of the globs. Paths reported by ripgrep may start with
+    /// `./`, which is ignored.
+    pub fn is_match(&self, path: &Path) -> bool {
+        self.globs.is_match(path.strip_prefix("./").unwrap_or(path))
+    }
+
+    /// Selects or deselects the matches in `items`.
+    pub fn apply(&self, items: &mut [Item]) {
+        for item in items.iter_mut().filter(|i| i.kind == RgMessageKind::Match) {
+            let is_match = item.path_buf().is_some_and(|p| self.is_match(&p));
+            match (self.action, is_match) {
+                (SelectionAction::Include, true) | (SelectionAction::Only, true) => {
+                    item.set_should_replace_all(true)
+                }
+                (SelectionAction::Exclude, true) | (SelectionAction::Only, false) => {
+                    item.set_should_replace_all(false)
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn it_parses_commands() {
+        let rule = SelectionRule::parse("  exclude tests/**  *.snap ").unwrap();
+        assert_eq!(rule.action, SelectionAction::Exclude);
+        assert!(rule.is_match(Path::new("tests/foo/bar.rs")));
+        assert!(rule.is_match(Path::new("./tests/bar.rs")));
+        assert!(rule.is_match(Path::new("src/snapshots/foo.snap")));
+        assert!(!rule.is_match(Path::new("src/tests.rs")));
+
+        assert_eq!(
+            SelectionRule::parse("only *.rs").unwrap().action,
+            SelectionAction::Only
+        );
+        assert_eq!(
+            SelectionRule::parse("include src/*").unwrap().action,
+            SelectionAction::Include
+        );
+
+        assert!(SelectionRule::parse("").is_err());
+        assert!(SelectionRule::parse("only").is_err());
+        assert!(SelectionRule::parse("select *.rs").is_err());
+        assert!(SelectionRule::parse("only a[").is_err());
+    }
+}
@@ -0,0 +1,341 @@
+//! Templates used for the replacement text.
+//!
+//! Besides references to capturing groups (`$1`, `${1}`, `$name` and `${name}`), a replacement may
+//! contain the following variables:
+//!
+//! * `${n}`: the number of the match (counting only matches that will be replaced)
+//! * `${file_n}`: the number of the match in its file
+//! * `${line}`: the line number of the match
+//! * `${file}`: the file name of the match
+//! * `${path}`: the path of the match, as reported by ripgrep
+//!
+//! Both capturing groups and variables can be modified by appending modifiers, e.g., `${1:upper}`
+//! or `${n:03}`. Modifiers are applied in order, and are one of:
+//!
+//! * a case: `lower`, `upper`, `title`, `camel`, `pascal`, `snake`, `screaming_snake` or `kebab`
+//! * padding to a width: `0W` (with zeros), `W` (with spaces on the left) or `-W` (with spaces on
+//!   the right)
+
+use std::collections::HashMap;
+
+use regex::bytes::Captures;
+
+use crate::model::CaseStyle;
+use crate::rg::de::{ArbitraryData, RgMessageKind};
+use crate::ui::line::Item;
+
+/// Information about a single match, used to evaluate the variables in a template.
+#[derive(Debug, Default, Clone)]
+pub struct MatchContext<'a> {
+    pub path: Option<&'a ArbitraryData>,
+    pub line_number: Option<usize>,
+    /// The number of the match, starting from 1.
+    pub n: usize,
+    /// The number of the match in its file, starting from 1.
+    pub file_n: usize,
+    /// The lines the match is on, which capturing groups are resolved against if the match on its
+    /// own doesn't match the pattern.
+    pub lines: Option<&'a ArbitraryData>,
+}
+
+impl<'a> MatchContext<'a> {
+    /// Creates the context for the submatch at `sub_idx` of `item`.
+    pub fn new(item: &'a Item, sub_idx: usize, counts: Option<&MatchCounts>) -> MatchContext<'a> {
+        let (n, file_n) = counts.map(|c| c.get(item, sub_idx)).unwrap_or_default();
+        MatchContext {
+            path: item.path(),
+            line_number: item.line_number_at(sub_idx),
+            n,
+            file_n,
+            lines: item.lines(),
+        }
+    }
+
+    fn variable(&self, name: &str) -> Option<Vec<u8>> {
+        match name {
+            "n" => Some(self.n.to_string().into_bytes()),
+            "file_n" => Some(self.file_n.to_string().into_bytes()),
+            "line" => Some(
+                self.line_number
+                    .map(|n| n.to_string())
+                    .unwrap_or_default()
+                    .into_bytes(),
+            ),
+            "file" => Some(
+                self.path
+                    .and_then(|p| p.to_path_buf().ok())
+                    .and_then(|p| p.file_name().map(|f| f.to_string_lossy().into_owned()))
+                    .unwrap_or_default()
+                    .into_bytes(),
+            ),
+            "path" => Some(self.path.map(|p| p.to_vec()).unwrap_or_default()),
+            _ => None,
+        }
+    }
+}
+
+/// The number of matches that will be replaced before each item, both in total and in its file.
+/// This is used for the `${n}` and `${file_n}` variables.
+#[derive(Debug, Default)]
+pub struct MatchCounts {
+    counts: HashMap<(ArbitraryData, usize), (usize, usize)>,
+}
+
+impl MatchCounts {
+    /// Counts the matches in `items`, which must be in the order they were reported by ripgrep.
+    pub fn new(items: &[Item]) -> MatchCounts {
+        let mut counts = HashMap::new();
+        let mut total = 0;
+        let mut in_file = 0;
+        let mut last_path = None;
+        for item in items {
+            if !matches!(item.kind, RgMessageKind::Match) {
+                continue;
+            }
+
+            let path = item.path().unwrap();
+            if last_path != Some(path) {
+                in_file = 0;
+                last_path = Some(path);
+            }
+
+            counts.insert((path.clone(), item.offset().unwrap()), (total, in_file));
+            total += item.replace_count();
+            in_file += item.replace_count();
+        }
+
+        MatchCounts { counts }
+    }
+
+    /// Returns the number of the submatch at `sub_idx` of `item`, both in total and in its file.
+    fn get(&self, item: &Item, sub_idx: usize) -> (usize, usize) {
+        let key = (item.path().unwrap().clone(), item.offset().unwrap());
+        let (total, in_file) = self.counts.get(&key).copied().unwrap_or_default();
+        let before = item.sub_items()[..sub_idx]
+            .iter()
+            .filter(|s| s.should_replace)
+            .count();
+
+        (total + before + 1, in_file + before + 1)
+    }
+}
+
+/// Expands `template` for a single match.
+///
+/// If `captures` is `None`, then the only thing expanded are variables, and everything else
+/// (including `$1` and `$$`) is left as it is.
+pub fn expand_template(
+    template: &[u8],
+    captures: Option<&Captures>,
+    ctx: &MatchContext,
+) -> Vec<u8> {
+    let mut out = Vec::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(pos) = rest.iter().position(|&b| b == b'$') {
+        out.extend_from_slice(&rest[..pos]);
+        rest = &rest[pos..];
+
+        // `$$` is an escaped `$`
+        if rest.get(1) == Some(&b'$') {
+            match captures {
+                Some(_) => out.push(b'$'),
+                None => out.extend_from_slice(b"$$"),
+            }
+            rest = &rest[2..];
+            continue;
+        }
+
+        // `${expr}`
+        if rest.get(1) == Some(&b'{') {
+            if let Some(end) = rest.iter().position(|&b| b == b'}') {
+                match evaluate(&rest[2..end], captures, ctx) {
+                    Some(value) => out.extend_from_slice(&value),
+                    None => out.extend_from_slice(&rest[..=end]),
+                }
+                rest = &rest[end + 1..];
+                continue;
+            }
+        }
+
+        // `$name`
+        let name_len = rest[1..]
+            .iter()
+            .take_while(|b| b.is_ascii_alphanumeric() || **b == b'_')
+            .count();
+        match captures {
+            Some(captures) if name_len > 0 => {
+                let name = std::str::from_utf8(&rest[1..=name_len]).unwrap();
+                out.extend_from_slice(group(captures, name).unwrap_or_default());
+                rest = &rest[name_len + 1..];
+            }
+            _ => {
+                out.push(b'$');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.extend_from_slice(rest);
+
+    out
+}
+
+/// Returns the value of a capturing group, referred to by either its index or name.
+fn group<'t>(captures: &Captures<'t>, name: &str) -> Option<&'t [u8]> {
+    match name.parse::<usize>() {
+        Ok(i) => captures.get(i),
+        Err(_) => captures.name(name),
+    }
+    .map(|m| m.as_bytes())
+}
+
+/// Evaluates the inside of a `${...}` expression. Returns `None` if it's not valid, in which case
+/// it should be left as it is.
+fn evaluate(expr: &[u8], captures: Option<&Captures>, ctx: &MatchContext) -> Option<Vec<u8>> {
+    let expr = std::str::from_utf8(expr).ok()?;
+    let mut parts = expr.split(':');
+    let name = parts.next()?;
+    let modifiers = parts.collect::<Vec<_>>();
+
+    let is_group = |captures: &Captures| match name.parse::<usize>() {
+        Ok(i) => i < captures.len(),
+        Err(_) => captures.name(name).is_some(),
+    };
+    let value = match captures {
+        // capturing groups take precedence over variables
+        Some(captures) if is_group(captures) => group(captures, name).unwrap_or_default().to_vec(),
+        _ => match ctx.variable(name) {
+            Some(value) => value,
+            // in the same way as `Captures::expand`, unknown groups are replaced with nothing
+            None if captures.is_some() && modifiers.is_empty() => vec![],
+            None => return None,
+        },
+    };
+
+    if modifiers.is_empty() {
+        return Some(value);
+    }
+
+    let mut value = String::from_utf8_lossy(&value).into_owned();
+    for modifier in modifiers {
+        value = apply_modifier(modifier, &value)?;
+    }
+
+    Some(value.into_bytes())
+}
+
+fn apply_modifier(modifier: &str, value: &str) -> Option<String> {
+    let case = match modifier {
+        "lower" => Some(CaseStyle::Lower),
+        "upper" => Some(CaseStyle::Upper),
+        "title" => Some(CaseStyle::Title),
+        "camel" => Some(CaseStyle::Camel),
+        "pascal" => Some(CaseStyle::Pascal),
+        "snake" => Some(CaseStyle::Snake),
+        "screaming_snake" => Some(CaseStyle::ScreamingSnake),
+        "kebab" => Some(CaseStyle::Kebab),
+        _ => None,
+    };
+    if let Some(case) = case {
+        return Some(case.apply(value));
+    }
+
+    // padding
+    let (align_left, width) = match modifier.strip_prefix('-') {
+        Some(width) => (true, width),
+        None => (false, modifier),
+    };
+    let zeros = !align_left && width.len() > 1 && width.starts_with('0');
+    let width = width.parse::<usize>().ok()?;
+    let padding = width.saturating_sub(value.chars().count());
+    let fill = if zeros { "0" } else { " " }.repeat(padding);
+
+    Some(if align_left {
+        format!("{}{}", value, fill)
+    } else {
+        format!("{}{}", fill, value)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use regex::bytes::Regex;
+
+    use super::*;
+
+    fn expand(template: &str, re: Option<&str>, matched: &str, ctx: &MatchContext) -> String {
+        let re = re.map(|re| Regex::new(re).unwrap());
+        let captures = re.as_ref().and_then(|re| re.captures(matched.as_bytes()));
+        String::from_utf8(expand_template(template.as_bytes(), captures.as_ref(), ctx)).unwrap()
+    }
+
+    #[test]
+    fn it_expands_captures_like_the_regex_crate() {
+        let ctx = MatchContext::default();
+        let re = r"(?P<first>\w+) (\w+)";
+        for template in ["$2 $1", "${2}_$first", "$$1", "$3|${nope}|$", "a$", "${1"] {
+            let mut expected = vec![];
+            let captures = Regex::new(re).unwrap().captures(b"foo bar").unwrap();
+            captures.expand(template.as_bytes(), &mut expected);
+            assert_eq!(
+                expand(template, Some(re), "foo bar", &ctx),
+                String::from_utf8(expected).unwrap(),
+                "template: {}",
+                template
+            );
+        }
+    }
+
+    #[test]
+    fn it_leaves_captures_without_a_capture_pattern() {
+        let ctx = MatchContext {
+            n: 3,
+            ..Default::default()
+        };
+        assert_eq!(expand("$1 $$ ${1} $n", None, "foo", &ctx), "$1 $$ ${1} $n");
+        assert_eq!(expand("${1:upper} ${n}", None, "foo", &ctx), "${1:upper} 3");
+    }
+
+    #[test]
+    fn it_applies_modifiers() {
+        let ctx = MatchContext::default();
+        let re = Some(r"(\w+)_(\w+)");
+        assert_eq!(expand("${1:upper}", re, "foo_bar", &ctx), "FOO");
+        assert_eq!(expand("${0:camel}", re, "foo_bar", &ctx), "fooBar");
+        assert_eq!(expand("${0:pascal}", re, "foo_bar", &ctx), "FooBar");
+        assert_eq!(expand("${0:kebab:upper}", re, "foo_bar", &ctx), "FOO-BAR");
+        assert_eq!(expand("[${1:5}]", re, "foo_bar", &ctx), "[  foo]");
+        assert_eq!(expand("[${1:-5}]", re, "foo_bar", &ctx), "[foo  ]");
+        assert_eq!(expand("[${1:05}]", re, "foo_bar", &ctx), "[00foo]");
+        assert_eq!(expand("[${1:2}]", re, "foo_bar", &ctx), "[foo]");
+        // invalid modifiers are left as they are
+        assert_eq!(expand("${1:nope}", re, "foo_bar", &ctx), "${1:nope}");
+    }
+
+    #[test]
+    fn it_expands_variables() {
+        let path = ArbitraryData::new_with_text("src/dir/main.rs".into());
+        let ctx = MatchContext {
+            path: Some(&path),
+            line_number: Some(42),
+            n: 7,
+            file_n: 2,
+            ..Default::default()
+        };
+        assert_eq!(
+            expand(
+                "${n:03} ${file_n} ${line} ${file} ${path}",
+                None,
+                "foo",
+                &ctx
+            ),
+            "007 2 42 main.rs src/dir/main.rs"
+        );
+
+        // capturing groups take precedence
+        assert_eq!(
+            expand("${n}/${line}", Some(r"(?P<n>\w+)"), "foo", &ctx),
+            "foo/42"
+        );
+    }
+}
@@ -0,0 +1,214 @@
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::rg::de::RgMessageKind;
+use crate::ui::line::Item;
+
+/// A directory or a file in a `FileTree`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeNode {
+    /// The last component of the node's path.
+    pub name: String,
+    pub path: PathBuf,
+    /// How many directories this node is nested in.
+    pub depth: usize,
+    /// The index of the file's `Begin` item, or `None` if this node is a directory.
+    pub begin_idx: Option<usize>,
+    /// The indices of all the `Match` items under this node.
+    pub matches: Vec<usize>,
+}
+
+impl TreeNode {
+    pub fn is_dir(&self) -> bool {
+        self.begin_idx.is_none()
+    }
+}
+
+/// The results of a search, grouped by directory and file.
+#[derive(Debug, Default)]
+pub struct FileTree {
+    /// The nodes of the tree in depth-first order, sorted by path.
+    nodes: Vec<TreeNode>,
+}
+
+impl FileTree {
+    /// Builds the tree from the items in the matches list. Hidden items are left out.
+    pub fn new(items: &[Item]) -> FileTree {
+        let mut files = BTreeMap::<PathBuf, (usize, Vec<usize>)>::new();
+        let mut current_file = None;
+        for item in items.iter().filter(|i| !i.is_hidden()) {
+            match item.kind {
+                RgMessageKind::Begin => {
+                    current_file = item.path_buf().map(|p| {
+                        let p = p.strip_prefix("./").map(Path::to_path_buf).unwrap_or(p);
+                        files.entry(p.clone()).or_insert((item.index, vec![]));
+                        p
+                    });
+                }
+                RgMessageKind::Match => {
+                    if let Some(file) = files.get_mut(current_file.as_ref().unwrap()) {
+                        file.1.push(item.index);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mut nodes: Vec<TreeNode> = vec![];
+        // the indices of the directory nodes which contain the current file
+        let mut dirs: Vec<usize> = vec![];
+        for (path, (begin_idx, matches)) in files {
+            let parents = path
+                .parent()
+                .map(|p| p.components().collect::<Vec<_>>())
+                .unwrap_or_default();
+
+            // leave the directories which don't contain this file
+            let common = dirs
+                .iter()
+                .zip(&parents)
+                .take_while(|(&node_idx, c)| {
+                    nodes[node_idx].name == c.as_os_str().to_string_lossy()
+                })
+                .count();
+            dirs.truncate(common);
+
+            // and enter the ones that do
+            for component in &parents[common..] {
+                let parent_path = dirs
+                    .last()
+                    .map(|&i| nodes[i].path.clone())
+                    .unwrap_or_default();
+                nodes.push(TreeNode {
+                    name: component.as_os_str().to_string_lossy().into_owned(),
+                    path: parent_path.join(component),
+                    depth: dirs.len(),
+                    begin_idx: None,
+                    matches: vec![],
+                });
+                dirs.push(nodes.len() - 1);
+            }
+
+            for &node_idx in &dirs {
+                nodes[node_idx].matches.extend_from_slice(&matches);
+            }
+            nodes.push(TreeNode {
+                name: path
+                    .file_name()
+                    .map(|f| f.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+                path,
+                depth: dirs.len(),
+                begin_idx: Some(begin_idx),
+                matches,
+            });
+        }
+
+        FileTree { nodes }
+    }
+
+    pub fn nodes(&self) -> &[TreeNode] {
+        &self.nodes
+    }
+
+    /// Returns the indices of the nodes which aren't inside a folded directory.
+    pub fn visible(&self, folded: &HashSet<PathBuf>) -> Vec<usize> {
+        let mut visible = vec![];
+        let mut folded_depth = None;
+        for (i, node) in self.nodes.iter().enumerate() {
+            match folded_depth {
+                Some(depth) if node.depth > depth => continue,
+                _ => folded_depth = None,
+            }
+
+            visible.push(i);
+            if node.is_dir() && folded.contains(&node.path) {
+                folded_depth = Some(node.depth);
+            }
+        }
+
+        visible
+    }
+
+    /// Returns the index of the directory which contains the node at `idx`.
+    pub fn parent(&self, idx: usize) -> Option<usize> {
+        let depth = self.nodes[idx].depth;
+        self.nodes[..idx].iter().rposition(|n| n.depth < depth)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::rg::de::test_utilities::*;
+    use crate::rg::de::*;
+
+    fn file(path: &str, matches: usize) -> Vec<RgMessage> {
+        let mut messages = vec![RgMessageBuilder::new(RgMessageKind::Begin)
+            .with_path_text(path)
+            .build()];
+        for _ in 0..matches {
+            messages.push(
+                RgMessageBuilder::new(RgMessageKind::Match)
+                    .with_path_text(path)
+                    .with_offset(0)
+                    .with_lines_text("foo\n")
+                    .with_submatches(vec![SubMatch::new_text("foo", 0..3)])
+                    .build(),
+            );
+        }
+        messages.push(
+            RgMessageBuilder::new(RgMessageKind::End)
+                .with_path_text(path)
+                .with_stats(Stats::new())
+                .build(),
+        );
+        messages
+    }
+
+    #[test]
+    fn it_groups_files_by_directory() {
+        let items = [
+            file("./src/model/tree.rs", 2),
+            file("./src/main.rs", 1),
+            file("./tests/foo.rs", 1),
+            file("./src/model/item.rs", 1),
+        ]
+        .concat()
+        .into_iter()
+        .enumerate()
+        .map(|(i, m)| Item::new(i, m))
+        .collect::<Vec<_>>();
+
+        let tree = FileTree::new(&items);
+        let nodes = tree
+            .nodes()
+            .iter()
+            .map(|n| (n.depth, n.name.as_str(), n.begin_idx, n.matches.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            nodes,
+            vec![
+                (0, "src", None, vec![5, 11, 1, 2]),
+                (1, "main.rs", Some(4), vec![5]),
+                (1, "model", None, vec![11, 1, 2]),
+                (2, "item.rs", Some(10), vec![11]),
+                (2, "tree.rs", Some(0), vec![1, 2]),
+                (0, "tests", None, vec![8]),
+                (1, "foo.rs", Some(7), vec![8]),
+            ]
+        );
+        assert_eq!(tree.nodes()[3].path, Path::new("src/model/item.rs"));
+
+        assert_eq!(tree.parent(4), Some(2));
+        assert_eq!(tree.parent(2), Some(0));
+        assert_eq!(tree.parent(5), None);
+
+        let folded = vec![PathBuf::from("src/model"), PathBuf::from("tests")]
+            .into_iter()
+            .collect();
+        assert_eq!(tree.visible(&folded), vec![0, 1, 2, 5]);
+    }
+}
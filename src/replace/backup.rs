@@ -0,0 +1,187 @@
+//! Keeping a copy of each original file beside the replaced one (like `sed -i.orig`), or in a
+//! directory which mirrors the paths of the replaced files.
+//!
+//! Where possible the backup is a hard link to the original, which is then left untouched since the
+//! replaced file is renamed over its path. Otherwise the original is copied, along with its
+//! permissions and timestamps. Either way the backup is made beside its final path and renamed
+//! over it, so a previous backup is never removed before the new one exists.
+
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use filetime::FileTime;
+use tempfile::{Builder, TempPath};
+
+/// The suffix used when `--backup` is passed without one.
+pub const DEFAULT_SUFFIX: &str = ".orig";
+
+/// Where the original of each replaced file is kept.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Backups {
+    /// Appended to the name of each file.
+    pub suffix: String,
+    /// If set, the backups are kept in this directory at the absolute path of each file (e.g.,
+    /// `/etc/hosts` is kept as `<dir>/etc/hosts`), rather than beside it.
+    pub dir: Option<PathBuf>,
+}
+
+impl Backups {
+    /// Returns the path where the original of the file at `path` is kept.
+    pub fn path_for(&self, path: &Path) -> Result<PathBuf> {
+        let file_name = path
+            .file_name()
+            .with_context(|| anyhow!("Failed to get file name for file: {}", path.display()))?;
+        let mut backup_name = file_name.to_os_string();
+        backup_name.push(&self.suffix);
+
+        match &self.dir {
+            None => Ok(path.with_file_name(backup_name)),
+            Some(dir) => {
+                let parent_dir = match path.parent() {
+                    Some(parent) if parent != Path::new("") => parent,
+                    _ => Path::new("."),
+                };
+                let parent_dir = fs::canonicalize(parent_dir)?;
+                let mirrored = parent_dir
+                    .components()
+                    .filter(|c| matches!(c, Component::Normal(_)))
+                    .fold(dir.clone(), |dir, c| dir.join(c));
+                Ok(mirrored.join(backup_name))
+            }
+        }
+    }
+}
+
+/// Returns true if both paths exist and lead to the same file (other than by a hard link).
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Keeps the original file at `path` as `backup_path`, replacing any previous backup.
+pub fn back_up(path: &Path, backup_path: &Path) -> Result<()> {
+    log::debug!("Backing up {} as {}", path.display(), backup_path.display());
+    if is_same_file(path, backup_path) {
+        bail!("Refusing to back up {} over itself", path.display());
+    }
+
+    let parent_dir = match backup_path.parent() {
+        Some(parent) if parent != Path::new("") => parent,
+        _ => Path::new("."),
+    };
+    fs::create_dir_all(parent_dir)?;
+
+    let linked = Builder::new()
+        .prefix(".rgr-backup")
+        .make_in(parent_dir, |temp_path| fs::hard_link(path, temp_path));
+    let temp_path = match linked {
+        Ok(linked) => linked.into_temp_path(),
+        Err(e) => {
+            log::debug!("Failed to link backup, copying instead: {}", e);
+            copy_with_times(path, parent_dir)?
+        }
+    };
+
+    temp_path.persist(backup_path)?;
+    Ok(())
+}
+
+/// Copies the file at `path` into a temporary file in `dir`, along with its timestamps.
+fn copy_with_times(path: &Path, dir: &Path) -> Result<TempPath> {
+    // read the timestamps first, since copying may update the access time
+    let metadata = fs::metadata(path)?;
+    let temp_path = Builder::new()
+        .prefix(".rgr-backup")
+        .tempfile_in(dir)?
+        .into_temp_path();
+    fs::copy(path, &temp_path)?;
+    filetime::set_file_times(
+        &temp_path,
+        FileTime::from_last_access_time(&metadata),
+        FileTime::from_last_modification_time(&metadata),
+    )?;
+
+    Ok(temp_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn it_puts_backups_beside_or_in_a_mirrored_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.txt");
+
+        let beside = Backups {
+            suffix: DEFAULT_SUFFIX.to_string(),
+            dir: None,
+        };
+        assert_eq!(
+            beside.path_for(&path).unwrap(),
+            dir.path().join("a.txt.orig")
+        );
+
+        let backup_dir = tempfile::tempdir().unwrap();
+        let mirrored = Backups {
+            suffix: String::new(),
+            dir: Some(backup_dir.path().to_path_buf()),
+        };
+        let expected = fs::canonicalize(dir.path())
+            .unwrap()
+            .components()
+            .filter(|c| matches!(c, Component::Normal(_)))
+            .fold(backup_dir.path().to_path_buf(), |dir, c| dir.join(c))
+            .join("a.txt");
+        assert_eq!(mirrored.path_for(&path).unwrap(), expected);
+    }
+
+    #[test]
+    fn it_copies_the_timestamps_of_the_original() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.txt");
+        let backup_path = dir.path().join("mirror").join("a.txt.orig");
+        fs::write(&path, "foo").unwrap();
+
+        let mtime = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+        filetime::set_file_mtime(&path, FileTime::from_system_time(mtime)).unwrap();
+
+        back_up(&path, &backup_path).unwrap();
+        assert_eq!(fs::read_to_string(&backup_path).unwrap(), "foo");
+        assert_eq!(
+            fs::metadata(&backup_path).unwrap().modified().unwrap(),
+            mtime
+        );
+
+        // an older backup is replaced
+        let path = dir.path().join("b.txt");
+        fs::write(&path, "bar").unwrap();
+        fs::write(&backup_path, "old").unwrap();
+        back_up(&path, &backup_path).unwrap();
+        assert_eq!(fs::read_to_string(&backup_path).unwrap(), "bar");
+    }
+
+    #[test]
+    fn it_keeps_the_previous_backup_if_backing_up_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.txt");
+        let backup_path = dir.path().join("a.txt.orig");
+        fs::write(&path, "foo").unwrap();
+        fs::write(&backup_path, "old").unwrap();
+
+        // a file is never its own backup
+        assert!(back_up(&path, &path).is_err());
+        assert!(back_up(&path, &dir.path().join(".").join("a.txt")).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "foo");
+
+        // nothing is removed if the original can't be read
+        assert!(back_up(&dir.path().join("missing.txt"), &backup_path).is_err());
+        assert_eq!(fs::read_to_string(&backup_path).unwrap(), "old");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+}
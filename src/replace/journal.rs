@@ -0,0 +1,365 @@
+//! A journal of the files changed during a replacement session, so that the session can be undone.
+//!
+//! Before each file is written, a copy of its original contents is saved in a directory of the
+//! session inside the journal directory, along with a hash of the contents that are about to be
+//! written. Only the last session is kept, but a new session only replaces the previous one once
+//! it has replaced a file, so a session that writes nothing can't lose the previous one.
+
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use tempfile::{Builder, NamedTempFile, TempDir};
+
+use crate::rg::de::ArbitraryData;
+use crate::util::hash_reader;
+
+const MANIFEST_FILE_NAME: &str = "journal.json";
+
+/// Returns the default directory where the journal is kept.
+pub fn default_journal_dir() -> Option<PathBuf> {
+    dirs::state_dir()
+        .or_else(dirs::data_local_dir)
+        .map(|dir| dir.join(env!("CARGO_PKG_NAME")).join("journal"))
+}
+
+/// A single file that was changed during a replacement session.
+#[derive(Debug, Serialize, Deserialize)]
+struct JournalEntry {
+    /// The path of the file that was changed.
+    path: ArbitraryData,
+    /// The path of the file (relative to the journal directory) which holds the original contents.
+    backup: String,
+    /// Hash of the original contents of the file.
+    original_hash: String,
+    /// Hash of the contents that replaced the original contents.
+    replaced_hash: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Manifest {
+    entries: Vec<JournalEntry>,
+}
+
+/// The journal of a session, which can be shared between the threads replacing files. Files are
+/// copied and hashed without holding its lock.
+pub struct Journal {
+    dir: PathBuf,
+    /// The name of this session's directory inside `dir`.
+    session_name: String,
+    /// The number of the next file to be copied into the session's directory.
+    next_backup: AtomicUsize,
+    state: Mutex<JournalState>,
+}
+
+struct JournalState {
+    /// This session's directory until the session is committed, which removes it when dropped.
+    uncommitted: Option<TempDir>,
+    manifest: Manifest,
+}
+
+impl Journal {
+    /// Starts a new session in `dir`. The journal of any previous session is kept until this one is
+    /// committed.
+    pub fn begin(dir: impl AsRef<Path>) -> Result<Journal> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create journal: {}", dir.display()))?;
+        let session = Builder::new()
+            .prefix("session")
+            .tempdir_in(&dir)
+            .with_context(|| format!("Failed to create journal: {}", dir.display()))?;
+        let session_name = session
+            .path()
+            .file_name()
+            .and_then(|name| name.to_str())
+            .context("Failed to name the journal session")?
+            .to_string();
+
+        log::debug!("Starting journal in: {}", session.path().display());
+        Ok(Journal {
+            dir,
+            session_name,
+            next_backup: AtomicUsize::new(0),
+            state: Mutex::new(JournalState {
+                uncommitted: Some(session),
+                manifest: Manifest::default(),
+            }),
+        })
+    }
+
+    /// Makes this session the one that's undone, once one of its files has been replaced. The
+    /// journal of the previous session is removed the first time this is called.
+    pub fn commit(&self) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let session = match state.uncommitted.take() {
+            Some(session) => session,
+            None => return Ok(()),
+        };
+
+        log::debug!("Committing journal: {}", session.path().display());
+        let _ = session.into_path();
+        self.write_manifest(&state.manifest)?;
+
+        // remove the previous session, along with any left behind by sessions that were interrupted
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            if entry.file_name() == MANIFEST_FILE_NAME || entry.file_name() == *self.session_name {
+                continue;
+            }
+            let result = if entry.file_type()?.is_dir() {
+                fs::remove_dir_all(entry.path())
+            } else {
+                fs::remove_file(entry.path())
+            };
+            if let Err(e) = result {
+                log::warn!("Failed to remove {}: {}", entry.path().display(), e);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Records the original contents of the file at `path`, where `replaced_hash` is the hash of
+    /// the contents that are about to be written (see `util::hash`). This must be called *before*
+    /// the new contents are written.
+    pub fn record(&self, path: &ArbitraryData, replaced_hash: String) -> Result<()> {
+        let path_buf = path.to_path_buf()?;
+
+        // NOTE: the file is copied rather than read, since it may be too large to fit in memory.
+        let backup_index = self.next_backup.fetch_add(1, Ordering::Relaxed);
+        let backup = format!("{}/{}.orig", self.session_name, backup_index);
+        let backup_path = self.dir.join(&backup);
+        log::debug!("Journaling {} as {}", path_buf.display(), backup);
+        fs::copy(&path_buf, &backup_path)?;
+        let original_hash = hash_reader(BufReader::new(File::open(&backup_path)?))?;
+
+        let mut state = self.state.lock().unwrap();
+        state.manifest.entries.push(JournalEntry {
+            path: path.clone(),
+            backup,
+            original_hash,
+            replaced_hash,
+        });
+
+        // Write the manifest after every entry, so the journal is still usable if we're interrupted.
+        match state.uncommitted {
+            Some(_) => Ok(()),
+            None => self.write_manifest(&state.manifest),
+        }
+    }
+
+    /// Replaces the manifest, so that it's never left partly written.
+    fn write_manifest(&self, manifest: &Manifest) -> Result<()> {
+        let mut temp_file = NamedTempFile::new_in(&self.dir)?;
+        serde_json::to_writer(temp_file.as_file_mut(), manifest)?;
+        temp_file.persist(self.dir.join(MANIFEST_FILE_NAME))?;
+        Ok(())
+    }
+}
+
+/// Restores every file changed in the last session recorded in `dir`.
+///
+/// This refuses to restore anything if any of the files have been changed since they were written,
+/// and returns the number of files restored.
+pub fn undo_last_session(dir: impl AsRef<Path>) -> Result<usize> {
+    let dir = dir.as_ref();
+    let manifest_path = dir.join(MANIFEST_FILE_NAME);
+    if !manifest_path.exists() {
+        bail!("There is no replacement session to undo");
+    }
+
+    let manifest: Manifest = serde_json::from_reader(File::open(&manifest_path)?)
+        .with_context(|| format!("Failed to read journal: {}", manifest_path.display()))?;
+
+    // Check every file before touching any of them.
+    let mut to_restore = vec![];
+    let mut changed = vec![];
+    for entry in &manifest.entries {
+        let path_buf = entry.path.to_path_buf()?;
+        let current_hash = File::open(&path_buf)
+            .and_then(|file| hash_reader(BufReader::new(file)))
+            .ok();
+        match current_hash {
+            // The session was interrupted before this file was written, nothing to do.
+            Some(h) if h == entry.original_hash => {
+                log::debug!("Already in its original state: {}", path_buf.display());
+            }
+            Some(h) if h == entry.replaced_hash => to_restore.push((entry, path_buf)),
+            _ => changed.push(path_buf),
+        }
+    }
+
+    if !changed.is_empty() {
+        bail!(
+            "Refusing to undo, the following files have changed since they were replaced:\n{}",
+            changed
+                .iter()
+                .map(|p| format!("  - {}", p.display()))
+                .collect::<Vec<_>>()
+                .join("\n")
+        );
+    }
+
+    for (entry, path_buf) in &to_restore {
+        log::debug!("Restoring {} from {}", path_buf.display(), entry.backup);
+        restore_file(path_buf, &dir.join(&entry.backup))
+            .with_context(|| format!("Failed to restore: {}", path_buf.display()))?;
+    }
+
+    fs::remove_dir_all(dir)?;
+
+    Ok(to_restore.len())
+}
+
+/// Replaces the file at `path_buf` with a copy of `backup_path`, keeping its permissions.
+fn restore_file(path_buf: &Path, backup_path: &Path) -> Result<()> {
+    let parent_dir = path_buf.parent().with_context(|| {
+        anyhow!(
+            "Failed to get parent directory for file: {}",
+            path_buf.display()
+        )
+    })?;
+
+    let temp_path = NamedTempFile::new_in(parent_dir)?.into_temp_path();
+    fs::copy(backup_path, &temp_path)?;
+    fs::set_permissions(&temp_path, fs::metadata(path_buf)?.permissions())?;
+    temp_path.persist(path_buf)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::model::ReplacementCriteria;
+    use crate::replace::perform_replacements;
+    use crate::rg::de::test_utilities::RgMessageBuilder;
+    use crate::rg::de::{RgMessageKind, SubMatch};
+    use crate::temp_file;
+    use crate::ui::line::Item;
+
+    fn item(path: &Path, text: &str, submatch: SubMatch) -> Item {
+        Item::new(
+            0,
+            RgMessageBuilder::new(RgMessageKind::Match)
+                .with_path_text(path.to_string_lossy())
+                .with_lines_text(text)
+                .with_submatches(vec![submatch])
+                .with_offset(0)
+                .build(),
+        )
+    }
+
+    fn replace_with_journal(journal_dir: &Path, items: Vec<Item>) {
+        let mut criteria = ReplacementCriteria::new(None, "NEW_VALUE", items);
+        criteria.journal_dir = Some(journal_dir.to_path_buf());
+        perform_replacements(criteria).unwrap();
+    }
+
+    #[test]
+    fn it_undoes_the_last_session() {
+        let journal_dir = tempfile::tempdir().unwrap();
+        let p1 = temp_file!("foo bar baz");
+        let p2 = temp_file!("baz foo bar");
+
+        replace_with_journal(
+            journal_dir.path(),
+            vec![
+                item(&p1, "foo bar baz", SubMatch::new_text("foo", 0..3)),
+                item(&p2, "baz foo bar", SubMatch::new_text("foo", 4..7)),
+            ],
+        );
+        assert_eq!(fs::read_to_string(&p1).unwrap(), "NEW_VALUE bar baz");
+        assert_eq!(fs::read_to_string(&p2).unwrap(), "baz NEW_VALUE bar");
+
+        assert_eq!(undo_last_session(journal_dir.path()).unwrap(), 2);
+        assert_eq!(fs::read_to_string(&p1).unwrap(), "foo bar baz");
+        assert_eq!(fs::read_to_string(&p2).unwrap(), "baz foo bar");
+
+        // the journal is removed once it's been undone
+        assert!(undo_last_session(journal_dir.path()).is_err());
+    }
+
+    #[test]
+    fn it_refuses_to_undo_if_a_file_has_changed() {
+        let journal_dir = tempfile::tempdir().unwrap();
+        let p1 = temp_file!("foo bar baz");
+        let p2 = temp_file!("baz foo bar");
+
+        replace_with_journal(
+            journal_dir.path(),
+            vec![
+                item(&p1, "foo bar baz", SubMatch::new_text("foo", 0..3)),
+                item(&p2, "baz foo bar", SubMatch::new_text("foo", 4..7)),
+            ],
+        );
+        fs::write(&p2, "changed").unwrap();
+
+        let err = undo_last_session(journal_dir.path()).unwrap_err();
+        assert!(err.to_string().contains(&p2.display().to_string()));
+
+        // nothing should have been restored
+        assert_eq!(fs::read_to_string(&p1).unwrap(), "NEW_VALUE bar baz");
+        assert_eq!(fs::read_to_string(&p2).unwrap(), "changed");
+    }
+
+    #[test]
+    fn it_only_keeps_the_last_session() {
+        let journal_dir = tempfile::tempdir().unwrap();
+        let p1 = temp_file!("foo bar baz");
+        let p2 = temp_file!("baz foo bar");
+
+        replace_with_journal(
+            journal_dir.path(),
+            vec![item(&p1, "foo bar baz", SubMatch::new_text("foo", 0..3))],
+        );
+        replace_with_journal(
+            journal_dir.path(),
+            vec![item(&p2, "baz foo bar", SubMatch::new_text("foo", 4..7))],
+        );
+
+        // the first session is removed once the second has replaced a file
+        assert_eq!(fs::read_dir(journal_dir.path()).unwrap().count(), 2);
+        assert_eq!(undo_last_session(journal_dir.path()).unwrap(), 1);
+        assert_eq!(fs::read_to_string(&p1).unwrap(), "NEW_VALUE bar baz");
+        assert_eq!(fs::read_to_string(&p2).unwrap(), "baz foo bar");
+    }
+
+    #[test]
+    fn it_keeps_the_last_session_if_nothing_is_replaced() {
+        let journal_dir = tempfile::tempdir().unwrap();
+        let p1 = temp_file!("foo bar baz");
+        replace_with_journal(
+            journal_dir.path(),
+            vec![item(&p1, "foo bar baz", SubMatch::new_text("foo", 0..3))],
+        );
+
+        // a session that fails before it writes anything
+        let missing = p1.with_extension("missing");
+        let mut criteria = ReplacementCriteria::new(
+            None,
+            "NEW_VALUE",
+            vec![item(
+                &missing,
+                "foo bar baz",
+                SubMatch::new_text("foo", 0..3),
+            )],
+        );
+        criteria.journal_dir = Some(journal_dir.path().to_path_buf());
+        assert!(perform_replacements(criteria).is_err());
+
+        // only the manifest and the directory of the first session are left
+        assert_eq!(fs::read_dir(journal_dir.path()).unwrap().count(), 2);
+        assert_eq!(undo_last_session(journal_dir.path()).unwrap(), 1);
+        assert_eq!(fs::read_to_string(&p1).unwrap(), "foo bar baz");
+    }
+}
@@ -0,0 +1,1577 @@
+pub mod backup;
+pub mod journal;
+pub mod pool;
+pub mod report;
+pub mod stale;
+pub mod stream;
+pub mod transaction;
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use encoding::{DecoderTrap, EncoderTrap, EncodingRef};
+use serde::Serialize;
+use similar::TextDiff;
+use tempfile::NamedTempFile;
+
+use self::journal::Journal;
+use self::report::{FileReport, Report, SkipReason, SkippedMatch};
+use self::stale::OnStale;
+use self::transaction::StagedFile;
+use crate::encoding::{get_encoder, Bom};
+use crate::model::{MatchContext, MatchCounts, ReplacementCriteria};
+use crate::rg::de::{ArbitraryData, RgMessageKind, SubMatch};
+use crate::rg::RgEncoding;
+use crate::ui::line::Item;
+use crate::util::hash;
+
+/// A file which has had its replacements performed in memory, but which hasn't been written yet.
+struct ReplacedFile {
+    path_data: ArbitraryData,
+    path_buf: PathBuf,
+    bom: Option<Bom>,
+    encoder: EncodingRef,
+    /// The decoded contents of the file before any replacements were made.
+    original: String,
+    /// The decoded contents of the file after all replacements were made.
+    replaced: String,
+    /// The number of matches that were replaced.
+    replaced_count: usize,
+    skipped: Vec<SkippedMatch>,
+}
+
+impl ReplacedFile {
+    /// Re-encodes the replaced contents into the file's original encoding (including its BOM).
+    fn encode(&self) -> Result<Vec<u8>> {
+        // Convert back into the detected encoding.
+        log::trace!("Re-encoding file");
+        let replaced_contents = self
+            .encoder
+            .encode(&self.replaced, EncoderTrap::Strict)
+            .map_err(|e| anyhow!("Failed to encode replaced string: {}", e))?;
+
+        // Write a BOM if one existed beforehand.
+        match self.bom {
+            // NOTE: we don't strip a UTF8 BOM, because ripgrep doesn't either therefore no need to re-write one
+            // See: https://github.com/BurntSushi/ripgrep/issues/1638
+            None | Some(Bom::Utf8) => Ok(replaced_contents),
+            Some(bom) => {
+                let bom_bytes = bom.bytes();
+                log::debug!("Writing BOM: {:?}", bom_bytes);
+                Ok([bom_bytes, &replaced_contents].concat())
+            }
+        }
+    }
+
+    /// Writes `contents` to a temporary file beside the original, which can then atomically
+    /// overwrite it.
+    fn stage(&self, contents: &[u8]) -> Result<StagedFile> {
+        let mut temp_file = create_temp_file(&self.path_buf)?;
+
+        // Write the replaced contents.
+        log::debug!("Writing: {}", temp_file.path().display());
+        temp_file.write_all(contents)?;
+
+        Ok(StagedFile {
+            path_buf: self.path_buf.clone(),
+            temp_path: temp_file.into_temp_path(),
+            backup_path: None,
+        })
+    }
+
+    /// Writes a unified diff of the replacements to `out`. Nothing is written if the file is unchanged.
+    /// NOTE: the diff is made from the decoded contents, and is always written as UTF-8.
+    fn write_diff(&self, out: &mut impl Write) -> Result<()> {
+        if self.original == self.replaced {
+            return Ok(());
+        }
+
+        let (old_path, new_path) = diff_paths(&self.path_buf);
+        write!(
+            out,
+            "{}",
+            TextDiff::from_lines(&self.original, &self.replaced)
+                .unified_diff()
+                .header(&old_path, &new_path)
+        )?;
+
+        Ok(())
+    }
+}
+
+/// Creates a temporary file next to the file at `path_buf` with the same permissions, so that it can
+/// atomically replace it once it's been written.
+fn create_temp_file(path_buf: &Path) -> Result<NamedTempFile> {
+    let parent_dir = path_buf.parent().with_context(|| {
+        anyhow!(
+            "Failed to get parent directory for file: {}",
+            path_buf.display()
+        )
+    })?;
+    let temp_file = NamedTempFile::new_in(parent_dir)?;
+    log::debug!("Creating temporary file: {}", temp_file.path().display());
+
+    // Adjust permissions of the file to match the target file's permissions
+    // Related: https://github.com/Stebalien/tempfile/issues/157
+    temp_file.as_file().set_permissions({
+        let file = File::open(path_buf)?;
+        file.metadata()?.permissions()
+    })?;
+
+    Ok(temp_file)
+}
+
+/// The outcome of making the replacements in a single file.
+#[derive(Debug)]
+struct FileOutcome {
+    report: FileReport,
+    /// A unified diff of the replacements, if they were only being previewed.
+    diff: Vec<u8>,
+    /// The replaced file, if it's waiting to be committed with the others (see `transaction`).
+    staged: Option<StagedFile>,
+}
+
+impl From<FileReport> for FileOutcome {
+    fn from(report: FileReport) -> FileOutcome {
+        FileOutcome {
+            report,
+            diff: vec![],
+            staged: None,
+        }
+    }
+}
+
+impl FileOutcome {
+    /// Overwrites the original file with `staged` now, or keeps it to be committed later if the
+    /// replacements are being made in a transaction.
+    fn with_staged(
+        mut self,
+        criteria: &ReplacementCriteria,
+        mut staged: StagedFile,
+    ) -> Result<FileOutcome> {
+        if let Some(backups) = &criteria.backups {
+            staged.backup_path = Some(backups.path_for(&staged.path_buf)?);
+        }
+        if criteria.transaction {
+            self.staged = Some(staged);
+        } else {
+            staged.persist()?;
+        }
+
+        Ok(self)
+    }
+}
+
+impl From<ReplacedFile> for FileOutcome {
+    fn from(replaced_file: ReplacedFile) -> FileOutcome {
+        let mut report = FileReport::new(&replaced_file.path_data)
+            .with_encoding(replaced_file.bom, replaced_file.encoder.name());
+        report.replaced = replaced_file.replaced_count;
+        report.skipped = replaced_file.skipped;
+        FileOutcome::from(report)
+    }
+}
+
+/// The number of files and matches that were replaced.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ReplacementSummary {
+    pub files: usize,
+    pub matches: usize,
+}
+
+/// Returns the paths used in the header of a unified diff, in the same style as `git diff`, so
+/// that the resulting patch can be applied with `git apply` or `patch -p1`.
+fn diff_paths(path: &Path) -> (String, String) {
+    let path = path.strip_prefix(".").unwrap_or(path);
+    if path.is_absolute() {
+        let path = path.display().to_string();
+        (path.clone(), path)
+    } else {
+        (
+            format!("a/{}", path.display()),
+            format!("b/{}", path.display()),
+        )
+    }
+}
+
+/// Reads the file and performs all the replacements in memory, without writing anything to disk.
+fn perform_replacements_in_memory(
+    criteria: &ReplacementCriteria,
+    counts: &MatchCounts,
+    rg_encoding: &RgEncoding,
+    (path_data, mut items): (&ArbitraryData, Vec<&Item>),
+) -> Result<ReplacedFile> {
+    log::debug!("File: {} (item count: {})", path_data, items.len());
+    let path_buf = path_data.to_path_buf()?;
+
+    // Check the file for a BOM, detect its encoding and then decode it into a string.
+    let (bom, encoder, original) = {
+        let mut file_contents = vec![];
+        OpenOptions::new()
+            .read(true)
+            .open(&path_buf)?
+            .read_to_end(&mut file_contents)?;
+
+        // Search for a BOM and attempt to detect file encoding.
+        let (bom, encoder) = get_encoder(&file_contents, rg_encoding);
+        log::debug!("BOM: {:?}", bom);
+        log::debug!("Encoder: {}", encoder.name());
+
+        // Strip the BOM before we decode.
+        match bom {
+            // NOTE: we don't strip a UTF8 BOM, because ripgrep doesn't either
+            // See: https://github.com/BurntSushi/ripgrep/issues/1638
+            None | Some(Bom::Utf8) => {}
+            Some(_) => {
+                file_contents = file_contents
+                    .iter()
+                    .skip(bom.unwrap().len())
+                    .copied()
+                    .collect();
+            }
+        }
+
+        log::trace!("Decoding file");
+        let decoded = encoder
+            .decode(&file_contents, DecoderTrap::Strict)
+            .map_err(|e| anyhow!("Failed to decode file: {}", e))?;
+
+        (bom, encoder, decoded)
+    };
+    let mut file_as_str = original.clone();
+
+    // Sort the items so they're in order - ripgrep should give them to us in order anyway but we sort them here to
+    // future-proof against any changes.
+    // NOTE: we're sorting by the offset here with the assumption that no two Match items within one file will have
+    // the same offset.
+    items.sort_unstable_by_key(|i| i.offset());
+
+    // Iterate over the items in _reverse_ order -> this is so offsets can stay the same even though we're making
+    // changes to the string.
+    let mut skipped = vec![];
+    let mut replaced_count = 0;
+    for (i, item) in items.iter().rev().enumerate() {
+        let offset = item.offset().unwrap();
+        log::debug!("Item[{}] offset: {}", i, offset);
+
+        // Iterate backwards so the offset doesn't change as we make replacements.
+        for (i, sub_item) in item
+            .sub_items()
+            .iter()
+            .rev()
+            .filter(|s| s.should_replace)
+            .enumerate()
+        {
+            let SubMatch { range, text } = &sub_item.sub_match;
+            log::debug!("SubMatch[{}] range: {:?}, data: \"{}\"", i, range, text);
+
+            let normalised_range = (offset + range.start)..(offset + range.end);
+            let str_to_remove = match file_as_str.get(normalised_range.clone()) {
+                Some(str_to_remove) => str_to_remove,
+                None => {
+                    log::warn!("Match is outside of the file, or not on a character boundary!");
+                    log::warn!("\tFile: \"{}\"", path_buf.display());
+                    log::warn!("\tOffset: {}", offset + range.start);
+                    let reason = if normalised_range.end > file_as_str.len() {
+                        SkipReason::EndOfFile
+                    } else {
+                        SkipReason::BytesMismatch
+                    };
+                    skipped.push(SkippedMatch::new(item, sub_item, reason));
+                    continue;
+                }
+            };
+            let matched_bytes = text.to_vec();
+
+            if str_to_remove.as_bytes() == matched_bytes.as_slice() {
+                // compute replacement
+                let ctx = MatchContext::new(item, sub_item.index, Some(counts));
+                let replacement = criteria.replacement_for(sub_item, &ctx);
+
+                // have to save this because it will be invalid after the replacement
+                let removed_str = str_to_remove.to_string();
+                // must convert to strings since due to encoding support we perform replacements as strings
+                let replacement = std::str::from_utf8(&replacement)?;
+                // performance replacement
+                file_as_str.replace_range(normalised_range, replacement);
+                replaced_count += 1;
+
+                log::debug!(
+                    "Replacement - reported line: {:?}, removed: \"{}\", added: \"{}\"",
+                    item.line_number(),
+                    removed_str,
+                    replacement
+                );
+            } else {
+                log::warn!("Matched bytes do not match bytes to replace!");
+                log::warn!("\tFile: \"{}\"", path_buf.display());
+                log::warn!("\tMatch: data=\"{}\", bytes={:?}", text, matched_bytes);
+                log::warn!(
+                    "\tBytes: data=\"{}\", bytes={:?}",
+                    str_to_remove,
+                    str_to_remove.as_bytes()
+                );
+                log::warn!("\tOffset: {}", offset + range.start);
+                skipped.push(SkippedMatch::new(item, sub_item, SkipReason::BytesMismatch));
+            }
+        }
+    }
+    skipped.reverse();
+
+    Ok(ReplacedFile {
+        path_data: path_data.clone(),
+        path_buf,
+        bom,
+        encoder,
+        original,
+        replaced: file_as_str,
+        replaced_count,
+        skipped,
+    })
+}
+
+fn perform_replacements_in_file(
+    criteria: &ReplacementCriteria,
+    counts: &MatchCounts,
+    rg_encoding: &RgEncoding,
+    meta: (&ArbitraryData, Vec<&Item>),
+    journal: Option<&Journal>,
+) -> Result<FileOutcome> {
+    let replaced_file = perform_replacements_in_memory(criteria, counts, rg_encoding, meta)?;
+    let replaced_contents = replaced_file.encode()?;
+    if let Some(journal) = journal {
+        journal.record(&replaced_file.path_data, hash(&replaced_contents))?;
+    }
+    let staged = replaced_file.stage(&replaced_contents)?;
+
+    FileOutcome::from(replaced_file).with_staged(criteria, staged)
+}
+
+/// Performs the replacements in memory, and returns a unified diff of them instead of writing them.
+fn perform_dry_run_in_file(
+    criteria: &ReplacementCriteria,
+    counts: &MatchCounts,
+    rg_encoding: &RgEncoding,
+    meta: (&ArbitraryData, Vec<&Item>),
+) -> Result<FileOutcome> {
+    let replaced_file = perform_replacements_in_memory(criteria, counts, rg_encoding, meta)?;
+    let mut diff = vec![];
+    replaced_file.write_diff(&mut diff)?;
+
+    Ok(FileOutcome {
+        diff,
+        ..FileOutcome::from(replaced_file)
+    })
+}
+
+/// A file with matches, whether it changed since it was searched, and either the items to replace in
+/// it or its report if it won't be replaced (e.g., because it was skipped).
+type CheckedFile<'a> = (
+    &'a ArbitraryData,
+    bool,
+    std::result::Result<Vec<&'a Item>, FileReport>,
+);
+
+/// Returns the files that have matches, in the order that ripgrep reported them. Files that have
+/// changed since they were searched are handled according to `criteria.on_stale`, and the new items
+/// of any that were searched again are kept in `rerun_items`.
+fn stale_checked_files<'a>(
+    criteria: &'a ReplacementCriteria,
+    rerun_items: &'a mut Vec<Vec<Item>>,
+) -> Result<Vec<CheckedFile<'a>>> {
+    // Group items by their file so we only open each file once.
+    let mut files = criteria.as_map().into_iter().collect::<Vec<_>>();
+    files.sort_unstable_by_key(|(_, items)| items[0].index);
+
+    let mut on_stale = criteria.on_stale;
+
+    // each file with its items, or the index of its new items in `rerun_items` if it was searched
+    // again, or its report if it won't be replaced
+    let mut checked = vec![];
+    for (path_data, items) in files {
+        // Check whether the file has changed since it was searched before touching it.
+        let is_stale = criteria
+            .fingerprints
+            .get(path_data)
+            .is_some_and(|fingerprint| {
+                path_data
+                    .to_path_buf()
+                    .map_or(true, |path_buf| fingerprint.is_stale(&path_buf))
+            });
+        let action = match on_stale {
+            _ if !is_stale => OnStale::Force,
+            OnStale::Ask => stale::ask(path_data, &mut on_stale)?,
+            action => action,
+        };
+        if is_stale {
+            log::warn!("File has changed since it was searched: {}", path_data);
+        }
+
+        let mut report = FileReport::new(path_data);
+        report.stale = is_stale;
+        match (action, &criteria.rerun_args) {
+            (OnStale::Force, _) | (OnStale::Ask, _) => {
+                checked.push((path_data, is_stale, Ok((items, None))))
+            }
+            (OnStale::Skip, _) => {
+                eprintln!(
+                    "Skipped {}, it has changed since it was searched",
+                    path_data
+                );
+                checked.push((path_data, is_stale, Err(report)));
+            }
+            (OnStale::Rerun, None) => {
+                eprintln!(
+                    "Skipped {}, it has changed since it was searched and can't be searched again without a pattern",
+                    path_data
+                );
+                checked.push((path_data, is_stale, Err(report)));
+            }
+            (OnStale::Rerun, Some(rerun_args)) => match stale::rerun(rerun_args, path_data) {
+                Ok(mut new_items) => {
+                    let unseen = stale::reapply_selection(&items, &mut new_items);
+                    if unseen > 0 {
+                        eprintln!(
+                            "{} new match(es) in {} weren't replaced, since they weren't selected",
+                            unseen, path_data
+                        );
+                    }
+                    checked.push((path_data, is_stale, Ok((vec![], Some(rerun_items.len())))));
+                    rerun_items.push(new_items);
+                }
+                Err(e) => {
+                    log::warn!("Failed to search {} again: {}", path_data, e);
+                    eprintln!("Failed to search {} again: {}", path_data, e);
+                    let error = format!("Failed to search again: {}", e);
+                    checked.push((path_data, is_stale, Err(report.with_error(error))));
+                }
+            },
+        }
+    }
+
+    let rerun_items = &*rerun_items;
+    Ok(checked
+        .into_iter()
+        .map(|(path_data, is_stale, items)| {
+            let items = items.map(|(items, rerun_index)| match rerun_index {
+                Some(i) => rerun_items[i]
+                    .iter()
+                    .filter(|item| matches!(item.kind, RgMessageKind::Match))
+                    .collect(),
+                None => items,
+            });
+            (path_data, is_stale, items)
+        })
+        .collect())
+}
+
+/// Runs `work` over each file that has matches in parallel (see `pool::run_in_order`), and then
+/// `finish` over each of the results in the order that ripgrep reported the files. Returns the
+/// report of every file, see `conclude`.
+///
+/// If `streaming` is true, then `work` is told which files should be streamed rather than replaced
+/// in memory: those at least as large as `criteria.streaming_threshold` that `stream::can_stream`.
+fn for_each_file<W, F>(
+    criteria: &ReplacementCriteria,
+    streaming: bool,
+    work: W,
+    mut finish: F,
+) -> Result<Report>
+where
+    W: Fn(&MatchCounts, &RgEncoding, (&ArbitraryData, Vec<&Item>), bool) -> Result<FileOutcome>
+        + Sync,
+    F: FnMut(&mut FileOutcome) -> Result<()>,
+{
+    log::debug!(
+        "Replacement text: \"{}\"",
+        String::from_utf8_lossy(&criteria.user_replacement)
+    );
+
+    let rg_encoding = RgEncoding::from(&criteria.encoding);
+    log::debug!("User passed encoding: {:?}", rg_encoding);
+
+    let counts = criteria.match_counts();
+
+    let mut report = Report::new(criteria.dry_run);
+
+    let mut rerun_items = vec![];
+    let files = stale_checked_files(criteria, &mut rerun_items)?;
+
+    // the cost of each file is its size, since that's roughly how much memory replacing it takes,
+    // unless it's streamed (or not replaced at all)
+    let jobs = files
+        .into_iter()
+        .map(|(path_data, is_stale, items)| {
+            if items.is_err() {
+                return ((path_data, is_stale, items, false), 0);
+            }
+
+            let path_buf = path_data.to_path_buf().ok();
+            let size = path_buf
+                .as_ref()
+                .and_then(|path_buf| fs::metadata(path_buf).ok())
+                .map_or(0, |metadata| metadata.len());
+            let stream = streaming
+                && size >= criteria.streaming_threshold
+                && path_buf.is_some_and(|path_buf| {
+                    stream::can_stream(&path_buf, &rg_encoding).unwrap_or(false)
+                });
+
+            let cost = if stream {
+                log::debug!("Streaming {} ({} bytes)", path_data, size);
+                size.min(stream::STREAMING_COST)
+            } else {
+                size
+            };
+            ((path_data, is_stale, items, stream), cost)
+        })
+        .collect();
+
+    log::debug!(
+        "Replacing with {} thread(s), and at most {} bytes in flight",
+        criteria.threads,
+        criteria.max_bytes_in_flight
+    );
+    pool::run_in_order(
+        jobs,
+        criteria.threads,
+        criteria.max_bytes_in_flight,
+        |(path_data, is_stale, items, stream)| {
+            let items = match items {
+                Ok(items) => items,
+                Err(file_report) => return (path_data, Ok(FileOutcome::from(file_report))),
+            };
+            let outcome =
+                work(&counts, &rg_encoding, (path_data, items), stream).map(|mut outcome| {
+                    outcome.report.stale = is_stale;
+                    outcome
+                });
+            (path_data, outcome)
+        },
+        |(path_data, result)| match result.and_then(|mut outcome| {
+            finish(&mut outcome)?;
+            Ok(outcome)
+        }) {
+            Ok(outcome) => report.add(outcome.report),
+            Err(e) => {
+                log::warn!("Failed to make all replacements: {}", e);
+                eprintln!("Failed to make all replacements: {}", e);
+                report.add(FileReport::new(path_data).with_error(format!("{:#}", e)));
+            }
+        },
+    );
+
+    Ok(report)
+}
+
+/// Writes the report to `criteria.report` (if it's set), and returns the summary of the
+/// replacements or an error if any of them failed.
+fn conclude(criteria: &ReplacementCriteria, report: Report) -> Result<ReplacementSummary> {
+    if let Some(output) = &criteria.report {
+        report.write(output)?;
+    }
+
+    if report.has_failures() {
+        log::warn!("Failed to perform all replacements");
+        Err(anyhow!("Failed to perform all replacements, see log"))
+    } else {
+        Ok(report.summary)
+    }
+}
+
+/// Makes the journal replace the previous session's, once a file has been replaced. The files have
+/// already been written by then, so a failure is only reported.
+fn commit_journal(journal: &Journal) {
+    if let Err(e) = journal.commit() {
+        log::warn!("Failed to commit the journal: {:#}", e);
+        eprintln!(
+            "Failed to save the journal, so this can't be undone: {:#}",
+            e
+        );
+    }
+}
+
+pub fn perform_replacements(criteria: ReplacementCriteria) -> Result<ReplacementSummary> {
+    log::trace!("--- PERFORM REPLACEMENTS ---");
+    let journal = match &criteria.journal_dir {
+        Some(dir) => Some(Journal::begin(dir)?),
+        None => None,
+    };
+
+    let mut staged = vec![];
+    let mut report = for_each_file(
+        &criteria,
+        true,
+        |counts, rg_encoding, meta, stream| {
+            if stream {
+                stream::stream_replacements_in_file(&criteria, counts, meta)
+            } else {
+                perform_replacements_in_file(&criteria, counts, rg_encoding, meta, journal.as_ref())
+            }
+        },
+        |outcome| {
+            match (&journal, outcome.staged.take()) {
+                (_, Some(staged_file)) => staged.push(staged_file),
+                (Some(journal), None) if outcome.report.replaced > 0 => commit_journal(journal),
+                _ => {}
+            }
+            Ok(())
+        },
+    )?;
+
+    // in a transaction, the files are only replaced once every one of them is ready
+    if criteria.transaction {
+        if report.has_failures() {
+            log::warn!("Not replacing any files, since not all of them could be prepared");
+            eprintln!("Not replacing any files, since not all of them could be prepared");
+            report.roll_back("Not replaced, since not every file could be");
+        } else if let Err(e) = transaction::commit(staged) {
+            log::warn!("Failed to commit the replacements: {:#}", e);
+            eprintln!("Failed to commit the replacements: {:#}", e);
+            report.roll_back(format!("Rolled back: {:#}", e));
+        }
+    }
+    if let (Some(journal), true) = (&journal, report.summary.files > 0) {
+        commit_journal(journal);
+    }
+
+    conclude(&criteria, report)
+}
+
+/// Performs all replacements in memory, and writes a unified diff of the changes to `out` instead
+/// of writing them to disk.
+pub fn perform_dry_run(
+    criteria: ReplacementCriteria,
+    mut out: impl Write,
+) -> Result<ReplacementSummary> {
+    log::trace!("--- PERFORM DRY RUN ---");
+    // NOTE: files are never streamed here, since the diff is made from their whole contents
+    let report = for_each_file(
+        &criteria,
+        false,
+        |counts, rg_encoding, meta, _| {
+            perform_dry_run_in_file(&criteria, counts, rg_encoding, meta)
+        },
+        |outcome| Ok(out.write_all(&outcome.diff)?),
+    )?;
+
+    out.flush()?;
+    conclude(&criteria, report)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, OpenOptions};
+    use std::io::Read;
+    use std::path::{Path, PathBuf};
+
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    use base64_simd::STANDARD as base64;
+    use pretty_assertions::assert_eq;
+    use regex::bytes::Regex;
+
+    use crate::model::*;
+    use crate::replace::report::ReportOutput;
+    use crate::replace::{diff_paths, perform_dry_run, perform_replacements, ReplacementSummary};
+    use crate::rg::de::test_utilities::RgMessageBuilder;
+    use crate::rg::de::{Duration, RgMessageKind, Stats, SubMatch};
+    use crate::ui::line::*;
+
+    macro_rules! temp_item {
+        ($offset:expr, $lines:expr, $submatches:expr) => {{
+            let p = temp_file!($lines);
+            let item = Item::new(
+                0,
+                RgMessageBuilder::new(RgMessageKind::Match)
+                    .with_path_text(p.to_string_lossy().to_string())
+                    .with_lines_text($lines.to_string())
+                    .with_submatches($submatches)
+                    .with_offset($offset)
+                    .build(),
+            );
+
+            (item, p)
+        }};
+    }
+
+    // NOTE: due to permission issues on Windows platforms, we need to first "keep" the temporary files otherwise
+    // we cannot atomically replace them. See https://github.com/Stebalien/tempfile/issues/131
+    #[macro_export]
+    macro_rules! temp_file {
+        (bytes, $content:expr) => {{
+            let mut file = tempfile::NamedTempFile::new().unwrap();
+            use std::io::Write;
+            file.write_all($content).unwrap();
+            // NOTE: we *must* drop the file here, otherwise Windows will fail with permissions errors
+            let (_, p) = file.keep().unwrap();
+            p
+        }};
+        ($content:expr) => {
+            temp_file!(bytes, $content.as_bytes())
+        };
+    }
+
+    macro_rules! re {
+        () => {
+            None
+        };
+        ($re:expr) => {
+            Some(Regex::new($re).unwrap().into())
+        };
+    }
+
+    #[test]
+    fn asdf() {
+        let file_text = "foo bar baz";
+        let s = |a, b| SubMatch::new_text(a, b);
+        let test_cases = vec![
+            // no capture groups
+            (s("foo", 0..3), r"foo", re!(), r"foo bar baz"),
+            (s("foo", 0..3), r"bar", re!(), r"bar bar baz"),
+            (s("foo", 0..3), r"A", re!(), r"A bar baz"),
+            (s("foo", 0..3), r"Rust", re!(), r"Rust bar baz"),
+            (s("foo", 0..3), r"🦀", re!(), r"🦀 bar baz"),
+            (s("foo", 0..3), r"¯\_(ツ)_/¯", re!(), r"¯\_(ツ)_/¯ bar baz"),
+            // capture groups
+            (s("foo", 0..3), r"$1", re!("(foo)"), r"foo bar baz"),
+            (s("foo", 0..3), r"$1 $1", re!("(foo)"), r"foo foo bar baz"),
+            (s("foo", 0..3), r"bar$1", re!("(f)oo"), r"barf bar baz"),
+            (s("foo", 0..3), r"$2🦀$2", re!("(f)(o+)"), r"oo🦀oo bar baz"),
+            (
+                s("foo bar baz", 0..11),
+                r"¯\_(ツ)_/¯$3$4$1 ${2}f",
+                re!("(f)((o)(o)).*"),
+                r"¯\_(ツ)_/¯oof oof",
+            ),
+        ];
+
+        for (submatch, replacement, capture_pattern, expected) in test_cases {
+            let (item1, p1) = temp_item!(0, file_text, vec![submatch]);
+            let criteria = ReplacementCriteria::new(capture_pattern, replacement, vec![item1]);
+            perform_replacements(criteria).unwrap();
+            assert_eq!(fs::read_to_string(p1).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn it_never_expands_captures_in_literal_mode() {
+        let file_text = "foo bar baz";
+        let s = |a, b| SubMatch::new_text(a, b);
+        let test_cases = vec![
+            (s("foo", 0..3), r"$1", re!(), r"$1 bar baz"),
+            (s("foo", 0..3), r"$1", re!("(foo)"), r"$1 bar baz"),
+            (s("foo", 0..3), r"${1}$$", re!("(f)oo"), r"${1}$$ bar baz"),
+            (
+                s("foo", 0..3),
+                r"$name",
+                re!("(?P<name>foo)"),
+                r"$name bar baz",
+            ),
+        ];
+
+        for (submatch, replacement, capture_pattern, expected) in test_cases {
+            let (item1, p1) = temp_item!(0, file_text, vec![submatch]);
+            let mut criteria = ReplacementCriteria::new(capture_pattern, replacement, vec![item1]);
+            criteria.literal = true;
+            perform_replacements(criteria).unwrap();
+            assert_eq!(fs::read_to_string(p1).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn it_resolves_captures_against_the_line_if_the_match_alone_doesnt_match() {
+        let file_text = "foo(bar) baz";
+        let (item, p) = temp_item!(0, file_text, vec![SubMatch::new_text("baz", 9..12)]);
+        let criteria = ReplacementCriteria::new(re!(r"(\w+)\((\w+)\)"), "$2-$1", vec![item]);
+        perform_replacements(criteria).unwrap();
+        assert_eq!(fs::read_to_string(p).unwrap(), "foo(bar) bar-foo");
+    }
+
+    #[test]
+    fn it_performs_replacements_only_on_match_items() {
+        let text = "foo bar baz";
+        let build_item = |kind, p: &PathBuf| {
+            Item::new(
+                0,
+                RgMessageBuilder::new(kind)
+                    .with_path_text(p.to_string_lossy())
+                    .with_lines_text(text)
+                    .with_submatches(vec![SubMatch::new_text("foo", 0..3)])
+                    .with_stats(Stats::new())
+                    .with_elapsed_total(Duration::new())
+                    .with_offset(0)
+                    .build(),
+            )
+        };
+
+        let p1 = temp_file!(text);
+        let p2 = temp_file!(text);
+        let p3 = temp_file!(text);
+        let p4 = temp_file!(text);
+        let p5 = temp_file!(text);
+
+        let items = vec![
+            build_item(RgMessageKind::Begin, &p1),
+            build_item(RgMessageKind::Context, &p2),
+            build_item(RgMessageKind::Match, &p3),
+            build_item(RgMessageKind::End, &p4),
+            build_item(RgMessageKind::Summary, &p5),
+        ];
+
+        perform_replacements(ReplacementCriteria::new(None, "NEW_VALUE", items)).unwrap();
+        assert_eq!(fs::read_to_string(p1).unwrap(), text);
+        assert_eq!(fs::read_to_string(p2).unwrap(), text);
+        assert_eq!(fs::read_to_string(p3).unwrap(), "NEW_VALUE bar baz");
+        assert_eq!(fs::read_to_string(p4).unwrap(), text);
+        assert_eq!(fs::read_to_string(p5).unwrap(), text);
+    }
+
+    #[test]
+    fn it_writes_a_report_of_the_replacements() {
+        let (item1, p1) = temp_item!(0, "foo bar baz", vec![SubMatch::new_text("foo", 0..3)]);
+        // this is really "bar"
+        let (mut item2, p2) = temp_item!(
+            0,
+            "foo bar foo",
+            vec![
+                SubMatch::new_text("foo", 0..3),
+                SubMatch::new_text("foo", 4..7)
+            ]
+        );
+        item2.index = 1;
+        let report_dir = tempfile::tempdir().unwrap();
+        let report_path = report_dir.path().join("report.json");
+
+        let mut criteria = ReplacementCriteria::new(None, "qux", vec![item1, item2]);
+        criteria.report = Some(ReportOutput::File(report_path.clone()));
+        assert!(perform_replacements(criteria).is_err());
+
+        let report: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&report_path).unwrap()).unwrap();
+        assert_eq!(
+            report,
+            serde_json::json!({
+                "dry_run": false,
+                "files": [
+                    {
+                        "path": { "text": p1.to_string_lossy() },
+                        "replaced": 1,
+                        "skipped": [],
+                        "bom": null,
+                        "encoder": "utf-8",
+                        "streamed": false,
+                        "stale": false,
+                        "error": null,
+                    },
+                    {
+                        "path": { "text": p2.to_string_lossy() },
+                        "replaced": 1,
+                        "skipped": [
+                            {
+                                "line_number": null,
+                                "offset": 4,
+                                "text": { "text": "foo" },
+                                "reason": "bytes-mismatch",
+                            },
+                        ],
+                        "bom": null,
+                        "encoder": "utf-8",
+                        "streamed": false,
+                        "stale": false,
+                        "error": null,
+                    },
+                ],
+                "summary": { "files": 2, "matches": 2 },
+            })
+        );
+    }
+
+    #[test]
+    fn it_replaces_no_files_in_a_failed_transaction() {
+        let (item1, p1) = temp_item!(0, "foo bar baz", vec![SubMatch::new_text("foo", 0..3)]);
+        let (mut item2, p2) = temp_item!(0, "baz foo bar", vec![SubMatch::new_text("foo", 4..7)]);
+        item2.index = 1;
+        // this file can't be replaced, since it no longer exists
+        let (mut item3, p3) = temp_item!(0, "bar baz foo", vec![SubMatch::new_text("foo", 8..11)]);
+        item3.index = 2;
+        fs::remove_file(&p3).unwrap();
+
+        let mut criteria = ReplacementCriteria::new(None, "NEW_VALUE", vec![item1, item2, item3]);
+        criteria.transaction = true;
+        assert!(perform_replacements(criteria).is_err());
+        assert_eq!(fs::read_to_string(&p1).unwrap(), "foo bar baz");
+        assert_eq!(fs::read_to_string(&p2).unwrap(), "baz foo bar");
+
+        // nothing is left behind beside the files
+        let dir = p1.parent().unwrap();
+        let leftovers = fs::read_dir(dir)
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| {
+                entry
+                    .file_name()
+                    .to_string_lossy()
+                    .starts_with(".rgr-backup")
+            })
+            .count();
+        assert_eq!(leftovers, 0);
+    }
+
+    #[test]
+    fn it_replaces_every_file_in_a_transaction() {
+        let (item1, p1) = temp_item!(0, "foo bar baz", vec![SubMatch::new_text("foo", 0..3)]);
+        let (mut item2, p2) = temp_item!(0, "baz foo bar", vec![SubMatch::new_text("foo", 4..7)]);
+        item2.index = 1;
+
+        let mut criteria = ReplacementCriteria::new(None, "NEW_VALUE", vec![item1, item2]);
+        criteria.transaction = true;
+        assert_eq!(
+            perform_replacements(criteria).unwrap(),
+            ReplacementSummary {
+                files: 2,
+                matches: 2
+            }
+        );
+        assert_eq!(fs::read_to_string(&p1).unwrap(), "NEW_VALUE bar baz");
+        assert_eq!(fs::read_to_string(&p2).unwrap(), "baz NEW_VALUE bar");
+    }
+
+    #[test]
+    #[cfg(not(target_os = "windows"))]
+    fn it_keeps_backups_of_the_originals() {
+        use std::os::unix::fs::PermissionsExt;
+        use std::time::{Duration, SystemTime};
+
+        use super::backup::{Backups, DEFAULT_SUFFIX};
+
+        let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        for transaction in [false, true] {
+            let (item, path) = temp_item!(0, "foo bar baz", vec![SubMatch::new_text("foo", 0..3)]);
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+            filetime::set_file_mtime(&path, filetime::FileTime::from_system_time(mtime)).unwrap();
+
+            let mut criteria = ReplacementCriteria::new(None, "NEW_VALUE", vec![item]);
+            criteria.transaction = transaction;
+            criteria.backups = Some(Backups {
+                suffix: DEFAULT_SUFFIX.to_string(),
+                dir: None,
+            });
+            perform_replacements(criteria).unwrap();
+            assert_eq!(fs::read_to_string(&path).unwrap(), "NEW_VALUE bar baz");
+
+            let mut backup_name = path.file_name().unwrap().to_os_string();
+            backup_name.push(".orig");
+            let backup_path = path.with_file_name(backup_name);
+            let metadata = fs::metadata(&backup_path).unwrap();
+            assert_eq!(fs::read_to_string(&backup_path).unwrap(), "foo bar baz");
+            assert_eq!(metadata.permissions().mode(), 0o100640);
+            assert_eq!(metadata.modified().unwrap(), mtime);
+            fs::remove_file(&backup_path).unwrap();
+        }
+    }
+
+    #[test]
+    #[cfg(not(target_os = "windows"))]
+    fn it_performs_replacements_and_keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+        // create temporary item
+        let (item, path) = temp_item!(0, "foo bar baz", vec![SubMatch::new_text("foo", 0..3)]);
+
+        // get permissions of file
+        let perms = || fs::metadata(&path).unwrap().permissions();
+
+        // set permissions to 777
+        let mut p = perms();
+        p.set_mode(0o777);
+        fs::set_permissions(&path, p).unwrap();
+
+        // sanity check perms were set
+        assert_eq!(perms().mode(), 0o100777);
+
+        // perform replacement
+        perform_replacements(ReplacementCriteria::new(None, "NEW_VALUE", vec![item])).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "NEW_VALUE bar baz");
+
+        // now check permissions are what we expect
+        assert_eq!(perms().mode(), 0o100777);
+    }
+
+    #[test]
+    fn it_performs_replacements_in_separate_files() {
+        let (item1, p1) = temp_item!(0, "foo bar baz", vec![SubMatch::new_text("foo", 0..3)]);
+        let (item2, p2) = temp_item!(0, "baz foo bar", vec![SubMatch::new_text("foo", 4..7)]);
+        let (item3, p3) = temp_item!(0, "bar baz foo", vec![SubMatch::new_text("foo", 8..11)]);
+
+        let items = vec![item1, item2, item3];
+        perform_replacements(ReplacementCriteria::new(None, "NEW_VALUE", items)).unwrap();
+        assert_eq!(fs::read_to_string(p1).unwrap(), "NEW_VALUE bar baz");
+        assert_eq!(fs::read_to_string(p2).unwrap(), "baz NEW_VALUE bar");
+        assert_eq!(fs::read_to_string(p3).unwrap(), "bar baz NEW_VALUE");
+    }
+
+    #[test]
+    fn it_replaces_many_files_in_parallel_in_order() {
+        let paths = (0..20)
+            .map(|i| temp_file!(format!("foo {}\n", i)))
+            .collect::<Vec<_>>();
+        let criteria = || {
+            let items = paths
+                .iter()
+                .enumerate()
+                .map(|(i, p)| {
+                    Item::new(
+                        i,
+                        RgMessageBuilder::new(RgMessageKind::Match)
+                            .with_path_text(p.to_string_lossy())
+                            .with_lines_text(format!("foo {}\n", i))
+                            .with_submatches(vec![SubMatch::new_text("foo", 0..3)])
+                            .with_offset(0)
+                            .build(),
+                    )
+                })
+                .collect();
+            let mut criteria = ReplacementCriteria::new(None, "bar", items);
+            criteria.threads = 4;
+            criteria.max_bytes_in_flight = 16;
+            criteria
+        };
+
+        // the diff is written in the same order as the files were given
+        let mut out = vec![];
+        perform_dry_run(criteria(), &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let added = out
+            .lines()
+            .filter(|line| line.starts_with("+bar"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            added,
+            (0..20).map(|i| format!("+bar {}", i)).collect::<Vec<_>>()
+        );
+
+        assert_eq!(
+            perform_replacements(criteria()).unwrap(),
+            ReplacementSummary {
+                files: 20,
+                matches: 20
+            }
+        );
+        for (i, p) in paths.iter().enumerate() {
+            assert_eq!(fs::read_to_string(p).unwrap(), format!("bar {}\n", i));
+        }
+    }
+
+    #[test]
+    fn it_does_not_replace_deselected_matches() {
+        let (item1, p1) = temp_item!(0, "foo bar baz", vec![SubMatch::new_text("foo", 0..3)]);
+        let (item2, p2) = temp_item!(0, "baz foo bar", vec![SubMatch::new_text("foo", 4..7)]);
+        let (item3, p3) = temp_item!(0, "bar baz foo", vec![SubMatch::new_text("foo", 8..11)]);
+
+        let mut items = vec![item1, item2, item3];
+
+        items[0].set_should_replace(0, false);
+        items[1].set_should_replace(0, true);
+        items[2].set_should_replace(0, false);
+
+        perform_replacements(ReplacementCriteria::new(None, "NEW_VALUE", items)).unwrap();
+        assert_eq!(fs::read_to_string(p1).unwrap(), "foo bar baz");
+        assert_eq!(fs::read_to_string(p2).unwrap(), "baz NEW_VALUE bar");
+        assert_eq!(fs::read_to_string(p3).unwrap(), "bar baz foo");
+    }
+
+    #[test]
+    fn it_prints_a_diff_and_leaves_files_untouched_on_dry_run() {
+        let (item1, p1) = temp_item!(0, "foo bar baz\n", vec![SubMatch::new_text("foo", 0..3)]);
+        let (item2, p2) = temp_item!(0, "baz foo bar\n", vec![SubMatch::new_text("foo", 4..7)]);
+
+        let mut items = vec![item1, item2];
+        items[1].set_should_replace(0, false);
+
+        let mut out = vec![];
+        let mut criteria = ReplacementCriteria::new(None, "NEW_VALUE", items);
+        criteria.dry_run = true;
+        perform_dry_run(criteria, &mut out).unwrap();
+
+        let p1_display = p1.display();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!(
+                "--- {p1_display}\n+++ {p1_display}\n@@ -1 +1 @@\n-foo bar baz\n+NEW_VALUE bar baz\n"
+            )
+        );
+        assert_eq!(fs::read_to_string(p1).unwrap(), "foo bar baz\n");
+        assert_eq!(fs::read_to_string(p2).unwrap(), "baz foo bar\n");
+    }
+
+    #[test]
+    fn it_uses_git_style_paths_in_diff_headers() {
+        assert_eq!(
+            diff_paths(Path::new("./src/main.rs")),
+            ("a/src/main.rs".into(), "b/src/main.rs".into())
+        );
+        assert_eq!(
+            diff_paths(Path::new("src/main.rs")),
+            ("a/src/main.rs".into(), "b/src/main.rs".into())
+        );
+        assert_eq!(
+            diff_paths(Path::new("/tmp/main.rs")),
+            ("/tmp/main.rs".into(), "/tmp/main.rs".into())
+        );
+    }
+
+    #[test]
+    fn it_performs_multiple_replacements_one_file() {
+        let (item, p) = temp_item!(
+            0,
+            "foo bar baz",
+            vec![
+                SubMatch::new_text("foo", 0..3),
+                SubMatch::new_text("bar", 4..7),
+                SubMatch::new_text("baz", 8..11),
+            ]
+        );
+
+        perform_replacements(ReplacementCriteria::new(None, "NEW_VALUE", vec![item])).unwrap();
+        assert_eq!(
+            fs::read_to_string(p).unwrap(),
+            "NEW_VALUE NEW_VALUE NEW_VALUE"
+        );
+    }
+
+    #[test]
+    fn it_uses_the_replacement_for_each_match_if_it_has_one() {
+        let (mut item, p) = temp_item!(
+            0,
+            "foo bar baz",
+            vec![
+                SubMatch::new_text("foo", 0..3),
+                SubMatch::new_text("bar", 4..7),
+                SubMatch::new_text("baz", 8..11),
+            ]
+        );
+        item.set_replacement(1, Some("<$1>".into()));
+        item.set_replacement(2, Some("".into()));
+
+        let resolver = CaptureResolver::from(Regex::new(r"b(a\w)").unwrap());
+        let criteria = ReplacementCriteria::new(Some(resolver), "NEW_VALUE", vec![item]);
+        perform_replacements(criteria).unwrap();
+        assert_eq!(fs::read_to_string(p).unwrap(), "NEW_VALUE <ar> ");
+    }
+
+    #[test]
+    fn it_preserves_the_case_of_each_match() {
+        let (mut item, p) = temp_item!(
+            0,
+            "fooBar FOO_BAR foo_bar foo-bar FooBar",
+            vec![
+                SubMatch::new_text("fooBar", 0..6),
+                SubMatch::new_text("FOO_BAR", 7..14),
+                SubMatch::new_text("foo_bar", 15..22),
+                SubMatch::new_text("foo-bar", 23..30),
+                SubMatch::new_text("FooBar", 31..37),
+            ]
+        );
+        // matches with their own replacement are left as they are
+        item.set_replacement(4, Some("bazQux".into()));
+
+        let mut criteria = ReplacementCriteria::new(None, "baz_qux", vec![item]);
+        criteria.preserve_case = true;
+        perform_replacements(criteria).unwrap();
+        assert_eq!(
+            fs::read_to_string(p).unwrap(),
+            "bazQux BAZ_QUX baz_qux baz-qux bazQux"
+        );
+    }
+
+    #[test]
+    fn it_expands_templates_for_each_match() {
+        let build_item = |index, p: &PathBuf, line_number, offset, text: &str, submatches| {
+            Item::new(
+                index,
+                RgMessageBuilder::new(RgMessageKind::Match)
+                    .with_path_text(p.to_string_lossy())
+                    .with_lines_text(text)
+                    .with_line_number(line_number)
+                    .with_submatches(submatches)
+                    .with_offset(offset)
+                    .build(),
+            )
+        };
+
+        let p1 = temp_file!("foo foo\nfoo\n");
+        let p2 = temp_file!("bar foo\n");
+        let mut items = vec![
+            build_item(
+                0,
+                &p1,
+                1,
+                0,
+                "foo foo\n",
+                vec![
+                    SubMatch::new_text("foo", 0..3),
+                    SubMatch::new_text("foo", 4..7),
+                ],
+            ),
+            build_item(1, &p1, 2, 8, "foo\n", vec![SubMatch::new_text("foo", 0..3)]),
+            build_item(
+                2,
+                &p2,
+                1,
+                0,
+                "bar foo\n",
+                vec![SubMatch::new_text("foo", 4..7)],
+            ),
+        ];
+        // deselected matches aren't counted
+        items[0].set_should_replace(1, false);
+
+        let resolver = CaptureResolver::from(Regex::new(r"f(o+)").unwrap());
+        let criteria =
+            ReplacementCriteria::new(Some(resolver), "${1:upper}${n:02}.${file_n}L${line}", items);
+        perform_replacements(criteria).unwrap();
+        assert_eq!(fs::read_to_string(p1).unwrap(), "OO01.1L1 foo\nOO02.2L2\n");
+        assert_eq!(fs::read_to_string(p2).unwrap(), "bar OO03.1L1\n");
+    }
+
+    #[test]
+    fn it_replaces_every_match_from_rg_messages() {
+        let p1 = temp_file!("foo bar foo");
+        let p2 = temp_file!("bar");
+        let p3 = temp_file!("foo");
+        let message = |kind, p: &PathBuf, text: &str, submatches| {
+            RgMessageBuilder::new(kind)
+                .with_path_text(p.to_string_lossy())
+                .with_lines_text(text)
+                .with_submatches(submatches)
+                .with_stats(Stats::new())
+                .with_elapsed_total(Duration::new())
+                .with_offset(0)
+                .build()
+        };
+
+        let criteria = ReplacementCriteria::from_rg_messages(
+            None,
+            "NEW_VALUE",
+            vec![
+                message(
+                    RgMessageKind::Match,
+                    &p1,
+                    "foo bar foo",
+                    vec![
+                        SubMatch::new_text("foo", 0..3),
+                        SubMatch::new_text("foo", 8..11),
+                    ],
+                ),
+                message(RgMessageKind::Context, &p2, "bar", vec![]),
+                message(
+                    RgMessageKind::Match,
+                    &p3,
+                    "foo",
+                    vec![SubMatch::new_text("foo", 0..3)],
+                ),
+                message(RgMessageKind::Summary, &p3, "", vec![]),
+            ],
+        );
+        assert_eq!(
+            perform_replacements(criteria).unwrap(),
+            ReplacementSummary {
+                files: 2,
+                matches: 3
+            }
+        );
+        assert_eq!(fs::read_to_string(p1).unwrap(), "NEW_VALUE bar NEW_VALUE");
+        assert_eq!(fs::read_to_string(p2).unwrap(), "bar");
+        assert_eq!(fs::read_to_string(p3).unwrap(), "NEW_VALUE");
+    }
+
+    #[test]
+    fn it_expands_captures_with_the_pattern_that_matched() {
+        let (item, p) = temp_item!(
+            0,
+            "foo123 bar_qux baz",
+            vec![
+                SubMatch::new_text("foo123", 0..6),
+                SubMatch::new_text("bar_qux", 7..14),
+                SubMatch::new_text("baz", 15..18),
+            ]
+        );
+
+        let resolver = CaptureResolver::new(&[r"foo(\d+)", r"bar_(\w+)", r"baz"]).unwrap();
+        let criteria = ReplacementCriteria::new(Some(resolver), "<$1>", vec![item]);
+        perform_replacements(criteria).unwrap();
+        assert_eq!(fs::read_to_string(p).unwrap(), "<123> <qux> <>");
+    }
+
+    #[test]
+    fn it_performs_replacements_on_multiple_lines() {
+        let p = temp_file!("foo bar baz\n...\nbaz foo bar\n...\nbar baz foo");
+
+        let path_string = p.to_string_lossy();
+        let items = vec![
+            Item::new(
+                0,
+                RgMessageBuilder::new(RgMessageKind::Match)
+                    .with_path_text(&path_string)
+                    .with_submatches(vec![SubMatch::new_text("foo", 0..3)])
+                    .with_lines_text("foo bar baz\n")
+                    .with_offset(0)
+                    .build(),
+            ),
+            Item::new(
+                1,
+                RgMessageBuilder::new(RgMessageKind::Match)
+                    .with_path_text(&path_string)
+                    .with_submatches(vec![SubMatch::new_text("foo", 4..7)])
+                    .with_lines_text("baz foo bar\n")
+                    .with_offset(16)
+                    .build(),
+            ),
+            Item::new(
+                2,
+                RgMessageBuilder::new(RgMessageKind::Match)
+                    .with_path_text(&path_string)
+                    .with_submatches(vec![SubMatch::new_text("foo", 8..11)])
+                    .with_lines_text("bar baz foo")
+                    .with_offset(32)
+                    .build(),
+            ),
+        ];
+
+        perform_replacements(ReplacementCriteria::new(None, "NEW_VALUE", items)).unwrap();
+        assert_eq!(
+            fs::read_to_string(p).unwrap(),
+            "NEW_VALUE bar baz\n...\nbaz NEW_VALUE bar\n...\nbar baz NEW_VALUE"
+        );
+    }
+
+    #[test]
+    fn it_performs_replacements_on_multiline_matches() {
+        let p = temp_file!("foo bar baz\n...\nbaz 1\n22\n333 bar\n...\nbar 4444 foo");
+
+        let path_string = p.to_string_lossy();
+        let items = vec![
+            Item::new(
+                0,
+                RgMessageBuilder::new(RgMessageKind::Match)
+                    .with_path_text(&path_string)
+                    .with_submatches(vec![SubMatch::new_text("1\n22\n333", 4..12)])
+                    .with_lines_text("baz 1\n22\n333 bar\n")
+                    .with_offset(16)
+                    .build(),
+            ),
+            Item::new(
+                1,
+                RgMessageBuilder::new(RgMessageKind::Match)
+                    .with_path_text(&path_string)
+                    .with_submatches(vec![SubMatch::new_text("4444", 4..8)])
+                    .with_lines_text("bar 4444 foo")
+                    .with_offset(37)
+                    .build(),
+            ),
+        ];
+
+        perform_replacements(ReplacementCriteria::new(None, "NEW_VALUE", items)).unwrap();
+        assert_eq!(
+            fs::read_to_string(p).unwrap(),
+            "foo bar baz\n...\nbaz NEW_VALUE bar\n...\nbar NEW_VALUE foo"
+        );
+    }
+
+    // TODO: write a similar test for Windows/macOS systems
+    #[test]
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    fn it_performs_replacements_files_with_non_utf8_paths_unix() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+        use std::path::PathBuf;
+
+        use tempfile::tempdir;
+
+        // Here, the values 0x66 and 0x6f correspond to 'f' and 'o'
+        // respectively. The value 0x80 is a lone continuation byte, invalid
+        // in a UTF-8 sequence.
+        let invalid_file_name_bytes = [0x66, 0x6f, 0x80, 0x6f];
+        let invalid_file_name = OsStr::from_bytes(&invalid_file_name_bytes[..]);
+
+        let d = tempdir().unwrap();
+        let p = PathBuf::from(d.path()).join(invalid_file_name);
+        let lines = "hello earth";
+        fs::write(&p, lines.as_bytes()).unwrap();
+
+        let item = Item::new(
+            0,
+            RgMessageBuilder::new(RgMessageKind::Match)
+                .with_path_base64(base64.encode_to_string(p.as_os_str().as_bytes()))
+                .with_lines_text(lines)
+                .with_submatches(vec![SubMatch::new_text("o", 4..5)])
+                .with_offset(0)
+                .build(),
+        );
+
+        perform_replacements(ReplacementCriteria::new(None, " on", vec![item])).unwrap();
+        assert_eq!(fs::read_to_string(p).unwrap(), "hell on earth");
+    }
+
+    // Encodings
+
+    macro_rules! simple_test {
+        ($name:ident, $src:expr, $dst:expr, ($needle:expr, $replace:expr), $submatches:expr) => {
+            #[test]
+            fn $name() {
+                let src_bytes = hex::decode($src).unwrap();
+
+                let p = temp_file!(bytes, &src_bytes);
+
+                let items: Vec<Item> = $submatches
+                    .iter()
+                    .map(|(offset, range)| {
+                        Item::new(
+                            0,
+                            RgMessageBuilder::new(RgMessageKind::Match)
+                                .with_path_text(p.to_string_lossy())
+                                .with_lines_text(&format!("{}\n", $needle))
+                                .with_submatches(vec![SubMatch::new_text($needle, range.clone())])
+                                .with_offset(*offset)
+                                .build(),
+                        )
+                    })
+                    .collect();
+
+                perform_replacements(ReplacementCriteria::new(None, $replace, items)).unwrap();
+
+                // Read file bytes.
+                let mut file_bytes = vec![];
+                OpenOptions::new()
+                    .read(true)
+                    .open(p)
+                    .unwrap()
+                    .read_to_end(&mut file_bytes)
+                    .unwrap();
+
+                // Check real bytes are the same as expected bytes.
+                assert_eq!(file_bytes, hex::decode($dst).unwrap());
+            }
+        };
+    }
+
+    // The following are generated with:
+    //   printf "<BOM>%s" $(printf "foo bar baz\n...\nbaz foo bar\n...\nbar baz foo" | iconv -f UTF8 -t <ENCODING> | xxd -p -c 128)
+
+    const UTF8_FOO: &str =
+        "666f6f206261722062617a0a2e2e2e0a62617a20666f6f206261720a2e2e2e0a6261722062617a20666f6f";
+    const UTF8BOM_FOO: &str = "efbbbf666f6f206261722062617a0a2e2e2e0a62617a20666f6f206261720a2e2e2e0a6261722062617a20666f6f";
+    const UTF16BE_FOO: &str = "feff0066006f006f0020006200610072002000620061007a000a002e002e002e000a00620061007a00200066006f006f0020006200610072000a002e002e002e000a006200610072002000620061007a00200066006f006f";
+    const UTF16LE_FOO: &str = "fffe66006f006f0020006200610072002000620061007a000a002e002e002e000a00620061007a00200066006f006f0020006200610072000a002e002e002e000a006200610072002000620061007a00200066006f006f00";
+
+    // The following are generated with:
+    //   printf "<BOM>%s" $(printf "RUST bar baz\n...\nbaz RUST bar\n...\nbar baz RUST" | iconv -f UTF8 -t <ENCODING> | xxd -p -c 128)
+
+    const UTF8_RUST: &str = "52555354206261722062617a0a2e2e2e0a62617a2052555354206261720a2e2e2e0a6261722062617a2052555354";
+    const UTF8BOM_RUST: &str = "efbbbf52555354206261722062617a0a2e2e2e0a62617a2052555354206261720a2e2e2e0a6261722062617a2052555354";
+    const UTF16BE_RUST: &str = "feff00520055005300540020006200610072002000620061007a000a002e002e002e000a00620061007a002000520055005300540020006200610072000a002e002e002e000a006200610072002000620061007a00200052005500530054";
+    const UTF16LE_RUST: &str = "fffe520055005300540020006200610072002000620061007a000a002e002e002e000a00620061007a002000520055005300540020006200610072000a002e002e002e000a006200610072002000620061007a0020005200550053005400";
+
+    // The following are generated with:
+    //   printf "<BOM>%s" $(printf "A bar baz\n...\nbaz A bar\n...\nbar baz A" | iconv -f UTF8 -t <ENCODING> | xxd -p -c 128)
+
+    const UTF8_A: &str =
+        "41206261722062617a0a2e2e2e0a62617a2041206261720a2e2e2e0a6261722062617a2041";
+    const UTF8BOM_A: &str =
+        "efbbbf41206261722062617a0a2e2e2e0a62617a2041206261720a2e2e2e0a6261722062617a2041";
+    const UTF16BE_A: &str = "feff00410020006200610072002000620061007a000a002e002e002e000a00620061007a002000410020006200610072000a002e002e002e000a006200610072002000620061007a00200041";
+    const UTF16LE_A: &str = "fffe410020006200610072002000620061007a000a002e002e002e000a00620061007a002000410020006200610072000a002e002e002e000a006200610072002000620061007a0020004100";
+
+    // The following are generated with:
+    //   printf "<BOM>%s" $(printf "🦀 bar baz\n...\nbaz 🦀 bar\n...\nbar baz 🦀" | iconv -f UTF8 -t <ENCODING> | xxd -p -c 128)
+
+    const UTF8_EMOJI: &str = "f09fa680206261722062617a0a2e2e2e0a62617a20f09fa680206261720a2e2e2e0a6261722062617a20f09fa680";
+    const UTF8BOM_EMOJI: &str = "efbbbff09fa680206261722062617a0a2e2e2e0a62617a20f09fa680206261720a2e2e2e0a6261722062617a20f09fa680";
+    const UTF16BE_EMOJI: &str = "feffd83edd800020006200610072002000620061007a000a002e002e002e000a00620061007a0020d83edd800020006200610072000a002e002e002e000a006200610072002000620061007a0020d83edd80";
+    const UTF16LE_EMOJI: &str = "fffe3ed880dd20006200610072002000620061007a000a002e002e002e000a00620061007a0020003ed880dd20006200610072000a002e002e002e000a006200610072002000620061007a0020003ed880dd";
+
+    // The following are generated with:
+    //   printf "%s" $(printf "¯\_(ツ)_/¯ bar baz\n...\nbaz ¯\_(ツ)_/¯ bar\n...\nbar baz ¯\_(ツ)_/¯" | iconv -f UTF8 -t <ENCODING> | xxd -p -c 128)
+
+    const UTF8_UNICODE: &str = "c2af5c5f28e38384295f2fc2af206261722062617a0a2e2e2e0a62617a20c2af5c5f28e38384295f2fc2af206261720a2e2e2e0a6261722062617a20c2af5c5f28e38384295f2fc2af";
+    const UTF8BOM_UNICODE: &str = "efbbbfc2af5c5f28e38384295f2fc2af206261722062617a0a2e2e2e0a62617a20c2af5c5f28e38384295f2fc2af206261720a2e2e2e0a6261722062617a20c2af5c5f28e38384295f2fc2af";
+    const UTF16BE_UNICODE: &str = "feff00af005c005f002830c40029005f002f00af0020006200610072002000620061007a000a002e002e002e000a00620061007a002000af005c005f002830c40029005f002f00af0020006200610072000a002e002e002e000a006200610072002000620061007a002000af005c005f002830c40029005f002f00af";
+    const UTF16LE_UNICODE: &str = "fffeaf005c005f002800c43029005f002f00af0020006200610072002000620061007a000a002e002e002e000a00620061007a002000af005c005f002800c43029005f002f00af0020006200610072000a002e002e002e000a006200610072002000620061007a002000af005c005f002800c43029005f002f00af00";
+
+    macro_rules! simple_test_batch {
+        ($name:ident, $left:ident, $right:ident, $info:expr, $submatches:expr) => {
+            simple_test_batch!(@ [UTF8, UTF8BOM, UTF16BE, UTF16LE], $name, $left, $right, $info, $submatches);
+        };
+
+        (@ [$($enc:ident$(,)?)+], $name:ident, $left:ident, $right:ident, $info:expr, $submatches:expr) => {
+            paste::paste! {
+                $(
+                    simple_test!(
+                        [<multiline_ $name _ $enc:lower>],
+                        [<$enc _ $left:upper>],
+                        [<$enc _ $right:upper>],
+                        $info,
+                        // account for 3 byte BOM
+                        if stringify!($enc) == "UTF8BOM" {
+                            $submatches
+                                .into_iter()
+                                .map(|(a, b)| (a, std::ops::Range { start: b.start + 3, end: b.end + 3 }))
+                                .collect::<Vec<_>>()
+                        } else {
+                            $submatches
+                        }
+                    );
+                )+
+            }
+        };
+    }
+
+    simple_test_batch!(
+        to_longer,
+        FOO,
+        RUST,
+        ("foo", "RUST"),
+        vec![(0, 0..3), (16, 4..7), (32, 8..11)]
+    );
+
+    simple_test_batch!(
+        to_shorter,
+        FOO,
+        A,
+        ("foo", "A"),
+        vec![(0, 0..3), (16, 4..7), (32, 8..11)]
+    );
+
+    simple_test_batch!(
+        to_emoji,
+        FOO,
+        EMOJI,
+        ("foo", "🦀"),
+        vec![(0, 0..3), (16, 4..7), (32, 8..11)]
+    );
+
+    simple_test_batch!(
+        from_emoji,
+        EMOJI,
+        FOO,
+        ("🦀", "foo"),
+        vec![(0, 0..4), (16, 5..9), (32, 10..14)]
+    );
+
+    simple_test_batch!(
+        to_unicode,
+        FOO,
+        UNICODE,
+        ("foo", r"¯\_(ツ)_/¯"),
+        vec![(0, 0..3), (16, 4..7), (32, 8..11)]
+    );
+
+    simple_test_batch!(
+        from_unicode,
+        UNICODE,
+        FOO,
+        (r"¯\_(ツ)_/¯", "foo"),
+        vec![(0, 0..13), (16, 14..27), (32, 28..41)]
+    );
+}
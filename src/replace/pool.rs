@@ -0,0 +1,189 @@
+//! A small worker pool for rewriting files in parallel.
+//!
+//! Each job has a cost (the size of its file), and jobs are only started while the total cost of the
+//! jobs in flight stays under a limit, so many small files are rewritten at once but several large
+//! files are never loaded into memory together. A job larger than the limit is run on its own.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Condvar, Mutex};
+use std::thread;
+
+/// The default limit of the total size of the files being rewritten at once.
+pub const DEFAULT_MAX_BYTES_IN_FLIGHT: u64 = 256 * 1024 * 1024;
+
+/// Returns the default number of threads used to rewrite files.
+pub fn default_threads() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+struct State<J> {
+    /// The jobs that haven't been started yet, with their index and cost.
+    queue: VecDeque<(usize, J, u64)>,
+    /// The total cost of the jobs that have been started but not finished.
+    in_flight: u64,
+}
+
+/// Runs `work` on each job (with its cost) using up to `threads` threads, while the total cost of
+/// the jobs in flight is kept under `max_cost`. Each result is passed to `finish` on the calling
+/// thread in the same order as `jobs`, and a job's cost is only released once `finish` returns.
+///
+/// If `work` panics then no more jobs are started, and the panic is resumed on the calling thread
+/// once the jobs in flight have stopped.
+pub fn run_in_order<J, R, W, F>(
+    jobs: Vec<(J, u64)>,
+    threads: usize,
+    max_cost: u64,
+    work: W,
+    mut finish: F,
+) where
+    J: Send,
+    R: Send,
+    W: Fn(J) -> R + Sync,
+    F: FnMut(R),
+{
+    let threads = threads.clamp(1, jobs.len().max(1));
+    let state = Mutex::new(State {
+        queue: jobs
+            .into_iter()
+            .enumerate()
+            .map(|(index, (job, cost))| (index, job, cost))
+            .collect(),
+        in_flight: 0,
+    });
+    let released = Condvar::new();
+
+    // NOTE: jobs are started strictly in order, so the next job that `finish` is waiting for has
+    // always been started and can't be held up by the jobs after it.
+    let next_job = || {
+        let mut state = state.lock().unwrap();
+        loop {
+            match state.queue.front() {
+                None => return None,
+                Some(&(_, _, cost))
+                    if state.in_flight == 0 || state.in_flight + cost <= max_cost =>
+                {
+                    state.in_flight += cost;
+                    return state.queue.pop_front();
+                }
+                Some(_) => state = released.wait(state).unwrap(),
+            }
+        }
+    };
+
+    let panicked = thread::scope(|scope| {
+        let (tx, rx) = mpsc::channel();
+        for _ in 0..threads {
+            let tx = tx.clone();
+            let (next_job, work) = (&next_job, &work);
+            scope.spawn(move || {
+                while let Some((index, job, cost)) = next_job() {
+                    let result = panic::catch_unwind(AssertUnwindSafe(|| work(job)));
+                    if tx.send((index, result, cost)).is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+        drop(tx);
+
+        let mut pending = BTreeMap::new();
+        let mut next_index = 0;
+        for (index, result, cost) in rx {
+            match result {
+                Ok(result) => pending.insert(index, (result, cost)),
+                Err(payload) => {
+                    // stop the other workers, including any waiting for a job's cost to be released
+                    state.lock().unwrap().queue.clear();
+                    released.notify_all();
+                    return Some(payload);
+                }
+            };
+            while let Some((result, cost)) = pending.remove(&next_index) {
+                finish(result);
+                next_index += 1;
+
+                state.lock().unwrap().in_flight -= cost;
+                released.notify_all();
+            }
+        }
+
+        None
+    });
+
+    if let Some(payload) = panicked {
+        panic::resume_unwind(payload);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use super::*;
+
+    #[test]
+    fn it_finishes_jobs_in_order_within_the_limit() {
+        let in_flight = AtomicU64::new(0);
+        let max_seen = AtomicU64::new(0);
+        let jobs = (0..50u64).map(|i| (i, 10 + i % 7)).collect::<Vec<_>>();
+
+        let mut finished = vec![];
+        run_in_order(
+            jobs,
+            8,
+            40,
+            |i| {
+                let cost = 10 + i % 7;
+                let now = in_flight.fetch_add(cost, Ordering::SeqCst) + cost;
+                max_seen.fetch_max(now, Ordering::SeqCst);
+                thread::yield_now();
+                in_flight.fetch_sub(cost, Ordering::SeqCst);
+                i * 2
+            },
+            |result| finished.push(result),
+        );
+
+        assert_eq!(finished, (0..50).map(|i| i * 2).collect::<Vec<_>>());
+        assert!(max_seen.load(Ordering::SeqCst) <= 40);
+    }
+
+    #[test]
+    fn it_resumes_a_panic_from_a_job() {
+        // run the pool on another thread, so a deadlock fails the test rather than hanging it
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let result = panic::catch_unwind(|| {
+                run_in_order(
+                    (0..20).map(|i| (i, 10)).collect(),
+                    4,
+                    20,
+                    |i| {
+                        if i == 3 {
+                            panic!("job {} failed", i);
+                        }
+                        i
+                    },
+                    |_| {},
+                )
+            });
+            tx.send(result.is_err()).unwrap();
+        });
+
+        assert!(rx.recv_timeout(std::time::Duration::from_secs(10)).unwrap());
+    }
+
+    #[test]
+    fn it_runs_jobs_larger_than_the_limit_alone() {
+        let mut finished = vec![];
+        run_in_order(
+            vec![(1, 100), (2, 5), (3, 100)],
+            4,
+            10,
+            |i| i,
+            |result| finished.push(result),
+        );
+
+        assert_eq!(finished, [1, 2, 3]);
+    }
+}
@@ -0,0 +1,166 @@
+//! A machine-readable report of the replacements that were made, so that tools can audit them.
+//!
+//! The report is written as a single JSON object once every file has been handled. Paths and the
+//! text of skipped matches are written in the same form as ripgrep's JSON output, i.e., either as
+//! `{"text":"..."}` or (if they aren't valid UTF-8) as `{"bytes":"<base64>"}`.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use super::ReplacementSummary;
+use crate::encoding::Bom;
+use crate::rg::de::ArbitraryData;
+use crate::ui::line::{Item, SubItem};
+
+/// Where the report is written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReportOutput {
+    Stdout,
+    File(PathBuf),
+}
+
+/// Why a match wasn't replaced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SkipReason {
+    /// The bytes at the match's offset aren't the bytes ripgrep reported, e.g., because the file
+    /// changed after it was searched.
+    BytesMismatch,
+    /// The match overlaps a match before it.
+    Overlapping,
+    /// The file ends before the match.
+    EndOfFile,
+}
+
+/// A match that was selected but wasn't replaced.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SkippedMatch {
+    pub line_number: Option<usize>,
+    /// The offset of the match in bytes from the start of the file.
+    pub offset: usize,
+    /// The text ripgrep matched.
+    pub text: ArbitraryData,
+    pub reason: SkipReason,
+}
+
+impl SkippedMatch {
+    pub fn new(item: &Item, sub_item: &SubItem, reason: SkipReason) -> SkippedMatch {
+        SkippedMatch {
+            line_number: item.line_number_at(sub_item.index),
+            offset: item.offset().unwrap_or(0) + sub_item.sub_match.range.start,
+            text: sub_item.sub_match.text.clone(),
+            reason,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileReport {
+    pub path: ArbitraryData,
+    /// The number of matches that were replaced.
+    pub replaced: usize,
+    pub skipped: Vec<SkippedMatch>,
+    /// The BOM found at the start of the file, if any.
+    pub bom: Option<String>,
+    /// The name of the encoding the file was read and written with.
+    pub encoder: Option<String>,
+    /// If true, the file was streamed rather than read into memory.
+    pub streamed: bool,
+    /// If true, the file changed after it was searched. Nothing is replaced in it if it was skipped
+    /// (see `--on-stale`).
+    pub stale: bool,
+    /// Set if nothing in the file could be replaced.
+    pub error: Option<String>,
+}
+
+impl FileReport {
+    pub fn new(path: &ArbitraryData) -> FileReport {
+        FileReport {
+            path: path.clone(),
+            replaced: 0,
+            skipped: vec![],
+            bom: None,
+            encoder: None,
+            streamed: false,
+            stale: false,
+            error: None,
+        }
+    }
+
+    pub fn with_encoding(mut self, bom: Option<Bom>, encoder: &str) -> FileReport {
+        self.bom = bom.map(|bom| format!("{:?}", bom));
+        self.encoder = Some(encoder.to_string());
+        self
+    }
+
+    pub fn with_error(mut self, error: impl ToString) -> FileReport {
+        self.error = Some(error.to_string());
+        self
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    /// If true, nothing was written and the report describes what would have been replaced.
+    pub dry_run: bool,
+    /// Every file with selected matches, in the order ripgrep reported them.
+    pub files: Vec<FileReport>,
+    pub summary: ReplacementSummary,
+}
+
+impl Report {
+    pub fn new(dry_run: bool) -> Report {
+        Report {
+            dry_run,
+            files: vec![],
+            summary: ReplacementSummary::default(),
+        }
+    }
+
+    pub fn add(&mut self, file: FileReport) {
+        if file.replaced > 0 {
+            self.summary.files += 1;
+            self.summary.matches += file.replaced;
+        }
+        self.files.push(file);
+    }
+
+    /// Marks every file that had replacements as not replaced, e.g., because the replacements were
+    /// being made in a transaction that failed.
+    pub fn roll_back(&mut self, error: impl ToString) {
+        let error = error.to_string();
+        for file in self.files.iter_mut().filter(|file| file.replaced > 0) {
+            file.replaced = 0;
+            file.error.get_or_insert_with(|| error.clone());
+        }
+        self.summary = ReplacementSummary::default();
+    }
+
+    /// Returns true if any matches weren't replaced because of an error.
+    pub fn has_failures(&self) -> bool {
+        self.files
+            .iter()
+            .any(|file| file.error.is_some() || !file.skipped.is_empty())
+    }
+
+    pub fn write(&self, output: &ReportOutput) -> Result<()> {
+        match output {
+            ReportOutput::Stdout => self.write_to(io::stdout().lock()),
+            ReportOutput::File(path) => File::create(path)
+                .map_err(anyhow::Error::from)
+                .and_then(|file| self.write_to(BufWriter::new(file)))
+                .with_context(|| format!("Failed to write report to {}", path.display())),
+        }
+    }
+
+    fn write_to(&self, mut out: impl Write) -> Result<()> {
+        serde_json::to_writer(&mut out, self)?;
+        writeln!(out)?;
+        out.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,201 @@
+//! Handling of files that have changed since they were searched (e.g., by an editor or a formatter
+//! running in the background), since the offsets ripgrep reported for them may no longer be right.
+//!
+//! Each stale file is either skipped, searched again with ripgrep (keeping the user's selection for
+//! the matches that are still there), or replaced anyway.
+
+use std::collections::{HashMap, VecDeque};
+use std::ffi::OsString;
+use std::io::{self, IsTerminal, Write};
+use std::str::FromStr;
+
+use anyhow::{bail, Result};
+
+use crate::rg::de::{ArbitraryData, RgMessage, RgMessageKind};
+use crate::rg::exec::run_ripgrep;
+use crate::ui::line::{Item, SubItem};
+
+/// What to do with files that have changed since they were searched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnStale {
+    /// Ask the user for each file.
+    Ask,
+    /// Don't replace anything in the file.
+    Skip,
+    /// Search the file again, and keep the selection for the matches that are found again.
+    Rerun,
+    /// Replace the matches anyway. Matches whose text is no longer at the same offset are skipped.
+    Force,
+}
+
+impl FromStr for OnStale {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<OnStale> {
+        Ok(match s {
+            "ask" => OnStale::Ask,
+            "skip" => OnStale::Skip,
+            "rerun" => OnStale::Rerun,
+            "force" => OnStale::Force,
+            _ => bail!(
+                "unknown action '{}', expected one of: ask, skip, rerun, force",
+                s
+            ),
+        })
+    }
+}
+
+/// Asks the user what to do with the stale file at `path`. If the answer should be used for all
+/// the remaining files too, then `on_stale` is updated to it.
+pub fn ask(path: &ArbitraryData, on_stale: &mut OnStale) -> Result<OnStale> {
+    let stdin = io::stdin();
+    if !stdin.is_terminal() {
+        log::warn!("Can't ask what to do with {}, skipping it", path);
+        return Ok(OnStale::Skip);
+    }
+
+    loop {
+        eprint!(
+            "{} has changed since it was searched.\n\
+            [s]kip it, [r]e-run rg on it and keep the selection, or [f]orce the replacements?\n\
+            (uppercase applies to all remaining files) ",
+            path
+        );
+        io::stderr().flush()?;
+
+        let mut line = String::new();
+        if stdin.read_line(&mut line)? == 0 {
+            *on_stale = OnStale::Skip;
+            return Ok(OnStale::Skip);
+        }
+
+        let answer = line.trim();
+        let action = match answer.to_lowercase().as_str() {
+            "s" | "skip" => OnStale::Skip,
+            "r" | "rerun" => OnStale::Rerun,
+            "f" | "force" => OnStale::Force,
+            _ => continue,
+        };
+        if answer.chars().all(|c| c.is_uppercase()) {
+            *on_stale = action;
+        }
+
+        return Ok(action);
+    }
+}
+
+/// Searches the file at `path` again with ripgrep using `rerun_args`, and returns its new items.
+/// Returns no items if nothing matches any more.
+pub fn rerun(rerun_args: &[String], path: &ArbitraryData) -> Result<Vec<Item>> {
+    let path_buf = path.to_path_buf()?;
+    let mut args = rerun_args.iter().map(OsString::from).collect::<Vec<_>>();
+    args.push("--".into());
+    args.push(path_buf.into_os_string());
+
+    let mut rg_messages = vec![];
+    for rg_message in run_ripgrep(args)? {
+        match rg_message {
+            Ok(RgMessage::Summary { .. }) => {}
+            Ok(rg_message) => rg_messages.push(rg_message),
+            // an error is sent if nothing matches, which isn't a problem here
+            Err(e) if rg_messages.is_empty() => log::debug!("No matches in {}: {}", path, e),
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(rg_messages
+        .into_iter()
+        .enumerate()
+        .map(|(i, rg_message)| Item::new(i, rg_message))
+        .collect())
+}
+
+/// Applies the selection of `old_items` to `new_items`. Matches are paired up by the text of their
+/// line and the text they matched (in order), since their offsets may have changed. Matches that
+/// weren't found before are deselected, since the user hasn't seen them. Returns how many were.
+pub fn reapply_selection(old_items: &[&Item], new_items: &mut [Item]) -> usize {
+    let mut old_sub_items = HashMap::<(Vec<u8>, Vec<u8>), VecDeque<&SubItem>>::new();
+    for item in old_items {
+        let lines = item.lines().map(|l| l.to_vec()).unwrap_or_default();
+        for sub_item in item.sub_items() {
+            old_sub_items
+                .entry((lines.clone(), sub_item.sub_match.text.to_vec()))
+                .or_default()
+                .push_back(sub_item);
+        }
+    }
+
+    let mut unseen = 0;
+    for item in new_items
+        .iter_mut()
+        .filter(|i| matches!(i.kind, RgMessageKind::Match))
+    {
+        let lines = item.lines().map(|l| l.to_vec()).unwrap_or_default();
+        for idx in 0..item.sub_items().len() {
+            let key = (lines.clone(), item.sub_items()[idx].sub_match.text.to_vec());
+            match old_sub_items.get_mut(&key).and_then(|s| s.pop_front()) {
+                Some(old) => {
+                    item.set_should_replace(idx, old.should_replace);
+                    item.set_replacement(idx, old.replacement.clone());
+                }
+                None => {
+                    item.set_should_replace(idx, false);
+                    unseen += 1;
+                }
+            }
+        }
+    }
+
+    unseen
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::rg::de::test_utilities::*;
+    use crate::rg::de::SubMatch;
+
+    #[test]
+    fn it_parses_actions() {
+        assert_eq!("ask".parse::<OnStale>().unwrap(), OnStale::Ask);
+        assert_eq!("skip".parse::<OnStale>().unwrap(), OnStale::Skip);
+        assert_eq!("rerun".parse::<OnStale>().unwrap(), OnStale::Rerun);
+        assert_eq!("force".parse::<OnStale>().unwrap(), OnStale::Force);
+        assert!("never".parse::<OnStale>().is_err());
+    }
+
+    #[test]
+    fn it_reapplies_the_selection() {
+        let mut old = Item::new(1, RgMessage::from_str(RG_JSON_MATCH));
+        old.set_should_replace(0, false);
+        old.set_replacement(1, Some("override".into()));
+
+        // the same line moved to a different offset, and a new match was found after it
+        let moved = RgMessageBuilder::new(RgMessageKind::Match)
+            .with_path_text("src/model/item.rs")
+            .with_lines_text("    Item::new(rg_msg)\n")
+            .with_line_number(200)
+            .with_offset(100)
+            .with_submatches(vec![
+                SubMatch::new_text("Item", 4..8),
+                SubMatch::new_text("rg_msg", 14..20),
+            ])
+            .build();
+        let added = RgMessageBuilder::new(RgMessageKind::Match)
+            .with_path_text("src/model/item.rs")
+            .with_lines_text("Item\n")
+            .with_line_number(201)
+            .with_offset(200)
+            .with_submatches(vec![SubMatch::new_text("Item", 0..4)])
+            .build();
+        let mut new_items = vec![Item::new(0, moved), Item::new(1, added)];
+
+        assert_eq!(reapply_selection(&[&old], &mut new_items), 1);
+        assert!(!new_items[0].get_should_replace(0));
+        assert!(new_items[0].get_should_replace(1));
+        assert_eq!(new_items[0].get_replacement(1), Some("override"));
+        assert!(!new_items[1].get_should_replace(0));
+    }
+}
@@ -0,0 +1,232 @@
+//! Replacing matches in very large files without loading them into memory.
+//!
+//! This only works for files without a BOM that are UTF-8 (or ASCII), since then ripgrep's offsets
+//! are offsets into the raw bytes of the file and nothing needs to be decoded. The file is copied
+//! into a temporary file up to each match, the replacement is written in place of the match, and
+//! the temporary file then replaces the original.
+//!
+//! Streamed files are not journaled, since that would mean copying the whole of each one, so they
+//! can't be restored with `--undo`.
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use anyhow::Result;
+use encoding::Encoding;
+
+use super::report::{FileReport, SkipReason, SkippedMatch};
+use super::transaction::StagedFile;
+use super::{create_temp_file, FileOutcome};
+use crate::encoding::get_encoder;
+use crate::model::{MatchContext, MatchCounts, ReplacementCriteria};
+use crate::rg::de::ArbitraryData;
+use crate::rg::RgEncoding;
+use crate::ui::line::Item;
+
+/// How much of the start of a file is used to detect its encoding.
+const DETECT_LEN: u64 = 64 * 1024;
+
+/// The default size at which files are streamed rather than replaced in memory.
+pub const DEFAULT_STREAMING_THRESHOLD: u64 = 64 * 1024 * 1024;
+
+/// An estimate of how much memory streaming a file takes, used instead of its size when limiting
+/// the bytes in flight (see `pool::run_in_order`).
+pub const STREAMING_COST: u64 = 2 * BUF_SIZE as u64;
+
+const BUF_SIZE: usize = 64 * 1024;
+
+/// Returns true if the file at `path` can be streamed, i.e., it has no BOM and it's encoded as
+/// UTF-8 (which includes ASCII).
+pub fn can_stream(path: &Path, rg_encoding: &RgEncoding) -> Result<bool> {
+    let mut head = vec![];
+    File::open(path)?.take(DETECT_LEN).read_to_end(&mut head)?;
+
+    let (bom, encoder) = get_encoder(&head, rg_encoding);
+    Ok(bom.is_none() && encoder.name() == encoding::all::UTF_8.name())
+}
+
+/// Performs the replacements in the file by streaming it through a temporary file, which then
+/// replaces the original. Bytes outside the matches are copied as they are.
+pub(super) fn stream_replacements_in_file(
+    criteria: &ReplacementCriteria,
+    counts: &MatchCounts,
+    (path_data, mut items): (&ArbitraryData, Vec<&Item>),
+) -> Result<FileOutcome> {
+    log::debug!(
+        "Streaming file: {} (item count: {})",
+        path_data,
+        items.len()
+    );
+    let path_buf = path_data.to_path_buf()?;
+
+    // Unlike in memory, the matches are replaced from the start of the file to the end.
+    items.sort_unstable_by_key(|i| i.offset());
+
+    let reader = BufReader::with_capacity(BUF_SIZE, File::open(&path_buf)?);
+    let mut temp_file = create_temp_file(&path_buf)?;
+    let mut report = FileReport::new(path_data).with_encoding(None, encoding::all::UTF_8.name());
+    report.streamed = true;
+    {
+        let mut writer = BufWriter::with_capacity(BUF_SIZE, temp_file.as_file_mut());
+        copy_with_replacements(criteria, counts, &items, reader, &mut writer, &mut report)?;
+        writer.flush()?;
+    }
+
+    let staged = StagedFile {
+        path_buf,
+        temp_path: temp_file.into_temp_path(),
+        backup_path: None,
+    };
+
+    FileOutcome::from(report).with_staged(criteria, staged)
+}
+
+/// Copies `reader` to `writer`, writing the replacement for each selected match in `items` instead
+/// of the match, and adds the matches that were replaced or skipped to `report`. The items must be
+/// sorted by their offset.
+fn copy_with_replacements(
+    criteria: &ReplacementCriteria,
+    counts: &MatchCounts,
+    items: &[&Item],
+    mut reader: impl Read,
+    mut writer: impl Write,
+    report: &mut FileReport,
+) -> Result<()> {
+    let mut pos = 0;
+    let mut ended = false;
+    let mut matched = vec![];
+    for item in items {
+        let offset = item.offset().unwrap();
+        for sub_item in item.sub_items().iter().filter(|s| s.should_replace) {
+            let text = sub_item.sub_match.text.to_vec();
+            let start = offset + sub_item.sub_match.range.start;
+            if ended {
+                report
+                    .skipped
+                    .push(SkippedMatch::new(item, sub_item, SkipReason::EndOfFile));
+                continue;
+            }
+            if start < pos {
+                log::warn!("Skipping overlapping match at offset {}", start);
+                report
+                    .skipped
+                    .push(SkippedMatch::new(item, sub_item, SkipReason::Overlapping));
+                continue;
+            }
+
+            // copy everything up to the match
+            let to_copy = (start - pos) as u64;
+            let copied = io::copy(&mut reader.by_ref().take(to_copy), &mut writer)?;
+            matched.clear();
+            reader
+                .by_ref()
+                .take(text.len() as u64)
+                .read_to_end(&mut matched)?;
+            pos = start + matched.len();
+
+            if copied == to_copy && matched == text {
+                let ctx = MatchContext::new(item, sub_item.index, Some(counts));
+                let replacement = criteria.replacement_for(sub_item, &ctx);
+                // the replacement must be valid UTF-8, in the same way as it is in memory
+                writer.write_all(std::str::from_utf8(&replacement)?.as_bytes())?;
+                report.replaced += 1;
+            } else if copied < to_copy || matched.len() < text.len() {
+                log::warn!("File ended before the match at offset {}", start);
+                writer.write_all(&matched)?;
+                report
+                    .skipped
+                    .push(SkippedMatch::new(item, sub_item, SkipReason::EndOfFile));
+                ended = true;
+            } else {
+                log::warn!("Matched bytes do not match bytes to replace!");
+                log::warn!("\tMatch: bytes={:?}", text);
+                log::warn!("\tBytes: bytes={:?}", matched);
+                log::warn!("\tOffset: {}", start);
+                writer.write_all(&matched)?;
+                report
+                    .skipped
+                    .push(SkippedMatch::new(item, sub_item, SkipReason::BytesMismatch));
+            }
+        }
+    }
+
+    // copy the rest of the file
+    io::copy(&mut reader, &mut writer)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::replace::journal::undo_last_session;
+    use crate::replace::perform_replacements;
+    use crate::rg::de::test_utilities::RgMessageBuilder;
+    use crate::rg::de::{RgMessageKind, SubMatch};
+    use crate::temp_file;
+
+    fn item(index: usize, path: &Path, offset: usize, line: &str, submatch: SubMatch) -> Item {
+        Item::new(
+            index,
+            RgMessageBuilder::new(RgMessageKind::Match)
+                .with_path_text(path.to_string_lossy())
+                .with_lines_text(line)
+                .with_submatches(vec![submatch])
+                .with_offset(offset)
+                .build(),
+        )
+    }
+
+    #[test]
+    fn it_only_streams_utf8_files_without_a_bom() {
+        let ascii = temp_file!("foo bar baz");
+        let utf8 = temp_file!("🦀 foo");
+        let utf8_bom = temp_file!(bytes, b"\xEF\xBB\xBFfoo");
+        let utf16_bom = temp_file!(bytes, b"\xFF\xFEf\x00o\x00o\x00");
+
+        assert!(can_stream(&ascii, &RgEncoding::None).unwrap());
+        assert!(can_stream(&utf8, &RgEncoding::None).unwrap());
+        assert!(!can_stream(&utf8_bom, &RgEncoding::None).unwrap());
+        assert!(!can_stream(&utf16_bom, &RgEncoding::None).unwrap());
+        assert!(!can_stream(&ascii, &RgEncoding::from("utf-16le")).unwrap());
+    }
+
+    #[test]
+    fn it_streams_replacements_without_journaling_them() {
+        let journal_dir = tempfile::tempdir().unwrap();
+        let text = "🦀 foo\nbar foo\nbaz\n";
+        let p = temp_file!(text);
+
+        let items = vec![
+            item(0, &p, 0, "🦀 foo\n", SubMatch::new_text("foo", 5..8)),
+            item(1, &p, 9, "bar foo\n", SubMatch::new_text("foo", 4..7)),
+        ];
+        let mut criteria = ReplacementCriteria::new(None, "qüx", items);
+        criteria.streaming_threshold = 0;
+        criteria.journal_dir = Some(journal_dir.path().to_path_buf());
+        perform_replacements(criteria).unwrap();
+        assert_eq!(fs::read_to_string(&p).unwrap(), "🦀 qüx\nbar qüx\nbaz\n");
+
+        assert_eq!(undo_last_session(journal_dir.path()).unwrap(), 0);
+        assert_eq!(fs::read_to_string(&p).unwrap(), "🦀 qüx\nbar qüx\nbaz\n");
+    }
+
+    #[test]
+    fn it_skips_matches_that_have_moved_when_streaming() {
+        let p = temp_file!("foo bar\nbar foo\n");
+
+        let items = vec![
+            item(0, &p, 0, "foo bar\n", SubMatch::new_text("foo", 0..3)),
+            // this is really "bar"
+            item(1, &p, 8, "foo bar\n", SubMatch::new_text("foo", 0..3)),
+        ];
+        let mut criteria = ReplacementCriteria::new(None, "baz", items);
+        criteria.streaming_threshold = 0;
+        assert!(perform_replacements(criteria).is_err());
+        assert_eq!(fs::read_to_string(&p).unwrap(), "baz bar\nbar foo\n");
+    }
+}
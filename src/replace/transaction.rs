@@ -0,0 +1,288 @@
+//! Replacing every file or none of them.
+//!
+//! Each file's replaced contents are first staged in a temporary file beside it. Once every file has
+//! been staged, they're committed one by one: the original is renamed out of the way and the staged
+//! file is renamed in its place. If any of those renames fail, the files that were already committed
+//! are restored from their originals.
+//!
+//! Backups (see `backup`) are only made once every file has been committed, so that a rolled back
+//! transaction leaves any previous backups untouched.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use tempfile::{Builder, TempPath};
+
+use super::backup;
+
+/// A file whose replaced contents have been written beside it, but which hasn't replaced it yet.
+/// The staged contents are removed if this is dropped.
+#[derive(Debug)]
+pub struct StagedFile {
+    pub path_buf: PathBuf,
+    pub temp_path: TempPath,
+    /// If set, the original file is kept here when it's replaced (see `backup`).
+    pub backup_path: Option<PathBuf>,
+}
+
+/// An original file which has been moved aside while it's replaced. It's removed when dropped.
+struct Backup {
+    path_buf: PathBuf,
+    backup: TempPath,
+    backup_path: Option<PathBuf>,
+}
+
+impl StagedFile {
+    /// Replaces the original file with the staged one straight away.
+    pub fn persist(self) -> Result<()> {
+        self.back_up()?;
+        log::debug!(
+            "Moving {} to {}",
+            self.temp_path.display(),
+            self.path_buf.display()
+        );
+        self.temp_path.persist(&self.path_buf)?;
+        Ok(())
+    }
+
+    /// Replaces the original file with the staged one, and returns the original so it can be
+    /// restored.
+    fn commit(self) -> Result<Backup> {
+        let parent_dir = self.path_buf.parent().unwrap_or_else(|| Path::new("."));
+        let backup = Builder::new()
+            .prefix(".rgr-backup")
+            .tempfile_in(parent_dir)?
+            .into_temp_path();
+
+        log::debug!("Moving {} to {}", self.path_buf.display(), backup.display());
+        fs::rename(&self.path_buf, &backup)?;
+
+        log::debug!(
+            "Moving {} to {}",
+            self.temp_path.display(),
+            self.path_buf.display()
+        );
+        if let Err(e) = self.temp_path.persist(&self.path_buf) {
+            fs::rename(&backup, &self.path_buf)?;
+            return Err(e.error.into());
+        }
+
+        Ok(Backup {
+            path_buf: self.path_buf,
+            backup,
+            backup_path: self.backup_path,
+        })
+    }
+
+    fn back_up(&self) -> Result<()> {
+        match &self.backup_path {
+            Some(backup_path) => backup::back_up(&self.path_buf, backup_path)
+                .with_context(|| format!("Failed to back up {}", self.path_buf.display())),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Backup {
+    /// Puts the original file back in place of the replaced one.
+    fn restore(self) -> Result<()> {
+        log::debug!(
+            "Restoring {} from {}",
+            self.path_buf.display(),
+            self.backup.display()
+        );
+        match fs::rename(&self.backup, &self.path_buf) {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                // keep the original, so it can be restored by hand
+                let backup = self.backup.keep()?;
+                Err(anyhow!(
+                    "Failed to restore {}, its original is at {}: {}",
+                    self.path_buf.display(),
+                    backup.display(),
+                    e
+                ))
+            }
+        }
+    }
+
+    /// Keeps the original file as its backup, if it has one, once the transaction is committed.
+    fn finish(self) -> Result<()> {
+        let backup_path = match &self.backup_path {
+            Some(backup_path) => backup_path,
+            None => return Ok(()),
+        };
+        match backup::back_up(&self.backup, backup_path) {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                // keep the original, so it isn't lost
+                let backup = self.backup.keep()?;
+                Err(anyhow!(
+                    "Failed to back up {}, its original is at {}: {:#}",
+                    self.path_buf.display(),
+                    backup.display(),
+                    e
+                ))
+            }
+        }
+    }
+}
+
+/// Replaces every staged file in order. If any of them can't be replaced, then the ones that were
+/// are restored and an error is returned.
+pub fn commit(staged: Vec<StagedFile>) -> Result<()> {
+    let mut committed = Vec::with_capacity(staged.len());
+    for staged_file in staged {
+        let path_buf = staged_file.path_buf.clone();
+        match staged_file.commit() {
+            Ok(backup) => committed.push(backup),
+            Err(e) => {
+                let e = e.context(format!("Failed to replace {}", path_buf.display()));
+                log::warn!("{:#}, rolling back {} file(s)", e, committed.len());
+                let restore_errors = committed
+                    .into_iter()
+                    .rev()
+                    .filter_map(|backup| backup.restore().err())
+                    .map(|e| format!("\n  {:#}", e))
+                    .collect::<String>();
+                return Err(e).with_context(|| {
+                    if restore_errors.is_empty() {
+                        "Rolled back all files".to_string()
+                    } else {
+                        format!("Failed to roll back all files:{}", restore_errors)
+                    }
+                });
+            }
+        }
+    }
+
+    log::debug!("Committed {} file(s)", committed.len());
+    let backup_errors = committed
+        .into_iter()
+        .filter_map(|backup| backup.finish().err())
+        .map(|e| format!("\n  {:#}", e))
+        .collect::<String>();
+    if backup_errors.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "Replaced all files, but failed to back up:{}",
+            backup_errors
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use pretty_assertions::assert_eq;
+    use tempfile::NamedTempFile;
+
+    use super::*;
+
+    fn stage(path: &Path, contents: &str) -> StagedFile {
+        let mut temp_file = NamedTempFile::new_in(path.parent().unwrap()).unwrap();
+        temp_file.write_all(contents.as_bytes()).unwrap();
+        StagedFile {
+            path_buf: path.to_path_buf(),
+            temp_path: temp_file.into_temp_path(),
+            backup_path: None,
+        }
+    }
+
+    #[test]
+    fn it_commits_every_staged_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let p1 = dir.path().join("a.txt");
+        let p2 = dir.path().join("b.txt");
+        fs::write(&p1, "foo").unwrap();
+        fs::write(&p2, "bar").unwrap();
+
+        commit(vec![stage(&p1, "new foo"), stage(&p2, "new bar")]).unwrap();
+        assert_eq!(fs::read_to_string(&p1).unwrap(), "new foo");
+        assert_eq!(fs::read_to_string(&p2).unwrap(), "new bar");
+
+        // nothing is left behind
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn it_rolls_back_if_a_file_cant_be_replaced() {
+        let dir = tempfile::tempdir().unwrap();
+        let p1 = dir.path().join("a.txt");
+        let p2 = dir.path().join("b.txt");
+        let p3 = dir.path().join("c.txt");
+        fs::write(&p1, "foo").unwrap();
+        fs::write(&p2, "bar").unwrap();
+        fs::write(&p3, "baz").unwrap();
+
+        let staged = vec![
+            stage(&p1, "new foo"),
+            stage(&p2, "new bar"),
+            stage(&p3, "new baz"),
+        ];
+        // the second file disappears before it's replaced
+        fs::remove_file(&p2).unwrap();
+
+        let err = commit(staged).unwrap_err();
+        assert!(format!("{:#}", err).contains(&p2.display().to_string()));
+        assert_eq!(fs::read_to_string(&p1).unwrap(), "foo");
+        assert!(!p2.exists());
+        assert_eq!(fs::read_to_string(&p3).unwrap(), "baz");
+
+        // nothing is left behind
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn it_only_backs_up_files_once_committed() {
+        let dir = tempfile::tempdir().unwrap();
+        let p1 = dir.path().join("a.txt");
+        let p2 = dir.path().join("b.txt");
+        let p3 = dir.path().join("c.txt");
+        fs::write(&p1, "foo").unwrap();
+        fs::write(&p2, "bar").unwrap();
+        fs::write(&p3, "baz").unwrap();
+        fs::write(dir.path().join("a.txt.orig"), "old foo").unwrap();
+
+        let stage_with_backup = |path: &Path, contents| StagedFile {
+            backup_path: Some(path.with_extension("txt.orig")),
+            ..stage(path, contents)
+        };
+
+        // the second file disappears before it's replaced
+        let staged = vec![
+            stage_with_backup(&p1, "new foo"),
+            stage_with_backup(&p2, "new bar"),
+            stage_with_backup(&p3, "new baz"),
+        ];
+        fs::remove_file(&p2).unwrap();
+        commit(staged).unwrap_err();
+
+        // the previous backup is kept, and no new ones are made
+        assert_eq!(
+            fs::read_to_string(dir.path().join("a.txt.orig")).unwrap(),
+            "old foo"
+        );
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 3);
+
+        fs::write(&p2, "bar").unwrap();
+        commit(vec![
+            stage_with_backup(&p1, "new foo"),
+            stage_with_backup(&p2, "new bar"),
+        ])
+        .unwrap();
+        assert_eq!(fs::read_to_string(&p1).unwrap(), "new foo");
+        assert_eq!(
+            fs::read_to_string(dir.path().join("a.txt.orig")).unwrap(),
+            "foo"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join("b.txt.orig")).unwrap(),
+            "bar"
+        );
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 5);
+    }
+}
@@ -1,17 +1,21 @@
 use std::ffi::OsStr;
 use std::fmt::Display;
 use std::io::{ErrorKind, Read};
-use std::process::{Command, Stdio};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
 
 use anyhow::{anyhow, Error, Result};
 
 use crate::rg::de::RgMessage;
+use crate::rg::read::{send_messages, RgMessageReceiver};
 
 fn rg_run_error(msg: impl Display) -> Error {
     anyhow!("An error occurred when running `rg`:\n\n{}", msg)
 }
 
-pub fn run_ripgrep<I, S>(args: I) -> Result<Vec<RgMessage>>
+/// Spawns ripgrep and returns a receiver which yields its messages while it's still searching.
+pub fn run_ripgrep<I, S>(args: I) -> Result<RgMessageReceiver>
 where
     I: IntoIterator<Item = S>,
     S: AsRef<OsStr>,
@@ -24,6 +28,8 @@ where
         .arg("--no-binary")
         .arg("--no-text")
         .stdout(Stdio::piped())
+        // capture stderr, otherwise it would be drawn over our TUI
+        .stderr(Stdio::piped())
         .spawn()
     {
         Ok(child) => child,
@@ -38,32 +44,47 @@ where
         }
     };
 
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        if let Err(e) = read_ripgrep_output(&mut child, &tx) {
+            let _ = tx.send(Err(e));
+        }
+    });
+
+    Ok(rx)
+}
+
+fn read_ripgrep_output(child: &mut Child, tx: &Sender<Result<RgMessage>>) -> Result<()> {
+    // Read stderr on another thread, so ripgrep never blocks on a full stderr pipe.
+    let mut stderr = child.stderr.take().unwrap();
+    let stderr_thread = thread::spawn(move || {
+        let mut rg_stderr = String::new();
+        stderr.read_to_string(&mut rg_stderr).map(|_| rg_stderr)
+    });
+
     // Read messages from child process.
-    let rg_messages = super::read::read_messages(child.stdout.as_mut().unwrap())?;
+    if let Err(e) = send_messages(child.stdout.take().unwrap(), tx) {
+        // Either we failed to parse the output, or the receiver was dropped (the user quit before
+        // the search finished), so there's no reason to let ripgrep keep running.
+        let _ = child.kill();
+        let _ = child.wait();
+        return Err(e);
+    }
 
     // Wait for ripgrep to finish before returning.
     match child.wait() {
-        Ok(exit_status) if exit_status.success() => Ok(rg_messages),
-        Ok(_) => {
-            let mut rg_stderr = String::new();
-            Err(
-                match child
-                    .stderr
-                    .as_mut()
-                    .unwrap()
-                    .read_to_string(&mut rg_stderr)
-                {
-                    Ok(_) => {
-                        if rg_stderr.is_empty() {
-                            anyhow!("No matches found")
-                        } else {
-                            rg_run_error(rg_stderr)
-                        }
-                    }
-                    Err(e) => anyhow!("failed to read rg's stderr: {}", e),
-                },
-            )
-        }
+        Ok(exit_status) if exit_status.success() => Ok(()),
+        Ok(_) => Err(match stderr_thread.join() {
+            Ok(Ok(rg_stderr)) => {
+                if rg_stderr.is_empty() {
+                    anyhow!("No matches found")
+                } else {
+                    rg_run_error(rg_stderr)
+                }
+            }
+            Ok(Err(e)) => anyhow!("failed to read rg's stderr: {}", e),
+            Err(_) => anyhow!("failed to read rg's stderr"),
+        }),
         Err(e) => Err(anyhow!("failed to wait for rg to end: {}", e)),
     }
 }
@@ -1,35 +1,99 @@
-use std::io::{self, BufRead, BufReader, Read, Write};
+use std::io::{BufRead, BufReader, Read};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
 
 use anyhow::{anyhow, Result};
 
 use crate::rg::de::RgMessage;
 
-pub fn read_messages<R: Read>(rdr: R) -> Result<Vec<RgMessage>> {
-    let mut saw_match_message = false;
+/// The receiving end of a stream of messages from ripgrep. If an error occurs, it's sent as the
+/// last message of the stream.
+pub type RgMessageReceiver = Receiver<Result<RgMessage>>;
 
-    let mut rg_messages: Vec<RgMessage> = vec![];
-    let reader = BufReader::new(rdr);
-    for (i, line) in reader.lines().enumerate() {
-        // For large result lists show some progress in the terminal.
-        if i > 0 && i % 1000 == 0 {
-            let _ = io::stdout().write_all(format!("\rMatches found: ~{}", i).as_bytes());
-            let _ = io::stdout().flush();
+/// Reads messages from `rdr` on a background thread, so they can be received as soon as they're
+/// parsed rather than waiting for all of them to be read.
+pub fn read_messages<R: Read + Send + 'static>(rdr: R) -> RgMessageReceiver {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        if let Err(e) = send_messages(rdr, &tx) {
+            let _ = tx.send(Err(e));
         }
+    });
+
+    rx
+}
+
+/// Sends messages that have already been read, e.g., from a saved session.
+pub fn replay_messages(rg_messages: Vec<RgMessage>) -> RgMessageReceiver {
+    let (tx, rx) = mpsc::channel();
+    for rg_message in rg_messages {
+        // the receiver can't have been dropped yet
+        let _ = tx.send(Ok(rg_message));
+    }
+
+    rx
+}
+
+/// Reads messages from `rdr` and sends each one over `tx` as soon as it's parsed.
+/// Returns an error if the messages can't be parsed, if no matches were found, or if the receiver
+/// has been dropped.
+pub fn send_messages<R: Read>(rdr: R, tx: &Sender<Result<RgMessage>>) -> Result<()> {
+    let mut saw_match_message = false;
 
+    let reader = BufReader::new(rdr);
+    for line in reader.lines() {
         let rg_msg: RgMessage =
             serde_json::from_str(&line?).map_err(|e| anyhow!("Failed to parse JSON: {}", e))?;
 
-        if !saw_match_message && matches!(rg_msg, RgMessage::Match { .. }) {
-            saw_match_message = true;
+        match rg_msg {
+            RgMessage::Match { .. } => saw_match_message = true,
+            // We expect at least one match before the summary.
+            RgMessage::Summary { .. } if !saw_match_message => {
+                return Err(anyhow!("No matches returned from rg!"));
+            }
+            _ => {}
         }
 
-        rg_messages.push(rg_msg);
+        tx.send(Ok(rg_msg))
+            .map_err(|_| anyhow!("Receiver for rg messages was dropped"))?;
     }
 
     // We expect at least one message.
     if !saw_match_message {
         Err(anyhow!("No matches returned from rg!"))
     } else {
-        Ok(rg_messages)
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::rg::de::test_utilities::*;
+
+    #[test]
+    fn it_streams_messages() {
+        let json = format!(
+            "{}\n{}\n{}\n{}\n{}\n",
+            RG_JSON_BEGIN, RG_JSON_MATCH, RG_JSON_CONTEXT, RG_JSON_END, RG_JSON_SUMMARY
+        );
+
+        let rx = read_messages(std::io::Cursor::new(json));
+        let messages = rx.iter().collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(messages.len(), 5);
+        assert!(matches!(messages[1], RgMessage::Match { .. }));
+        assert!(matches!(messages[4], RgMessage::Summary { .. }));
+    }
+
+    #[test]
+    fn it_sends_an_error_when_there_are_no_matches() {
+        let json = format!("{}\n{}\n", RG_JSON_BEGIN, RG_JSON_SUMMARY);
+
+        let rx = read_messages(std::io::Cursor::new(json));
+        assert!(matches!(rx.recv().unwrap(), Ok(RgMessage::Begin { .. })));
+        assert!(rx.recv().unwrap().is_err());
+        assert!(rx.recv().is_err());
     }
 }
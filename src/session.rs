@@ -0,0 +1,274 @@
+//! Saving and resuming a selection session, so the matches the user has reviewed aren't lost if rgr
+//! exits before the replacements are made.
+//!
+//! A session is a single JSON file which holds ripgrep's messages (in the same form as they're read
+//! from `RGR_JSON_FILE`), which matches are selected, the replacement text and the position of the
+//! cursor. The state of each file that had matches is saved too, so that a session is only resumed
+//! if none of those files have changed since ripgrep searched them.
+
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use ratatui::layout::Rect;
+use serde::{Deserialize, Serialize};
+use tempfile::NamedTempFile;
+
+use crate::model::FileFingerprint;
+use crate::rg::de::{ArbitraryData, RgMessage};
+use crate::ui::app::App;
+
+/// The session is saved at most this often while the user is making changes.
+const SAVE_DELAY: Duration = Duration::from_secs(1);
+
+/// Returns the default path of the session file.
+pub fn default_session_path() -> Option<PathBuf> {
+    dirs::state_dir()
+        .or_else(dirs::data_local_dir)
+        .map(|dir| dir.join(env!("CARGO_PKG_NAME")).join("session.json"))
+}
+
+/// The parts of the app's state that are saved in a session, see `App::session_state`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionState {
+    /// Whether each submatch should be replaced, for every item in the list.
+    pub should_replace: Vec<Vec<bool>>,
+    /// The replacements entered for individual submatches, as `(item, submatch, replacement)`.
+    pub overrides: Vec<(usize, usize, String)>,
+    /// The replacement text, if the user had started entering it.
+    pub replacement: Option<String>,
+    pub preserve_case: bool,
+    pub filter: String,
+    /// The selected item and submatch.
+    pub cursor: (usize, usize),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Session {
+    /// The args ripgrep was run with, see `RgArgs::search_cmdline`.
+    pub search_cmdline: String,
+    pub rg_messages: Vec<RgMessage>,
+    /// The state of each file with matches when it was searched.
+    pub files: Vec<(ArbitraryData, FileFingerprint)>,
+    pub state: SessionState,
+}
+
+impl Session {
+    pub fn load(path: &Path) -> Result<Session> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                bail!("There is no session to resume at {}", path.display())
+            }
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+
+        serde_json::from_reader(BufReader::new(file))
+            .with_context(|| format!("Invalid session file {}", path.display()))
+    }
+
+    /// Writes the session to `path`. The file is replaced in one go, so an interrupted save never
+    /// leaves a partially written session behind.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create directory: {}", dir.display()))?;
+
+        let mut temp_file = NamedTempFile::new_in(dir)?;
+        serde_json::to_writer(BufWriter::new(temp_file.as_file_mut()), self)?;
+        temp_file.persist(path)?;
+        log::debug!("Saved session to: {}", path.display());
+        Ok(())
+    }
+
+    /// Returns an error listing the files that have changed (or can no longer be read) since they
+    /// were searched.
+    pub fn check_unchanged(&self) -> Result<()> {
+        let changed = self
+            .files
+            .iter()
+            .filter_map(|(path, fingerprint)| {
+                let path_buf = path.to_path_buf().ok()?;
+                fingerprint.is_stale(&path_buf).then_some(path_buf)
+            })
+            .collect::<Vec<_>>();
+
+        if !changed.is_empty() {
+            bail!(
+                "Refusing to resume, the following files have changed since they were searched:\n{}",
+                changed
+                    .iter()
+                    .map(|p| format!("  {}", p.display()))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Keeps the session file up to date while the app is running.
+pub struct SessionSaver {
+    /// Where the session is saved, or `None` if it isn't saved at all.
+    path: Option<PathBuf>,
+    /// The state of a resumed session, which is restored once ripgrep's messages have been added.
+    resumed_state: Option<SessionState>,
+    /// Set when the app may have changed since the session was last saved.
+    dirty: bool,
+    last_save: Instant,
+}
+
+impl SessionSaver {
+    pub fn new(path: PathBuf) -> SessionSaver {
+        SessionSaver {
+            path: Some(path),
+            ..SessionSaver::disabled()
+        }
+    }
+
+    /// Creates a saver which never saves, e.g., if there's no directory to save the session in.
+    pub fn disabled() -> SessionSaver {
+        SessionSaver {
+            path: None,
+            resumed_state: None,
+            dirty: false,
+            last_save: Instant::now(),
+        }
+    }
+
+    /// Continues saving a session that's being resumed. The session's messages should be passed to
+    /// the app separately, see `replay_messages`.
+    pub fn resume(path: PathBuf, session: Session) -> SessionSaver {
+        SessionSaver {
+            resumed_state: Some(session.state),
+            ..SessionSaver::new(path)
+        }
+    }
+
+    /// Called when the search is run again, since the results of the last one no longer apply.
+    pub fn search_started(&mut self) {
+        self.resumed_state = None;
+    }
+
+    /// Called once ripgrep has sent all of its messages.
+    pub fn search_finished(&mut self, app: &mut App, term_size: Rect) {
+        if let Some(state) = self.resumed_state.take() {
+            app.restore_session_state(&state, term_size);
+        }
+
+        self.dirty = true;
+    }
+
+    /// Marks the session as needing to be saved.
+    pub fn changed(&mut self) {
+        self.dirty = true;
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Returns how long to wait before the session should be saved.
+    pub fn save_delay(&self) -> Duration {
+        SAVE_DELAY.saturating_sub(self.last_save.elapsed())
+    }
+
+    /// Saves the session if it has changed. Nothing is saved while ripgrep is still searching.
+    /// Failing to save isn't fatal, so errors are only logged.
+    pub fn save(&mut self, app: &App) {
+        let (path, rg_messages) = match (&self.path, app.rg_messages()) {
+            (Some(path), Some(rg_messages)) if self.dirty => (path, rg_messages),
+            _ => return,
+        };
+
+        let session = Session {
+            search_cmdline: app.search_cmdline().to_string(),
+            rg_messages,
+            files: app
+                .fingerprints()
+                .iter()
+                .map(|(path, fingerprint)| (path.clone(), fingerprint.clone()))
+                .collect(),
+            state: app.session_state(),
+        };
+        if let Err(e) = session.save(path) {
+            log::warn!("Failed to save session to {}: {:#}", path.display(), e);
+        }
+
+        self.dirty = false;
+        self.last_save = Instant::now();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::rg::de::test_utilities::*;
+
+    #[test]
+    fn it_saves_and_loads_sessions() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested").join("session.json");
+        let session = Session {
+            search_cmdline: "--regexp=foo".into(),
+            rg_messages: vec![
+                RgMessage::from_str(RG_JSON_BEGIN),
+                RgMessage::from_str(RG_JSON_MATCH),
+                RgMessage::from_str(RG_JSON_END),
+                RgMessage::from_str(RG_JSON_SUMMARY),
+            ],
+            files: vec![],
+            state: SessionState {
+                should_replace: vec![vec![], vec![true, false], vec![]],
+                overrides: vec![(1, 0, "bar".into())],
+                replacement: Some("baz".into()),
+                preserve_case: true,
+                filter: "item".into(),
+                cursor: (1, 1),
+            },
+        };
+        session.save(&path).unwrap();
+
+        let loaded = Session::load(&path).unwrap();
+        assert_eq!(loaded.search_cmdline, session.search_cmdline);
+        assert_eq!(loaded.rg_messages, session.rg_messages);
+        assert_eq!(loaded.state, session.state);
+
+        assert!(Session::load(&dir.path().join("missing.json")).is_err());
+    }
+
+    #[test]
+    fn it_checks_files_are_unchanged() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file.txt");
+        fs::write(&path, "foo").unwrap();
+        let path_data = ArbitraryData::new_with_text(path.to_string_lossy().into());
+
+        let session = Session {
+            search_cmdline: String::new(),
+            rg_messages: vec![],
+            files: vec![(
+                path_data.clone(),
+                FileFingerprint::read(&path_data).unwrap(),
+            )],
+            state: SessionState::default(),
+        };
+        assert_eq!(session.files.len(), 1);
+        assert!(session.check_unchanged().is_ok());
+
+        fs::write(&path, "bar").unwrap();
+        let err = session.check_unchanged().unwrap_err().to_string();
+        assert!(err.contains(&path.display().to_string()), "{}", err);
+
+        fs::remove_file(&path).unwrap();
+        assert!(session.check_unchanged().is_err());
+    }
+}
@@ -1,12 +1,14 @@
 /// Event handling for `App`.
 use anyhow::Result;
-use crossterm::event::{Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::event::{Event, KeyEventKind, MouseButton, MouseEvent, MouseEventKind};
 use either::Either;
 use ratatui::layout::Rect;
 
-use crate::model::Movement;
+use crate::model::{Movement, SelectionRule, TreeNode};
 use crate::rg::de::RgMessageKind;
 use crate::ui::app::{App, AppState, AppUiState};
+use crate::ui::input::edit_line;
+use crate::ui::keymap::{Action, KeymapMode};
 use crate::util::{byte_pos_from_char_pos, clamp};
 
 impl App {
@@ -17,12 +19,15 @@ impl App {
                 self.update_indicator(new_size);
             }
             Event::Key(key) if self.is_frame_too_small(term_size) => {
-                match key.code {
-                    KeyCode::Char('q') | KeyCode::Esc => self.state = AppState::Cancelled,
-                    _ => {}
+                let action = self.keymap.action(KeymapMode::Select, &key);
+                if matches!(action, Some(Action::Quit)) {
+                    self.state = AppState::Cancelled;
                 }
                 return Ok(());
             }
+            Event::Mouse(mouse) if !self.is_frame_too_small(term_size) => {
+                self.on_mouse(term_size, mouse)
+            }
             Event::Key(key) => {
                 // We only care about `Press` events. Other events such as `Release` and `Repeat` aren't
                 // fired on every terminal, and we don't need them anyway.
@@ -30,38 +35,36 @@ impl App {
                     return Ok(());
                 }
 
-                // Common Ctrl+Key scroll keybindings that apply to multiple modes.
-                let control_pressed = key.modifiers.contains(KeyModifiers::CONTROL);
-                let alt_pressed = key.modifiers.contains(KeyModifiers::ALT);
-                if control_pressed {
-                    let did_handle_key = match &self.ui_state {
-                        AppUiState::SelectMatches
+                // Common keybindings that apply to the modes which show the matches list.
+                if matches!(
+                    self.ui_state,
+                    AppUiState::SelectMatches
                         | AppUiState::InputReplacement(_, _)
-                        | AppUiState::ConfirmReplacement(_, _) => match key.code {
-                            // Page movements
-                            KeyCode::Char('b') => {
-                                self.move_pos(
-                                    Movement::Backward(self.main_view_list_rect(term_size).height),
-                                    term_size,
-                                );
-                                true
-                            }
-                            KeyCode::Char('f') => {
-                                self.move_pos(
-                                    Movement::Forward(self.main_view_list_rect(term_size).height),
-                                    term_size,
-                                );
-                                true
-                            }
+                        | AppUiState::ConfirmReplacement(_, _)
+                ) {
+                    let did_handle_key = match self.keymap.action(KeymapMode::All, &key) {
+                        // Page movements
+                        Some(Action::PageUp) => {
+                            self.move_pos(
+                                Movement::Backward(self.main_view_list_rect(term_size).height),
+                                term_size,
+                            );
+                            true
+                        }
+                        Some(Action::PageDown) => {
+                            self.move_pos(
+                                Movement::Forward(self.main_view_list_rect(term_size).height),
+                                term_size,
+                            );
+                            true
+                        }
 
-                            // Toggle whitespace style
-                            KeyCode::Char('v') => {
-                                self.printable_style = self.printable_style.cycle();
-                                self.update_indicator(term_size);
-                                true
-                            }
-                            _ => false,
-                        },
+                        // Toggle whitespace style
+                        Some(Action::CyclePrintableStyle) => {
+                            self.printable_style = self.printable_style.cycle();
+                            self.update_indicator(term_size);
+                            true
+                        }
                         _ => false,
                     };
 
@@ -71,131 +74,168 @@ impl App {
                     }
                 }
 
+                let action = self.keymap.action(self.ui_state.keymap_mode(), &key);
                 match &self.ui_state {
-                    AppUiState::ConfirmReplacement(replacement, pos) => match key.code {
-                        KeyCode::Esc | KeyCode::Char('q') => {
+                    AppUiState::ConfirmReplacement(replacement, pos) => match action {
+                        Some(Action::Cancel) => {
                             self.ui_state =
                                 AppUiState::InputReplacement(replacement.to_owned(), *pos)
                         }
-                        KeyCode::Enter => {
+                        // wait for all the results before writing anything
+                        Some(Action::Accept) | Some(Action::DryRun) if self.is_searching() => {}
+                        Some(Action::Accept) => {
                             self.state = AppState::Complete;
                             return Ok(());
                         }
+                        Some(Action::DryRun) => {
+                            self.dry_run = true;
+                            self.state = AppState::Complete;
+                            return Ok(());
+                        }
+                        _ => {}
+                    },
+                    AppUiState::Help => match action {
+                        Some(Action::Cancel) => self.ui_state = AppUiState::SelectMatches,
+                        Some(Action::ScrollUp) => self.help_text_state.decr(),
+                        Some(Action::ScrollDown) => self.help_text_state.incr(),
                         _ => {}
                     },
-                    AppUiState::Help => match key.code {
-                        KeyCode::Esc | KeyCode::Char('q') => {
-                            self.ui_state = AppUiState::SelectMatches
+                    AppUiState::SelectMatches => match action {
+                        Some(Action::PrevLine) => self.move_pos(Movement::PrevLine, term_size),
+                        Some(Action::NextLine) => self.move_pos(Movement::NextLine, term_size),
+                        Some(Action::PrevFile) => self.move_pos(Movement::PrevFile, term_size),
+                        Some(Action::NextFile) => self.move_pos(Movement::NextFile, term_size),
+                        Some(Action::PrevMatch) => self.move_pos(Movement::Prev, term_size),
+                        Some(Action::NextMatch) => self.move_pos(Movement::Next, term_size),
+                        Some(Action::Toggle) => self.toggle_item(false),
+                        Some(Action::ToggleLine) => self.toggle_item(true),
+                        Some(Action::ToggleAll) => self.toggle_all_items(),
+                        Some(Action::Invert) => self.invert_selection_current(),
+                        Some(Action::InvertAll) => self.invert_selection_all(),
+                        Some(Action::Quit) => self.state = AppState::Cancelled,
+                        Some(Action::Help) => self.ui_state = AppUiState::Help,
+                        Some(Action::EditSearch) => self.edit_search(self.search_cmdline.clone()),
+                        Some(Action::Filter) => {
+                            let pos = self.filter.chars().count();
+                            self.ui_state = AppUiState::EditFilter(self.filter.clone(), pos);
+                        }
+                        Some(Action::Tree) => self.ui_state = AppUiState::SelectTree,
+                        Some(Action::Command) => {
+                            self.ui_state = AppUiState::EditCommand(String::new(), 0)
+                        }
+                        Some(Action::Override) => self.edit_override(),
+                        Some(Action::ClearOverride) => self.set_override(None),
+                        Some(Action::Replace) => {
+                            self.ui_state = AppUiState::InputReplacement(String::new(), 0)
                         }
-                        KeyCode::Char('k') | KeyCode::Up => self.help_text_state.decr(),
-                        KeyCode::Char('j') | KeyCode::Down => self.help_text_state.incr(),
                         _ => {}
                     },
-                    AppUiState::SelectMatches => {
-                        let shift = key.modifiers.contains(KeyModifiers::SHIFT);
-                        match key.code {
-                            KeyCode::Up | KeyCode::Char('k') => self.move_pos(
-                                if shift {
-                                    Movement::PrevFile
-                                } else {
-                                    Movement::PrevLine
-                                },
-                                term_size,
-                            ),
-                            KeyCode::Down | KeyCode::Char('j') => self.move_pos(
-                                if shift {
-                                    Movement::NextFile
-                                } else {
-                                    Movement::NextLine
-                                },
-                                term_size,
-                            ),
-                            KeyCode::Left | KeyCode::Char('h') | KeyCode::Char('H') => {
-                                self.move_pos(Movement::PrevFile, term_size)
-                            }
-                            KeyCode::Right | KeyCode::Char('l') | KeyCode::Char('L') => {
-                                self.move_pos(Movement::NextFile, term_size)
-                            }
-                            KeyCode::Char(' ') | KeyCode::Char(';') => self.toggle_item(false),
-                            KeyCode::Char('s') | KeyCode::Char('S') => self.toggle_item(true),
-                            KeyCode::Char('a') | KeyCode::Char('A') => self.toggle_all_items(),
-                            KeyCode::Char('v') => self.invert_selection_current(),
-                            KeyCode::Char('V') => self.invert_selection_all(),
-                            KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('K') => {
-                                self.state = AppState::Cancelled
-                            }
-                            KeyCode::Char('?') => self.ui_state = AppUiState::Help,
-                            KeyCode::Enter | KeyCode::Char('r') | KeyCode::Char('R') => {
-                                self.ui_state = AppUiState::InputReplacement(String::new(), 0)
-                            }
-                            _ => {}
+                    AppUiState::InputReplacement(ref input, pos) => match action {
+                        // accept replacement
+                        Some(Action::Accept) => {
+                            self.ui_state = AppUiState::ConfirmReplacement(input.to_owned(), *pos);
                         }
-                    }
-                    AppUiState::InputReplacement(ref input, pos) => match key.code {
-                        KeyCode::Char('u') if control_pressed => {
-                            self.ui_state = AppUiState::InputReplacement(
-                                Default::default(),
-                                Default::default(),
-                            );
+                        // toggle preserving the case of each match
+                        Some(Action::PreserveCase) => {
+                            self.preserve_case = !self.preserve_case;
                         }
-                        // input char
-                        KeyCode::Char(ch) => {
+                        // leave mode
+                        Some(Action::Cancel) => self.ui_state = AppUiState::SelectMatches,
+                        // insert return character
+                        Some(Action::Newline) => {
                             let mut new_input = input.clone();
-                            new_input.insert(byte_pos_from_char_pos(input, *pos), ch);
+                            new_input.insert(byte_pos_from_char_pos(input, *pos), '\n');
                             self.ui_state = AppUiState::InputReplacement(new_input, pos + 1);
                         }
-                        // remove character behind cursor
-                        KeyCode::Backspace => {
-                            if !input.is_empty() && *pos > 0 {
-                                let mut new_input = input.clone();
-                                new_input.remove(byte_pos_from_char_pos(input, *pos - 1));
-                                self.ui_state = AppUiState::InputReplacement(new_input, pos - 1);
+                        _ => {
+                            if let Some((input, pos)) = edit_line(input, *pos, &key) {
+                                self.ui_state = AppUiState::InputReplacement(input, pos);
                             }
                         }
-                        // remove character at cursor
-                        KeyCode::Delete => {
-                            if !input.is_empty() && *pos < input.chars().count() {
-                                let mut new_input = input.clone();
-                                new_input.remove(byte_pos_from_char_pos(input, *pos));
-                                self.ui_state = AppUiState::InputReplacement(new_input, *pos);
-                            }
+                    },
+                    AppUiState::InputOverride(ref input, pos) => match action {
+                        // leave mode without changing anything
+                        Some(Action::Cancel) => self.ui_state = AppUiState::SelectMatches,
+                        // accept replacement for the selected match
+                        Some(Action::Accept) => {
+                            let input = input.to_owned();
+                            self.set_override(Some(input));
+                            self.ui_state = AppUiState::SelectMatches;
                         }
-                        // leave mode
-                        KeyCode::Esc => self.ui_state = AppUiState::SelectMatches,
-                        // insert return character
-                        KeyCode::Enter => {
-                            if alt_pressed {
-                                let mut new_input = input.clone();
-                                new_input.insert(byte_pos_from_char_pos(input, *pos), '\n');
-                                self.ui_state = AppUiState::InputReplacement(new_input, pos + 1);
-                            } else {
-                                self.ui_state =
-                                    AppUiState::ConfirmReplacement(input.to_owned(), *pos);
+                        _ => {
+                            if let Some((input, pos)) = edit_line(input, *pos, &key) {
+                                self.ui_state = AppUiState::InputOverride(input, pos);
                             }
                         }
-                        // move cursor back
-                        KeyCode::Left => {
-                            self.ui_state =
-                                AppUiState::InputReplacement(input.clone(), pos.saturating_sub(1))
+                    },
+                    AppUiState::EditFilter(ref input, pos) => match action {
+                        // leave mode and go back to the previous filter
+                        Some(Action::Cancel) => {
+                            self.set_filter(&self.filter.clone(), term_size);
+                            self.ui_state = AppUiState::SelectMatches;
                         }
-                        // move cursor forward
-                        KeyCode::Right => {
-                            self.ui_state = AppUiState::InputReplacement(
-                                input.clone(),
-                                (pos + 1).clamp(0, input.chars().count()),
-                            )
+                        // keep the filter
+                        Some(Action::Accept) => {
+                            self.filter = input.to_owned();
+                            self.ui_state = AppUiState::SelectMatches;
                         }
-                        // move to start
-                        KeyCode::Home | KeyCode::PageUp => {
-                            self.ui_state = AppUiState::InputReplacement(input.clone(), 0)
+                        _ => {
+                            if let Some((input, pos)) = edit_line(input, *pos, &key) {
+                                self.set_filter(&input, term_size);
+                                self.ui_state = AppUiState::EditFilter(input, pos);
+                            }
                         }
-                        // move to end
-                        KeyCode::End | KeyCode::PageDown => {
-                            self.ui_state =
-                                AppUiState::InputReplacement(input.clone(), input.chars().count())
+                    },
+                    AppUiState::SelectTree => match action {
+                        Some(Action::PrevLine) => self.move_tree_row(-1),
+                        Some(Action::NextLine) => self.move_tree_row(1),
+                        Some(Action::Fold) => self.fold_tree_node(),
+                        Some(Action::Unfold) => self.unfold_tree_node(),
+                        Some(Action::Open) => self.open_tree_node(term_size),
+                        Some(Action::Toggle) => self.toggle_tree_node(),
+                        Some(Action::ToggleAll) => self.toggle_all_items(),
+                        Some(Action::Cancel) => self.ui_state = AppUiState::SelectMatches,
+                        Some(Action::Help) => self.ui_state = AppUiState::Help,
+                        Some(Action::Replace) => {
+                            self.ui_state = AppUiState::InputReplacement(String::new(), 0)
                         }
                         _ => {}
                     },
+                    AppUiState::EditCommand(ref input, pos) => match action {
+                        // leave mode
+                        Some(Action::Cancel) => {
+                            self.command_error = None;
+                            self.ui_state = AppUiState::SelectMatches;
+                        }
+                        // run the command, or stay in this mode if it's invalid
+                        Some(Action::Accept) => match SelectionRule::parse(input) {
+                            Ok(rule) => {
+                                rule.apply(&mut self.list);
+                                self.ui_state = AppUiState::SelectMatches;
+                            }
+                            Err(e) => self.command_error = Some(e.to_string()),
+                        },
+                        _ => {
+                            if let Some((input, pos)) = edit_line(input, *pos, &key) {
+                                self.command_error = None;
+                                self.ui_state = AppUiState::EditCommand(input, pos);
+                            }
+                        }
+                    },
+                    AppUiState::EditSearch(ref input, pos) => match action {
+                        // leave mode
+                        Some(Action::Cancel) => self.ui_state = AppUiState::SelectMatches,
+                        // run the search again
+                        Some(Action::Accept) => {
+                            self.search_request = Some(input.to_owned());
+                            self.ui_state = AppUiState::SelectMatches;
+                        }
+                        _ => {
+                            if let Some((input, pos)) = edit_line(input, *pos, &key) {
+                                self.ui_state = AppUiState::EditSearch(input, pos);
+                            }
+                        }
+                    },
                 }
             }
             _ => {}
@@ -233,23 +273,31 @@ impl App {
             }
         };
 
-        // Determine how far to skip down the list.
+        // Determine how far to skip down the list. If there's nowhere to move to, then move to the
+        // first or last item that isn't hidden.
         let selected_item = self.list_state.selected_item();
+        let first_item_idx = self.list.iter().position(|i| !i.is_hidden()).unwrap_or(0);
+        let last_item_idx = self
+            .list
+            .iter()
+            .rposition(|i| !i.is_hidden())
+            .unwrap_or(self.list.len() - 1);
         let (skip, default_item_idx) = match movement {
-            Movement::Prev | Movement::PrevLine | Movement::PrevFile => {
-                (self.list.len().saturating_sub(selected_item), 0)
-            }
+            Movement::Prev | Movement::PrevLine | Movement::PrevFile => (
+                self.list.len().saturating_sub(selected_item),
+                first_item_idx,
+            ),
             Movement::Backward(n) => (
                 self.list
                     .len()
                     .saturating_sub(selected_item.saturating_sub((*n - 1) as usize)),
-                0,
+                first_item_idx,
             ),
 
             Movement::Next | Movement::NextLine | Movement::NextFile => {
-                (selected_item, self.list.len() - 1)
+                (selected_item, last_item_idx)
             }
-            Movement::Forward(n) => (selected_item + (*n as usize), self.list.len() - 1),
+            Movement::Forward(n) => (selected_item + (*n as usize), last_item_idx),
         };
 
         // Find the new position.
@@ -289,13 +337,22 @@ impl App {
     /// Update the UI's indicator position to point to the start of the selected item, and in the case of
     /// a match which spans multiple lines and has multiple submatches, the start of the selected submatch.
     /// Note that this is also the mechanism which scrolls ratatui's list interface.
-    fn update_indicator(&mut self, term_size: Rect) {
+    pub(crate) fn update_indicator(&mut self, term_size: Rect) {
+        // There may not be any items yet if ripgrep is still searching.
+        if self.list.is_empty() {
+            return;
+        }
+
         let item_idx = self.list_state.selected_item();
         let match_idx = self.list_state.selected_submatch();
         let main_view_list_rect = self.main_view_list_rect(term_size);
 
         let mut indicator_idx = 0;
         for item in &mut self.list.as_mut_slice()[0..item_idx] {
+            if item.is_hidden() {
+                continue;
+            }
+
             let item_height = item.line_count(main_view_list_rect.width, self.printable_style);
             indicator_idx += item_height;
         }
@@ -328,6 +385,10 @@ impl App {
     }
 
     pub(crate) fn move_pos(&mut self, movement: Movement, term_size: Rect) {
+        if self.list.is_empty() {
+            return;
+        }
+
         if !self.move_horizontally(&movement) {
             self.move_vertically(&movement);
         }
@@ -335,9 +396,40 @@ impl App {
         self.update_indicator(term_size);
     }
 
+    /// Applies a new filter to the list. If the selected item is hidden by it, then the closest item
+    /// that's still shown is selected instead.
+    fn set_filter(&mut self, filter: &str, term_size: Rect) {
+        self.apply_filter(filter);
+        if self.list.is_empty() {
+            return;
+        }
+
+        let selected_item = self.list_state.selected_item();
+        if !self.list[selected_item].is_selectable() {
+            let item_idx = self.list[selected_item..]
+                .iter()
+                .position(|i| i.is_selectable())
+                .map(|i| selected_item + i)
+                .or_else(|| {
+                    self.list[..selected_item]
+                        .iter()
+                        .rposition(|i| i.is_selectable())
+                })
+                .unwrap_or(selected_item);
+            self.list_state.set_selected_item(item_idx);
+            self.list_state.set_selected_submatch(0);
+        }
+
+        self.list_state.set_window_start(0);
+        self.update_indicator(term_size);
+    }
+
     pub(crate) fn toggle_item(&mut self, all_sub_items: bool) {
         let selected_item = self.list_state.selected_item();
         let selected_match = self.list_state.selected_submatch();
+        if selected_item >= self.list.len() {
+            return;
+        }
 
         // If Match item, toggle replace.
         if matches!(self.list[selected_item].kind, RgMessageKind::Match) {
@@ -363,16 +455,58 @@ impl App {
         }
     }
 
+    /// Starts editing the replacement for only the selected match.
+    fn edit_override(&mut self) {
+        let selected_item = self.list_state.selected_item();
+        if selected_item >= self.list.len()
+            || !matches!(self.list[selected_item].kind, RgMessageKind::Match)
+        {
+            return;
+        }
+
+        let input = self.list[selected_item]
+            .get_replacement(self.list_state.selected_submatch())
+            .unwrap_or_default()
+            .to_owned();
+        let pos = input.chars().count();
+        self.ui_state = AppUiState::InputOverride(input, pos);
+    }
+
+    /// Sets (or clears) the replacement for only the selected match. Setting a replacement also
+    /// selects the match, since it's expected to be replaced.
+    pub(crate) fn set_override(&mut self, replacement: Option<String>) {
+        let selected_item = self.list_state.selected_item();
+        let selected_match = self.list_state.selected_submatch();
+        if selected_item >= self.list.len()
+            || !matches!(self.list[selected_item].kind, RgMessageKind::Match)
+        {
+            return;
+        }
+
+        let item = &mut self.list[selected_item];
+        if replacement.is_some() {
+            item.set_should_replace(selected_match, true);
+        }
+        item.set_replacement(selected_match, replacement);
+    }
+
     pub(crate) fn toggle_all_items(&mut self) {
-        let should_replace = !self.list.iter().all(|i| i.get_should_replace_all());
+        let should_replace = !self
+            .list
+            .iter()
+            .filter(|i| !i.is_hidden())
+            .all(|i| i.get_should_replace_all());
 
-        for item in self.list.iter_mut() {
+        for item in self.list.iter_mut().filter(|i| !i.is_hidden()) {
             item.set_should_replace_all(should_replace);
         }
     }
 
     fn invert_selection_current(&mut self) {
         let selected_item = self.list_state.selected_item();
+        if selected_item >= self.list.len() {
+            return;
+        }
 
         match self.list[selected_item].kind {
             RgMessageKind::Match => self.list[selected_item].invert_selection(),
@@ -390,20 +524,206 @@ impl App {
             .iter_mut()
             .skip(selected_item)
             .take_while(|i| i.kind != RgMessageKind::End)
-            .filter(|i| i.kind == RgMessageKind::Match)
+            .filter(|i| i.kind == RgMessageKind::Match && !i.is_hidden())
             .collect()
     }
 
     fn invert_selection_all(&mut self) {
-        for item in self.list.iter_mut() {
+        for item in self.list.iter_mut().filter(|i| !i.is_hidden()) {
             item.invert_selection();
         }
     }
+
+    /// Returns the selected node of the tree view and its index, if there are any nodes.
+    fn selected_tree_node(&mut self) -> Option<(usize, TreeNode)> {
+        let visible = self.tree_state.visible(&self.list);
+        let row = self
+            .tree_state
+            .selected_row()
+            .min(visible.len().saturating_sub(1));
+        let node_idx = *visible.get(row)?;
+        let node = self.tree_state.tree().nodes()[node_idx].clone();
+        Some((node_idx, node))
+    }
+
+    fn on_mouse(&mut self, term_size: Rect, mouse: MouseEvent) {
+        let is_list = matches!(
+            self.ui_state,
+            AppUiState::SelectMatches
+                | AppUiState::InputReplacement(_, _)
+                | AppUiState::ConfirmReplacement(_, _)
+        );
+
+        match (&self.ui_state, mouse.kind) {
+            (AppUiState::Help, MouseEventKind::ScrollUp) => self.help_text_state.decr(),
+            (AppUiState::Help, MouseEventKind::ScrollDown) => self.help_text_state.incr(),
+            (AppUiState::SelectTree, MouseEventKind::ScrollUp) => self.move_tree_row(-1),
+            (AppUiState::SelectTree, MouseEventKind::ScrollDown) => self.move_tree_row(1),
+            (AppUiState::SelectTree, MouseEventKind::Down(MouseButton::Left)) => {
+                self.click_tree_row(term_size, mouse.row)
+            }
+            (_, MouseEventKind::ScrollUp) if is_list => {
+                self.move_pos(Movement::PrevLine, term_size)
+            }
+            (_, MouseEventKind::ScrollDown) if is_list => {
+                self.move_pos(Movement::NextLine, term_size)
+            }
+            // matches can only be toggled while selecting them, the cursor can always be moved
+            (AppUiState::SelectMatches, MouseEventKind::Down(MouseButton::Left)) => {
+                self.click_list(term_size, mouse.column, mouse.row, true)
+            }
+            (_, MouseEventKind::Down(MouseButton::Left)) if is_list => {
+                self.click_list(term_size, mouse.column, mouse.row, false)
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns the index of the item drawn at `line` of the whole list, and the row within that
+    /// item. Lines are counted the same way as in `update_indicator`.
+    fn item_at_line(&mut self, line: usize, list_width: u16) -> Option<(usize, usize)> {
+        let mut start = 0;
+        for (idx, item) in self.list.iter_mut().enumerate() {
+            if item.is_hidden() {
+                continue;
+            }
+
+            let item_height = item.line_count(list_width, self.printable_style);
+            if line < start + item_height {
+                return Some((idx, line - start));
+            }
+            start += item_height;
+        }
+
+        None
+    }
+
+    /// Moves the cursor to the item and submatch at the clicked position. If `toggle` is set and
+    /// they're already selected, then they're toggled instead.
+    fn click_list(&mut self, term_size: Rect, column: u16, row: u16, toggle: bool) {
+        let list_rect = self.main_view_list_rect(term_size);
+        if row < list_rect.top() || row >= list_rect.bottom() {
+            return;
+        }
+
+        let line = self.list_state.window_start() + (row - list_rect.top()) as usize;
+        let (item_idx, item_row) = match self.item_at_line(line, list_rect.width) {
+            Some(found) => found,
+            None => return,
+        };
+        if !self.list[item_idx].is_selectable() {
+            return;
+        }
+
+        // clicks on the indicator count as clicks on the start of the line
+        let col = column.saturating_sub(list_rect.left()) as usize;
+        let sub_idx =
+            self.list[item_idx].sub_item_at(item_row, col, list_rect.width, self.printable_style);
+        if toggle
+            && item_idx == self.list_state.selected_item()
+            && sub_idx == self.list_state.selected_submatch()
+        {
+            self.toggle_item(false);
+        } else {
+            self.list_state.set_selected_item(item_idx);
+            self.list_state.set_selected_submatch(sub_idx);
+        }
+
+        self.update_indicator(term_size);
+    }
+
+    /// Selects the clicked row of the tree, or toggles it if it's already selected.
+    fn click_tree_row(&mut self, term_size: Rect, row: u16) {
+        let rect = self.main_view_rect(term_size);
+        if row < rect.top() || row >= rect.bottom() {
+            return;
+        }
+
+        let clicked_row = self.tree_state.rows_offset() + (row - rect.top()) as usize;
+        if clicked_row >= self.tree_state.visible(&self.list).len() {
+            return;
+        }
+
+        if clicked_row == self.tree_state.selected_row() {
+            self.toggle_tree_node();
+        } else {
+            self.tree_state.set_selected_row(clicked_row);
+        }
+    }
+
+    fn move_tree_row(&mut self, delta: isize) {
+        let row_count = self.tree_state.visible(&self.list).len();
+        let row = (self.tree_state.selected_row() as isize + delta).max(0) as usize;
+        self.tree_state
+            .set_selected_row(clamp(row, 0, row_count.saturating_sub(1)));
+    }
+
+    /// Folds the selected directory, or moves to the parent directory if it's already folded.
+    fn fold_tree_node(&mut self) {
+        let (node_idx, node) = match self.selected_tree_node() {
+            Some(selected) => selected,
+            None => return,
+        };
+
+        if node.is_dir() && !self.tree_state.is_folded(&node.path) {
+            self.tree_state.set_folded(node.path, true);
+        } else if let Some(parent_idx) = self.tree_state.tree().parent(node_idx) {
+            let visible = self.tree_state.visible(&self.list);
+            let row = visible.iter().position(|&i| i == parent_idx).unwrap_or(0);
+            self.tree_state.set_selected_row(row);
+        }
+    }
+
+    fn unfold_tree_node(&mut self) {
+        if let Some((_, node)) = self.selected_tree_node() {
+            self.tree_state.set_folded(node.path, false);
+        }
+    }
+
+    /// Folds or unfolds the selected directory, or shows the selected file in the matches list.
+    fn open_tree_node(&mut self, term_size: Rect) {
+        let (_, node) = match self.selected_tree_node() {
+            Some(selected) => selected,
+            None => return,
+        };
+
+        match node.begin_idx {
+            Some(begin_idx) => {
+                self.list_state.set_selected_item(begin_idx);
+                self.list_state.set_selected_submatch(0);
+                self.update_indicator(term_size);
+                self.ui_state = AppUiState::SelectMatches;
+            }
+            None => {
+                let folded = self.tree_state.is_folded(&node.path);
+                self.tree_state.set_folded(node.path, !folded);
+            }
+        }
+    }
+
+    /// Toggles all the matches under the selected node of the tree view.
+    fn toggle_tree_node(&mut self) {
+        let (_, node) = match self.selected_tree_node() {
+            Some(selected) => selected,
+            None => return,
+        };
+
+        let should_replace = !node
+            .matches
+            .iter()
+            .all(|&i| self.list[i].get_should_replace_all());
+        for i in node.matches {
+            self.list[i].set_should_replace_all(should_replace);
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+    use crossterm::event::{
+        Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseButton, MouseEvent,
+        MouseEventKind,
+    };
     use pretty_assertions::assert_eq;
     use ratatui::layout::Rect;
 
@@ -1037,4 +1357,454 @@ mod tests {
         // and back to input
         send_key_assert!(app, key!(Enter), "", 0);
     }
+
+    #[test]
+    fn confirm_replacement_dry_run() {
+        let mut app = new_app();
+        send_key_assert!(app, key!(Enter), "", 0);
+        send_key_assert!(app, key!(Char('a')), "a", 1);
+        send_key!(app, key!(Char('s'), modifiers = KeyModifiers::CONTROL));
+        assert_eq!(app.ui_state, AppUiState::ConfirmReplacement("a".into(), 1));
+
+        send_key!(app, key!(Char('d')));
+        assert!(matches!(app.state, AppState::Complete));
+        assert!(app.get_replacement_criteria().unwrap().dry_run);
+    }
+
+    #[test]
+    fn streaming_results() {
+        let mut app = App::new(None, "TESTS".to_string(), vec![]);
+        assert!(app.is_searching());
+
+        // nothing to move to or toggle yet
+        send_key!(app, key!(Char('j')));
+        send_key!(app, key!(Char(' ')));
+        send_key!(app, key!(Char('v')));
+        send_key!(app, Event::Resize(100, 30));
+        assert_eq!(app.list_state.selected_item(), 0);
+
+        let mut messages = rg_messages();
+        let summary = messages.pop().unwrap();
+        for message in messages {
+            app.add_rg_message(message);
+        }
+        assert!(app.is_searching());
+        assert_eq!(app.list.len(), 6);
+
+        // can navigate and toggle while searching
+        send_key!(app, key!(Char('j')));
+        assert_eq!(app.list_state.selected_item(), 1);
+        send_key!(app, key!(Char(' ')));
+        assert!(!app.list[1].get_should_replace(0));
+
+        // can't write replacements until the search has finished
+        send_key_assert!(app, key!(Enter), "", 0);
+        send_key!(app, key!(Char('s'), modifiers = KeyModifiers::CONTROL));
+        send_key!(app, key!(Enter));
+        assert!(matches!(app.state, AppState::Running));
+
+        app.add_rg_message(summary);
+        assert!(!app.is_searching());
+        send_key!(app, key!(Enter));
+        assert!(matches!(app.state, AppState::Complete));
+    }
+
+    #[test]
+    fn edit_search() {
+        let mut app = new_app();
+        app.set_search_cmdline("foo".into());
+
+        send_key!(app, key!(Char('e')));
+        assert_eq!(app.ui_state, AppUiState::EditSearch("foo".into(), 3));
+        send_key!(app, key!(Char('d')));
+        send_key!(app, key!(Home));
+        send_key!(app, key!(Char('-')));
+        send_key!(app, key!(Char('i')));
+        send_key!(app, key!(Char(' ')));
+        assert_eq!(app.ui_state, AppUiState::EditSearch("-i food".into(), 3));
+
+        // cancel
+        send_key!(app, key!(Esc));
+        assert_eq!(app.ui_state, AppUiState::SelectMatches);
+        assert_eq!(app.take_search_request(), None);
+
+        // accept
+        send_key!(app, key!(Char('e')));
+        send_key!(app, key!(Char('d')));
+        send_key!(app, key!(Enter));
+        assert_eq!(app.ui_state, AppUiState::SelectMatches);
+        assert_eq!(app.take_search_request(), Some("food".into()));
+        assert_eq!(app.take_search_request(), None);
+    }
+
+    #[test]
+    fn restart_search_keeps_deselected_matches() {
+        let mut app = new_app();
+        // deselect the second submatch of the first match
+        app.list[1].set_should_replace(1, false);
+
+        app.restart_search(None, false, "NEW".into(), "NEW".into());
+        assert!(app.is_searching());
+        assert!(app.list.is_empty());
+
+        for message in rg_messages() {
+            app.add_rg_message(message);
+        }
+
+        // both matches are at the same path and offset, but only the first was deselected
+        assert!(!app.is_searching());
+        assert!(app.list[1].get_should_replace(0));
+        assert!(!app.list[1].get_should_replace(1));
+        assert!(app.list[3].get_should_replace(0));
+        assert!(!app.list[3].get_should_replace(1));
+
+        // once the new search is complete, there's nothing to go back to
+        assert!(!app.restore_previous_search());
+    }
+
+    #[test]
+    fn restore_previous_search() {
+        let mut app = new_app();
+        app.list[1].set_should_replace(0, false);
+
+        app.restart_search(None, false, "NEW".into(), "NEW".into());
+        app.add_rg_message(RgMessage::from_str(RG_JSON_BEGIN));
+        assert_eq!(app.rg_cmdline, "NEW");
+
+        assert!(app.restore_previous_search());
+        assert!(!app.is_searching());
+        assert_eq!(app.rg_cmdline, "TESTS");
+        assert_eq!(app.list.len(), 6);
+        assert!(!app.list[1].get_should_replace(0));
+    }
+
+    #[test]
+    fn override_replacement() {
+        let mut app = new_app();
+        app.list[1].set_should_replace(0, false);
+
+        // only matches can have their own replacement
+        send_key!(app, key!(Char('o')));
+        assert_eq!(app.ui_state, AppUiState::SelectMatches);
+
+        send_key!(app, key!(Char('j')));
+        send_key!(app, key!(Char('o')));
+        assert_eq!(app.ui_state, AppUiState::InputOverride("".into(), 0));
+        send_key!(app, key!(Char('x')));
+        send_key!(app, key!(Char('y')));
+
+        // cancel
+        send_key!(app, key!(Esc));
+        assert_eq!(app.ui_state, AppUiState::SelectMatches);
+        assert_eq!(app.list[1].get_replacement(0), None);
+
+        // accept, which also selects the match
+        send_key!(app, key!(Char('o')));
+        send_key!(app, key!(Char('x')));
+        send_key!(app, key!(Enter));
+        assert_eq!(app.ui_state, AppUiState::SelectMatches);
+        assert_eq!(app.list[1].get_replacement(0), Some("x"));
+        assert_eq!(app.list[1].get_replacement(1), None);
+        assert!(app.list[1].get_should_replace(0));
+
+        // editing starts with the current replacement
+        send_key!(app, key!(Char('o')));
+        assert_eq!(app.ui_state, AppUiState::InputOverride("x".into(), 1));
+        send_key!(app, key!(Esc));
+
+        // clear
+        send_key!(app, key!(Char('O')));
+        assert_eq!(app.list[1].get_replacement(0), None);
+
+        // move to the second match on the line
+        send_key!(app, key!(Char('w')));
+        assert_eq!(app.list_state.selected_submatch(), 1);
+        send_key!(app, key!(Char('o')));
+        send_key!(app, key!(Char('y')));
+        send_key!(app, key!(Enter));
+        assert_eq!(app.list[1].get_replacement(0), None);
+        assert_eq!(app.list[1].get_replacement(1), Some("y"));
+
+        // and back to the first
+        send_key!(app, key!(Char('b')));
+        assert_eq!(app.list_state.selected_item(), 1);
+        assert_eq!(app.list_state.selected_submatch(), 0);
+    }
+
+    #[test]
+    fn toggle_preserve_case() {
+        let mut app = new_app();
+        send_key_assert!(app, key!(Enter), "", 0);
+        send_key_assert!(app, key!(Char('p')), "p", 1);
+        assert!(!app.preserve_case);
+
+        send_key_assert!(app, key!(Char('p'), modifiers = KeyModifiers::ALT), "p", 1);
+        assert!(app.preserve_case);
+        send_key!(app, key!(Char('s'), modifiers = KeyModifiers::CONTROL));
+        assert!(app.get_replacement_criteria().unwrap().preserve_case);
+    }
+
+    #[test]
+    fn filter_matches() {
+        let mut app = new_app_multiple_files();
+        send_key!(app, key!(Char('/')));
+        assert_eq!(app.ui_state, AppUiState::EditFilter("".into(), 0));
+
+        // the filter is applied while typing
+        send_key!(app, key!(Char('b')));
+        send_key!(app, key!(Char('a')));
+        send_key!(app, key!(Char('z')));
+        assert_eq!(app.ui_state, AppUiState::EditFilter("baz".into(), 3));
+        let shown = app
+            .list
+            .iter()
+            .enumerate()
+            .filter(|(_, i)| !i.is_hidden())
+            .map(|(idx, _)| idx)
+            .collect::<Vec<_>>();
+        assert_eq!(shown, vec![6, 7, 8, 15, 16, 17]);
+
+        // the selection moves to the first item that's shown
+        assert_eq!(app.list_state.selected_item(), 6);
+        assert_eq!(app.list_state.indicator_mut().selected(), Some(0));
+
+        send_key!(app, key!(Enter));
+        assert_eq!(app.ui_state, AppUiState::SelectMatches);
+        assert_eq!(app.filter, "baz");
+
+        // movement skips hidden items
+        send_key!(app, key!(Char('j')));
+        assert_eq!(app.list_state.selected_item(), 7);
+        send_key!(app, key!(Char('j')));
+        assert_eq!(app.list_state.selected_item(), 15);
+        assert_eq!(app.list_state.indicator_mut().selected(), Some(5));
+
+        // toggling only affects the items that are shown
+        send_key!(app, key!(Char('a')));
+        assert_eq!(
+            app_list_to_match_replace(&app),
+            vec![true, true, false, true, true, false]
+        );
+        send_key!(app, key!(Char('V')));
+        assert_eq!(app_list_to_match_replace(&app), vec![true; 6]);
+
+        // cancelling goes back to the previous filter
+        send_key!(app, key!(Char('/')));
+        send_key!(app, key!(Backspace));
+        send_key!(app, key!(Backspace));
+        send_key!(app, key!(Backspace));
+        assert!(app.list.iter().all(|i| !i.is_hidden()));
+        send_key!(app, key!(Esc));
+        assert_eq!(app.filter, "baz");
+        assert!(app.list[0].is_hidden());
+
+        // an empty filter shows everything
+        send_key!(app, key!(Char('/')));
+        send_key!(app, key!(Char('u'), modifiers = KeyModifiers::CONTROL));
+        send_key!(app, key!(Enter));
+        assert_eq!(app.filter, "");
+        assert!(app.list.iter().all(|i| !i.is_hidden()));
+    }
+
+    #[test]
+    fn select_matches_by_glob() {
+        // matches are in "src/model/item.rs" and "./foo/baz"
+        let mut app = new_app_multiple_files();
+        send_key!(app, key!(Char(':')));
+        assert_eq!(app.ui_state, AppUiState::EditCommand("".into(), 0));
+        for c in "exclude foo/*".chars() {
+            send_key!(app, key!(Char(c)));
+        }
+        send_key!(app, key!(Enter));
+        assert_eq!(app.ui_state, AppUiState::SelectMatches);
+        assert_eq!(
+            app_list_to_match_replace(&app),
+            vec![true, true, false, true, true, false]
+        );
+
+        send_key!(app, key!(Char(':')));
+        for c in "only *.rs".chars() {
+            send_key!(app, key!(Char(c)));
+        }
+        send_key!(app, key!(Enter));
+        assert_eq!(
+            app_list_to_match_replace(&app),
+            vec![true, true, false, true, true, false]
+        );
+
+        send_key!(app, key!(Char(':')));
+        for c in "include **/baz".chars() {
+            send_key!(app, key!(Char(c)));
+        }
+        send_key!(app, key!(Enter));
+        assert_eq!(app_list_to_match_replace(&app), vec![true; 6]);
+
+        // invalid commands aren't run
+        send_key!(app, key!(Char(':')));
+        for c in "only".chars() {
+            send_key!(app, key!(Char(c)));
+        }
+        send_key!(app, key!(Enter));
+        assert_eq!(app.ui_state, AppUiState::EditCommand("only".into(), 4));
+        assert!(app.command_error.is_some());
+        send_key!(app, key!(Char(' ')));
+        assert!(app.command_error.is_none());
+        send_key!(app, key!(Esc));
+        assert_eq!(app.ui_state, AppUiState::SelectMatches);
+        assert_eq!(app_list_to_match_replace(&app), vec![true; 6]);
+    }
+
+    #[test]
+    fn tree_view() {
+        let mut messages = rg_messages();
+        messages.pop();
+        messages.extend([
+            RgMessage::from_str(&RG_JSON_BEGIN.replace("src/model/item.rs", "./foo/baz")),
+            RgMessage::from_str(RG_JSON_MATCH_MULTILINE),
+            RgMessage::from_str(&RG_JSON_END.replace("src/model/item.rs", "./foo/baz")),
+            RgMessage::from_str(RG_JSON_SUMMARY),
+        ]);
+        let mut app = App::new(None, "TESTS".to_string(), messages);
+
+        // the tree is: foo/baz, src/model/item.rs
+        send_key!(app, key!(Char('t')));
+        assert_eq!(app.ui_state, AppUiState::SelectTree);
+        assert_eq!(app.tree_state.visible(&app.list), vec![0, 1, 2, 3, 4]);
+
+        // toggle a file
+        send_key!(app, key!(Char('j')));
+        send_key!(app, key!(Char(' ')));
+        assert_eq!(app_list_to_match_replace(&app), vec![true, true, false]);
+
+        // fold a directory from inside it
+        send_key!(app, key!(Char('h')));
+        assert_eq!(app.tree_state.selected_row(), 0);
+        send_key!(app, key!(Char('h')));
+        assert_eq!(app.tree_state.visible(&app.list), vec![0, 2, 3, 4]);
+
+        // toggle a directory
+        send_key!(app, key!(Char('j')));
+        send_key!(app, key!(Char(' ')));
+        assert_eq!(app_list_to_match_replace(&app), vec![false; 3]);
+        send_key!(app, key!(Char(' ')));
+        assert_eq!(app_list_to_match_replace(&app), vec![true, true, false]);
+
+        send_key!(app, key!(Enter));
+        assert_eq!(app.tree_state.visible(&app.list), vec![0, 2]);
+        send_key!(app, key!(Char('l')));
+        assert_eq!(app.tree_state.visible(&app.list), vec![0, 2, 3, 4]);
+
+        // show a file in the list
+        send_key!(app, key!(Char('j')));
+        send_key!(app, key!(Char('j')));
+        send_key!(app, key!(Char('j')));
+        assert_eq!(app.tree_state.selected_row(), 3);
+        send_key!(app, key!(Enter));
+        assert_eq!(app.ui_state, AppUiState::SelectMatches);
+        assert_eq!(app.list_state.selected_item(), 0);
+    }
+
+    #[test]
+    fn remapped_keys() {
+        let mut app = new_app();
+        let config = crate::config::Config::parse(
+            "[keys.select]\nnext_line = \"n\"\nprev_line = \"p\"\n[keys.replace]\nback = \"ctrl+g\"",
+        )
+        .unwrap();
+        app.set_keymap(config.keymap);
+
+        send_key!(app, key!(Char('n')));
+        assert_eq!(app.list_state.selected_item(), 1);
+        send_key!(app, key!(Char('j')));
+        assert_eq!(app.list_state.selected_item(), 1);
+        send_key!(app, key!(Char('n')));
+        send_key!(app, key!(Char('p')));
+        assert_eq!(app.list_state.selected_item(), 1);
+
+        // keys which aren't bound any more are typed in text inputs
+        send_key_assert!(app, key!(Enter), "", 0);
+        send_key_assert!(app, key!(Esc), "", 0);
+        send_key!(app, key!(Char('g'), modifiers = KeyModifiers::CONTROL));
+        assert_eq!(app.ui_state, AppUiState::SelectMatches);
+    }
+
+    #[test]
+    fn mouse() {
+        let mut app = new_app();
+        let term_size = Rect::new(0, 0, 80, 24);
+        let list_rect = app.main_view_list_rect(term_size);
+        let mouse = |app: &mut App, kind, column: u16, row: u16| {
+            let event = MouseEvent {
+                kind,
+                column: list_rect.x + column,
+                row: list_rect.y + row,
+                modifiers: KeyModifiers::empty(),
+            };
+            app.on_event(term_size, Event::Mouse(event)).unwrap();
+        };
+        let click = MouseEventKind::Down(MouseButton::Left);
+
+        // "197:    Item::new(rg_msg)" with submatches at columns 8 and 18
+        mouse(&mut app, click, 20, 1);
+        assert_eq!(app.list_state.selected_item(), 1);
+        assert_eq!(app.list_state.selected_submatch(), 1);
+        assert!(app.list[1].get_should_replace(1));
+
+        // clicking the selected submatch toggles it
+        mouse(&mut app, click, 20, 1);
+        assert!(!app.list[1].get_should_replace(1));
+        mouse(&mut app, click, 9, 1);
+        assert_eq!(app.list_state.selected_submatch(), 0);
+        assert!(app.list[1].get_should_replace(0));
+
+        // context lines can't be selected
+        mouse(&mut app, click, 0, 2);
+        assert_eq!(app.list_state.selected_item(), 1);
+
+        mouse(&mut app, MouseEventKind::ScrollDown, 0, 0);
+        assert_eq!(app.list_state.selected_item(), 3);
+        mouse(&mut app, MouseEventKind::ScrollUp, 0, 0);
+        mouse(&mut app, MouseEventKind::ScrollUp, 0, 0);
+        assert_eq!(app.list_state.selected_item(), 0);
+
+        // clicks only move the cursor while replacing
+        app.ui_state = AppUiState::InputReplacement(String::new(), 0);
+        mouse(&mut app, click, 0, 3);
+        mouse(&mut app, click, 0, 3);
+        assert_eq!(app.list_state.selected_item(), 3);
+        assert!(app.list[3].get_should_replace(0));
+    }
+
+    #[test]
+    fn session_state() {
+        let term_size = Rect::new(0, 0, 80, 24);
+        let mut app = new_app_multiple_files();
+        app.list[3].set_should_replace(1, false);
+        app.list[7].set_replacement(0, Some("override".into()));
+        app.move_pos(Movement::Forward(4), term_size);
+        app.ui_state = AppUiState::InputReplacement("replacement".into(), 0);
+        app.preserve_case = true;
+        let state = app.session_state();
+
+        let mut resumed = new_app_multiple_files();
+        resumed.restore_session_state(&state, term_size);
+        assert_eq!(resumed.session_state(), state);
+        assert_eq!(
+            (
+                resumed.list_state.selected_item(),
+                resumed.list_state.selected_submatch()
+            ),
+            (
+                app.list_state.selected_item(),
+                app.list_state.selected_submatch()
+            )
+        );
+        assert!(!resumed.list[3].get_should_replace(1));
+        assert_eq!(resumed.list[7].get_replacement(0), Some("override"));
+        assert_eq!(
+            resumed.ui_state,
+            AppUiState::InputReplacement("replacement".into(), 11)
+        );
+        assert_eq!(resumed.rg_messages(), app.rg_messages());
+    }
 }
@@ -2,14 +2,16 @@
 use const_format::formatcp;
 use ratatui::backend::Backend;
 use ratatui::layout::{Alignment, Constraint, Direction, Layout, Rect};
-use ratatui::style::{Color, Modifier, Style};
 use ratatui::text::{Line, Span, Text};
 use ratatui::widgets::{Block, Borders, List, ListItem, Paragraph, Row, Table, Wrap};
 use ratatui::Frame;
+use unicode_width::UnicodeWidthStr;
 
-use crate::model::Printable;
+use crate::model::{MatchCounts, Printable};
 use crate::rg::de::RgMessageKind;
 use crate::ui::app::{App, AppUiState};
+use crate::ui::keymap::{Action, KeymapMode};
+use crate::ui::preview::preview_lines;
 use crate::ui::render::UiItemContext;
 use crate::util::byte_pos_from_char_pos;
 
@@ -30,6 +32,7 @@ impl App {
     // | - list
     // | - of
     // | - matches
+    // | preview of the selected match (only while replacing)
     // | status line (rg command line, matches, replacements, etc)
     // | command line (user input for replacement text, etc)
     // _
@@ -40,25 +43,42 @@ impl App {
         }
 
         let (root_split, stats_and_input_split) = self.get_layouts(frame);
-        if matches!(self.ui_state, AppUiState::Help) {
-            self.draw_help_view(f, root_split[0]);
-        } else {
-            self.draw_main_view(f, root_split[0]);
+        match self.ui_state {
+            AppUiState::Help => self.draw_help_view(f, root_split[0]),
+            AppUiState::SelectTree => self.draw_tree_view(f, root_split[0]),
+            _ => self.draw_main_view(f, root_split[0]),
+        }
+        if self.ui_state.is_replacing() {
+            self.draw_preview_view(f, root_split[1]);
         }
         self.draw_stats_line(f, stats_and_input_split[0]);
         self.draw_input_line(f, stats_and_input_split[1]);
     }
 
     fn get_layouts(&self, r: Rect) -> (Vec<Rect>, Vec<Rect>) {
-        let root_split = Layout::default()
-            .direction(Direction::Vertical)
-            .constraints([Constraint::Min(1), Constraint::Length(2)].as_ref())
-            .split(r);
+        let root_split = if self.ui_state.is_replacing() {
+            Layout::default()
+                .direction(Direction::Vertical)
+                .constraints(
+                    [
+                        Constraint::Min(1),
+                        Constraint::Percentage(40),
+                        Constraint::Length(2),
+                    ]
+                    .as_ref(),
+                )
+                .split(r)
+        } else {
+            Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Min(1), Constraint::Length(2)].as_ref())
+                .split(r)
+        };
 
         let stats_and_input_split = Layout::default()
             .direction(Direction::Vertical)
             .constraints([Constraint::Length(1), Constraint::Length(1)].as_ref())
-            .split(root_split[1]);
+            .split(root_split[root_split.len() - 1]);
 
         (root_split.to_vec(), stats_and_input_split.to_vec())
     }
@@ -73,21 +93,73 @@ impl App {
     }
 
     fn draw_input_line<B: Backend>(&mut self, f: &mut Frame<B>, r: Rect) {
-        let prefix = "Replacement: ";
+        let key_for = |mode, action| self.keymap.hint(mode, action);
+        let (prefix, hint) = match &self.ui_state {
+            AppUiState::EditSearch(_, _) => (
+                "Search: rg ",
+                format!(
+                    "    (press {} to search again)",
+                    key_for(KeymapMode::Search, Action::Accept)
+                ),
+            ),
+            AppUiState::EditCommand(_, _) => (
+                "Command: ",
+                "    (include, exclude or only, followed by globs, e.g., exclude tests/**)"
+                    .to_string(),
+            ),
+            AppUiState::EditFilter(_, _) => (
+                "Filter: ",
+                format!(
+                    "    (press {} to keep the filter, {} to cancel)",
+                    key_for(KeymapMode::Filter, Action::Accept),
+                    key_for(KeymapMode::Filter, Action::Cancel)
+                ),
+            ),
+            AppUiState::InputOverride(_, _) => (
+                "Replacement for this match: ",
+                format!(
+                    "    (press {} to accept replacement)",
+                    key_for(KeymapMode::Override, Action::Accept)
+                ),
+            ),
+            _ if self.preserve_case => (
+                "Replacement (preserving case): ",
+                format!(
+                    "    (press {} to accept replacement, {} to stop preserving case)",
+                    key_for(KeymapMode::Replace, Action::Accept),
+                    key_for(KeymapMode::Replace, Action::PreserveCase)
+                ),
+            ),
+            _ => (
+                "Replacement: ",
+                format!(
+                    "    (press {} to accept replacement, {} to preserve case)",
+                    key_for(KeymapMode::Replace, Action::Accept),
+                    key_for(KeymapMode::Replace, Action::PreserveCase)
+                ),
+            ),
+        };
         let mut spans = match &self.ui_state {
-            AppUiState::Help => vec![Span::from("Viewing Help. Press <esc> or <q> to return...")],
-            AppUiState::SelectMatches => vec![Span::from(
-                "Select (or deselect) Matches with <space> then press <Enter>. Press <?> for help.",
-            )],
-            AppUiState::InputReplacement(input, pos) => {
+            AppUiState::Help => vec![Span::from(format!(
+                "Viewing Help. Press {} to return...",
+                key_for(KeymapMode::Help, Action::Cancel)
+            ))],
+            AppUiState::SelectMatches => vec![Span::from(format!(
+                "Select (or deselect) Matches with {} then press {}. Press {} for help.",
+                key_for(KeymapMode::Select, Action::Toggle),
+                key_for(KeymapMode::Select, Action::Replace),
+                key_for(KeymapMode::Select, Action::Help)
+            ))],
+            AppUiState::InputReplacement(input, pos)
+            | AppUiState::EditSearch(input, pos)
+            | AppUiState::InputOverride(input, pos)
+            | AppUiState::EditFilter(input, pos)
+            | AppUiState::EditCommand(input, pos) => {
                 let mut spans = vec![Span::from(prefix)];
                 if input.is_empty() {
-                    spans.push(Span::styled(
-                        "<empty>",
-                        Style::default().fg(Color::DarkGray),
-                    ));
+                    spans.push(Span::styled("<empty>", self.theme.hint));
                 } else {
-                    let (before, after) = input.split_at(byte_pos_from_char_pos(&input, *pos));
+                    let (before, after) = input.split_at(byte_pos_from_char_pos(input, *pos));
                     let style = self.printable_style.as_one_line();
                     spans.push(Span::from(before.to_printable(style)));
                     spans.push(Span::from(after.to_printable(style)));
@@ -95,26 +167,55 @@ impl App {
 
                 spans
             }
-            AppUiState::ConfirmReplacement(_, _) => vec![Span::from(
-                "Press <enter> to write changes, <esc> to cancel.",
-            )],
+            AppUiState::SelectTree => vec![Span::from(format!(
+                "Toggle with {}, fold with {} and {}, show a file with {}. Press {} to return.",
+                key_for(KeymapMode::Tree, Action::Toggle),
+                key_for(KeymapMode::Tree, Action::Fold),
+                key_for(KeymapMode::Tree, Action::Unfold),
+                key_for(KeymapMode::Tree, Action::Open),
+                key_for(KeymapMode::Tree, Action::Cancel)
+            ))],
+            AppUiState::ConfirmReplacement(_, _) if self.is_searching() => {
+                vec![Span::from(format!(
+                    "Waiting for rg to finish searching... Press {} to cancel.",
+                    key_for(KeymapMode::Confirm, Action::Cancel)
+                ))]
+            }
+            AppUiState::ConfirmReplacement(_, _) if self.dry_run => vec![Span::from(format!(
+                "Press {} to print a diff of the changes, {} to cancel.",
+                key_for(KeymapMode::Confirm, Action::Accept),
+                key_for(KeymapMode::Confirm, Action::Cancel)
+            ))],
+            AppUiState::ConfirmReplacement(_, _) => vec![Span::from(format!(
+                "Press {} to write changes, {} to print a diff instead, {} to cancel.",
+                key_for(KeymapMode::Confirm, Action::Accept),
+                key_for(KeymapMode::Confirm, Action::DryRun),
+                key_for(KeymapMode::Confirm, Action::Cancel)
+            ))],
         };
 
         let mut render_input = |spans| f.render_widget(Paragraph::new(Line::from(spans)), r);
 
         // Draw input cursor after rendering input
-        if let AppUiState::InputReplacement(input, _) = &self.ui_state {
+        if let AppUiState::InputReplacement(input, _)
+        | AppUiState::EditSearch(input, _)
+        | AppUiState::InputOverride(input, _)
+        | AppUiState::EditFilter(input, _)
+        | AppUiState::EditCommand(input, _) = &self.ui_state
+        {
             let x_start = r.x + (prefix.len() as u16);
             let x_pos = if input.is_empty() {
                 0
             } else {
-                (&spans[spans.len() - 2]).width() as u16
+                spans[spans.len() - 2].width() as u16
             };
 
-            spans.push(Span::styled(
-                "    (press <control+s> to accept replacement)",
-                Style::default().fg(Color::DarkGray),
-            ));
+            match &self.command_error {
+                Some(error) => {
+                    spans.push(Span::styled(format!("    ({})", error), self.theme.error))
+                }
+                None => spans.push(Span::styled(hint, self.theme.hint)),
+            }
 
             render_input(spans);
             f.set_cursor(x_start + x_pos, r.y);
@@ -124,17 +225,21 @@ impl App {
     }
 
     fn draw_stats_line<B: Backend>(&mut self, f: &mut Frame<B>, r: Rect) {
-        let replacement_count = self
+        let (replacement_count, match_count) = self
             .list
             .iter()
-            .filter_map(|i| {
-                if matches!(i.kind, RgMessageKind::Match) {
-                    Some(i.replace_count())
-                } else {
-                    None
-                }
-            })
-            .sum::<usize>();
+            .filter(|i| matches!(i.kind, RgMessageKind::Match))
+            .fold((0, 0), |(replacements, matches), i| {
+                (
+                    replacements + i.replace_count(),
+                    matches + i.sub_items().len(),
+                )
+            });
+        let match_count = self
+            .stats
+            .as_ref()
+            .map(|stats| stats.matches)
+            .unwrap_or(match_count);
 
         // Split the stats line into halves, so we can render left and right aligned portions.
         let hsplit = Layout::default()
@@ -143,23 +248,28 @@ impl App {
             .constraints([Constraint::Length(10), Constraint::Min(1)].as_ref())
             .split(r);
 
-        let left_side_items = vec![Line::from(self.ui_state.to_span())];
-        let right_side_items = vec![Line::from(vec![
-            Span::styled(
-                format!(" {} ", self.rg_cmdline),
-                Style::default().bg(Color::Blue).fg(Color::Black),
-            ),
+        let left_side_items = vec![Line::from(self.ui_state.to_span(&self.theme))];
+        let mut right_side_spans = vec![];
+        if self.is_searching() {
+            right_side_spans.push(Span::styled(" searching… ", self.theme.status_searching));
+        }
+        if self.list_filter.is_some() {
+            right_side_spans.push(Span::styled(" filtered ", self.theme.status_filtered));
+        }
+        right_side_spans.extend([
+            Span::styled(format!(" {} ", self.rg_cmdline), self.theme.status_cmdline),
             Span::styled(
                 format!(" CtrlChars: {} ", self.printable_style),
-                Style::default().bg(Color::Cyan).fg(Color::Black),
+                self.theme.status_control_chars,
             ),
             Span::styled(
-                format!(" {}/{} ", replacement_count, self.stats.matches),
-                Style::default().bg(Color::Magenta).fg(Color::Black),
+                format!(" {}/{} ", replacement_count, match_count),
+                self.theme.status_count,
             ),
-        ])];
+        ]);
+        let right_side_items = vec![Line::from(right_side_spans)];
 
-        let stats_line_style = Style::default().bg(Color::DarkGray).fg(Color::White);
+        let stats_line_style = self.theme.status_line;
         f.render_widget(
             Paragraph::new(left_side_items)
                 .style(stats_line_style)
@@ -175,60 +285,50 @@ impl App {
     }
 
     fn draw_help_view<B: Backend>(&mut self, f: &mut Frame<B>, r: Rect) {
-        let title_style = Style::default().fg(Color::Magenta);
+        let title_style = self.theme.title;
         let hsplit = Layout::default()
             .direction(Direction::Horizontal)
             .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
             .split(r);
 
-        let help_table = Table::new(
-            vec![
-                Row::new(vec!["MODE: ALL"]).style(title_style),
-                Row::new(vec!["control + b", "move backward one page"]),
-                Row::new(vec!["control + f", "move forward one page"]),
-                Row::new(vec![
-                    "control + v",
-                    "toggle how control characters are rendered",
-                ])
-                .bottom_margin(1),
-                Row::new(vec!["MODE: SELECT"]).style(title_style),
-                Row::new(vec!["k, up", "move to previous match"]),
-                Row::new(vec!["j, down", "move to next match"]),
-                Row::new(vec!["K, shift + up", "move to previous file"]),
-                Row::new(vec!["J, shift + down", "move to next file"]),
-                Row::new(vec!["space", "toggle selection"]),
-                Row::new(vec!["a, A", "toggle selection for all matches"]),
-                Row::new(vec!["s, S", "toggle selection for whole line"]),
-                Row::new(vec!["v", "invert section for the current item"]),
-                Row::new(vec!["V", "invert section for all items"]),
-                Row::new(vec!["enter, r, R", "accept selection"]),
-                Row::new(vec!["q, esc", "quit"]),
-                Row::new(vec!["?", "show help and keybindings"]).bottom_margin(1),
-                Row::new(vec!["MODE: REPLACE"]).style(title_style),
-                Row::new(vec!["control + s", "accept replacement text"]),
-                Row::new(vec!["esc", "previous mode"]).bottom_margin(1),
-                Row::new(vec!["MODE: CONFIRM"]).style(title_style),
-                Row::new(vec!["enter", "write replacements to disk"]),
-                Row::new(vec!["q, esc", "previous mode"]),
-            ]
-            .into_iter(),
-        )
-        .header(
-            Row::new(vec!["[Key]", "[Action]"])
-                .style(
-                    Style::default()
-                        .fg(Color::Yellow)
-                        .add_modifier(Modifier::BOLD),
-                )
-                .bottom_margin(1),
-        )
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .title(Span::styled("Keybindings", title_style)),
-        )
-        .widths(&[Constraint::Length(20), Constraint::Length(50)])
-        .column_spacing(1);
+        let mut rows = vec![];
+        let mut keys_width = 20;
+        for mode in KeymapMode::ALL {
+            rows.push(
+                Row::new(vec![format!("MODE: {}", mode.name().to_uppercase())]).style(title_style),
+            );
+            for binding in self.keymap.bindings(mode) {
+                let keys = binding
+                    .keys
+                    .iter()
+                    .map(|k| k.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                keys_width = keys_width.max(keys.width());
+                rows.push(Row::new(vec![keys, binding.description.to_string()]));
+            }
+            if let Some(last) = rows.pop() {
+                rows.push(last.bottom_margin(1));
+            }
+        }
+
+        let widths = [
+            Constraint::Length(keys_width as u16),
+            Constraint::Length(50),
+        ];
+        let help_table = Table::new(rows)
+            .header(
+                Row::new(vec!["[Key]", "[Action]"])
+                    .style(self.theme.help_header)
+                    .bottom_margin(1),
+            )
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(Span::styled("Keybindings", title_style)),
+            )
+            .widths(&widths)
+            .column_spacing(1);
 
         f.render_widget(help_table, hsplit[1]);
 
@@ -255,6 +355,8 @@ impl App {
     }
 
     fn draw_main_view<B: Backend>(&mut self, f: &mut Frame<B>, r: Rect) {
+        // the list is shorter while the preview is shown, so make sure the selected item is visible
+        self.update_indicator(f.size());
         let list_rect = self.main_view_list_rect(f.size());
         let indicator_symbol = self.list_indicator();
 
@@ -267,12 +369,21 @@ impl App {
         let window_start = self.list_state.window_start();
         let window_end = window_start + window_height;
 
+        let match_counts = self
+            .ui_state
+            .is_replacing()
+            .then(|| MatchCounts::new(&self.list));
         let ctx = &UiItemContext {
             capture_pattern: self.capture_pattern.as_ref(),
+            literal: self.literal,
+            preserve_case: self.preserve_case,
+            match_counts: match_counts.as_ref(),
             replacement_text: self.ui_state.user_replacement_text(),
             printable_style: self.printable_style,
             app_list_state: &self.list_state,
             app_ui_state: &self.ui_state,
+            highlighter: self.highlighter.as_ref(),
+            theme: &self.theme,
             list_rect,
         };
 
@@ -280,7 +391,7 @@ impl App {
         // window region of the list (skipping all the others)
         let mut match_items = vec![];
         let mut curr_height = 0;
-        for item in self.list.iter_mut() {
+        for item in self.list.iter_mut().filter(|i| !i.is_hidden()) {
             // we've passed the visible region
             if curr_height > window_end {
                 break;
@@ -314,10 +425,124 @@ impl App {
         // See: https://github.com/fdehau/tui-rs/issues/239#issuecomment-657070300
         let match_list = List::new(match_items)
             .block(Block::default())
-            .style(Style::default().fg(Color::White))
+            .style(self.theme.text)
             .highlight_symbol(&indicator_symbol);
 
-        f.render_stateful_widget(match_list, r, &mut self.list_state.indicator_mut());
+        f.render_stateful_widget(match_list, r, self.list_state.indicator_mut());
+    }
+
+    fn draw_tree_view<B: Backend>(&mut self, f: &mut Frame<B>, r: Rect) {
+        let visible = self.tree_state.visible(&self.list);
+        let tree = self.tree_state.tree();
+
+        let mut rows = vec![];
+        for &node_idx in &visible {
+            let node = &tree.nodes()[node_idx];
+            let (selected, total) = node.matches.iter().fold((0, 0), |(selected, total), &i| {
+                let item = &self.list[i];
+                (
+                    selected + item.replace_count(),
+                    total + item.sub_items().len(),
+                )
+            });
+
+            let mut spans = vec![Span::from("  ".repeat(node.depth))];
+            if node.is_dir() {
+                let marker = if self.tree_state.is_folded(&node.path) {
+                    "▸ "
+                } else {
+                    "▾ "
+                };
+                spans.push(Span::from(marker));
+                spans.push(Span::styled(
+                    format!("{}/", node.name).to_printable(self.printable_style.as_one_line()),
+                    self.theme.tree_dir,
+                ));
+            } else {
+                spans.push(Span::from("  "));
+                spans.push(Span::styled(
+                    node.name.to_printable(self.printable_style.as_one_line()),
+                    self.theme.tree_file,
+                ));
+            }
+
+            let count_style = if selected == 0 {
+                self.theme.tree_count_none
+            } else if selected == total {
+                self.theme.tree_count_all
+            } else {
+                self.theme.tree_count_some
+            };
+            spans.push(Span::styled(
+                format!(" ({}/{})", selected, total),
+                count_style,
+            ));
+
+            rows.push(ListItem::new(Line::from(spans)));
+        }
+
+        let selected_row = self
+            .tree_state
+            .selected_row()
+            .min(visible.len().saturating_sub(1));
+        self.tree_state.set_selected_row(selected_row);
+
+        let tree_list = List::new(rows)
+            .block(Block::default())
+            .style(self.theme.text)
+            .highlight_style(self.theme.tree_cursor)
+            .highlight_symbol(LIST_HIGHLIGHT_SYMBOL);
+
+        f.render_stateful_widget(tree_list, r, self.tree_state.rows_mut());
+    }
+
+    /// Shows the selected match (or the first match after the selected item) before and after it's
+    /// replaced, side by side.
+    fn draw_preview_view<B: Backend>(&mut self, f: &mut Frame<B>, r: Rect) {
+        let title_style = self.theme.title;
+        let hsplit = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+            .split(r);
+
+        let (before, after) = match self.preview_item_idx() {
+            Some(item_idx) => {
+                let match_counts = MatchCounts::new(&self.list);
+                let ctx = &UiItemContext {
+                    capture_pattern: self.capture_pattern.as_ref(),
+                    literal: self.literal,
+                    preserve_case: self.preserve_case,
+                    match_counts: Some(&match_counts),
+                    replacement_text: self.ui_state.user_replacement_text(),
+                    printable_style: self.printable_style,
+                    app_list_state: &self.list_state,
+                    app_ui_state: &self.ui_state,
+                    highlighter: None,
+                    theme: &self.theme,
+                    list_rect: r,
+                };
+                let file_lines = match &self.preview_file {
+                    Some((idx, file_lines)) if *idx == item_idx => file_lines.as_ref(),
+                    _ => None,
+                };
+                preview_lines(&self.list[item_idx], ctx, file_lines)
+            }
+            None => (vec![], vec![]),
+        };
+
+        for (lines, title, r) in [(before, "Before", hsplit[0]), (after, "After", hsplit[1])] {
+            let paragraph = Paragraph::new(lines).wrap(Wrap { trim: false }).block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(Span::styled(title, title_style)),
+            );
+            f.render_widget(paragraph, r);
+        }
+    }
+
+    /// The area the list, tree or help view is drawn in.
+    pub(crate) fn main_view_rect(&self, term_size: Rect) -> Rect {
+        self.get_layouts(term_size).0[0]
     }
 
     pub(crate) fn main_view_list_rect(&self, term_size: Rect) -> Rect {
@@ -326,7 +551,7 @@ impl App {
             y,
             width,
             height,
-        } = self.get_layouts(term_size).0[0];
+        } = self.main_view_rect(term_size);
         let indicator_width = self.list_indicator_width();
         Rect::new(
             x + indicator_width,
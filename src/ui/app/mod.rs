@@ -2,73 +2,478 @@ mod app_events;
 mod app_render;
 mod state;
 
+use std::collections::{HashMap, HashSet};
+
 use anyhow::{bail, Result};
-use regex::bytes::Regex;
+use ratatui::layout::Rect;
 use state::HelpTextState;
-pub use state::{AppListState, AppState, AppUiState};
+pub use state::{AppListState, AppState, AppTreeState, AppUiState};
 
-use crate::model::{PrintableStyle, ReplacementCriteria};
-use crate::rg::de::{RgMessage, Stats};
+use crate::model::{
+    CaptureResolver, FileFingerprint, ListFilter, PrintableStyle, ReplacementCriteria,
+};
+use crate::rg::de::{ArbitraryData, RgMessage, RgMessageKind, Stats};
+use crate::rg::RgEncoding;
+use crate::session::SessionState;
+use crate::ui::highlight::Highlighter;
+use crate::ui::keymap::Keymap;
 use crate::ui::line::Item;
+use crate::ui::preview::{match_line_count, FileLines};
+use crate::ui::theme::Theme;
 
 const HELP_TEXT: &str = include_str!("../../../doc/rgr.1.template");
 
+/// The results of a search, see `App::restart_search`.
+struct PreviousSearch {
+    capture_pattern: Option<CaptureResolver>,
+    literal: bool,
+    rg_cmdline: String,
+    search_cmdline: String,
+    stats: Option<Stats>,
+    list: Vec<Item>,
+    fingerprints: HashMap<ArbitraryData, FileFingerprint>,
+}
+
 pub struct App {
     pub state: AppState,
 
-    /// If the user passed a regular expression with a capturing group, then this will be set to
-    /// indicate that we should use the capturing group when performing replacements.
-    capture_pattern: Option<Regex>,
+    /// If the user passed regular expressions with capturing groups, then this will be set to
+    /// indicate that we should use the capturing groups when performing replacements.
+    capture_pattern: Option<CaptureResolver>,
+    /// If true, the replacement text is inserted literally and capturing groups are never expanded.
+    /// This is set when ripgrep is run with `--fixed-strings`.
+    literal: bool,
+    /// If true, the replacement text is converted into the casing style of each match. This is
+    /// toggled by the user while entering the replacement.
+    preserve_case: bool,
 
     /// Raw args passed to `ripgrep`.
     rg_cmdline: String,
-    /// Stats from `ripgrep`'s JSON output
-    stats: Stats,
+    /// The args passed to `ripgrep`, in the form the user edits when running the search again.
+    search_cmdline: String,
+    /// Set when the user has asked for the search to be run again with new args.
+    search_request: Option<String>,
+    /// The results of the last complete search, kept while a new search is running in case it fails.
+    previous_search: Option<PreviousSearch>,
+    /// Matches which were deselected before the search was run again. Any new matches at the same
+    /// path and offset are deselected too.
+    deselected: HashSet<(ArbitraryData, usize)>,
+    /// Stats from `ripgrep`'s JSON output. This is `None` while ripgrep is still searching.
+    stats: Option<Stats>,
     /// A list that represents all matches and holds each match's state.
     list: Vec<Item>,
+    /// The index of the last `Begin` item in the list, which is the file of any items being added.
+    last_begin_idx: Option<usize>,
+    /// The state of each file with matches when its results were read, so that changes made to it
+    /// since can be noticed before replacing.
+    fingerprints: HashMap<ArbitraryData, FileFingerprint>,
+    /// The filter the user entered to narrow down the list. This is empty if there's no filter.
+    filter: String,
+    /// The filter currently applied to the list, which changes as the user types a new one.
+    list_filter: Option<ListFilter>,
     /// State for where the user is inside the list.
     list_state: AppListState,
+    /// State for the tree view of the list.
+    tree_state: AppTreeState,
     /// Current UI mode.
     ui_state: AppUiState,
+    /// Set when the command the user entered couldn't be run, and shown until it's edited.
+    command_error: Option<String>,
+    /// The index of the item shown in the preview pane, and the lines around it in its file (or
+    /// `None` if they couldn't be read). See `App::update_preview`.
+    preview_file: Option<(usize, Option<FileLines>)>,
+    /// The encoding passed to ripgrep, used to decode the files shown in the preview pane.
+    rg_encoding: RgEncoding,
     /// Holds state information used when rendering the help screen.
     help_text_state: HelpTextState,
+    /// The keys bound to each action, which are also listed in the help screen.
+    keymap: Keymap,
 
     /// The current printable style used to render text.
     printable_style: PrintableStyle,
+    /// If set, the lines in the list are highlighted according to the language of each file.
+    highlighter: Option<Highlighter>,
+    /// The styles used to draw the interface.
+    theme: Theme,
+
+    /// If true, the replacements will be printed as a diff rather than written to disk.
+    dry_run: bool,
 }
 
 impl App {
     pub fn new(
-        capture_pattern: Option<Regex>,
+        capture_pattern: Option<CaptureResolver>,
         rg_cmdline: String,
         rg_messages: Vec<RgMessage>,
     ) -> App {
-        let mut list = vec![];
-        let mut maybe_stats = None;
-
-        for (i, rg_message) in rg_messages.into_iter().enumerate() {
-            match rg_message {
-                RgMessage::Summary { stats, .. } => {
-                    maybe_stats = Some(stats);
-                    // NOTE: there should only be one RgMessage::Summary, and it should be the last item.
-                    break;
-                }
-                other => list.push(Item::new(i, other)),
-            }
-        }
-
-        App {
+        let mut app = App {
             state: AppState::Running,
 
+            search_cmdline: rg_cmdline.clone(),
             capture_pattern,
+            literal: false,
+            preserve_case: false,
             rg_cmdline,
-            stats: maybe_stats.expect("failed to find RgMessage::Summary from rg!"),
+            search_request: None,
+            previous_search: None,
+            deselected: HashSet::new(),
+            stats: None,
             list_state: AppListState::new(),
-            list,
+            tree_state: AppTreeState::default(),
+            list: vec![],
+            last_begin_idx: None,
+            fingerprints: HashMap::new(),
+            filter: String::new(),
+            list_filter: None,
             ui_state: AppUiState::SelectMatches,
+            command_error: None,
+            preview_file: None,
+            rg_encoding: RgEncoding::None,
             help_text_state: HelpTextState::new(HELP_TEXT),
+            keymap: Keymap::default(),
             printable_style: PrintableStyle::default(),
+            highlighter: None,
+            theme: Theme::default(),
+            dry_run: false,
+        };
+
+        for rg_message in rg_messages {
+            app.add_rg_message(rg_message);
         }
+
+        app
+    }
+
+    /// Adds a message received from ripgrep to the list. Messages may be added while the app is
+    /// running, so results can be shown before ripgrep has finished searching.
+    pub fn add_rg_message(&mut self, rg_message: RgMessage) {
+        // NOTE: there should only be one RgMessage::Summary, and it should be the last item.
+        if self.stats.is_some() {
+            log::warn!(
+                "Received message after RgMessage::Summary: {:?}",
+                rg_message
+            );
+            return;
+        }
+
+        match rg_message {
+            RgMessage::Summary { stats, .. } => {
+                self.stats = Some(stats);
+                self.previous_search = None;
+            }
+            other => {
+                if let RgMessage::Begin { path } = &other {
+                    match FileFingerprint::read(path) {
+                        Ok(fingerprint) => {
+                            self.fingerprints.insert(path.clone(), fingerprint);
+                        }
+                        Err(e) => log::warn!("Failed to read {}: {}", path, e),
+                    }
+                }
+
+                let mut item = Item::new(self.list.len(), other);
+                if !self.deselected.is_empty() && matches!(item.kind, RgMessageKind::Match) {
+                    let path = item.path().unwrap().clone();
+                    let offset = item.offset().unwrap();
+                    for i in 0..item.sub_items().len() {
+                        let start = item.sub_items()[i].sub_match.range.start;
+                        if self.deselected.contains(&(path.clone(), offset + start)) {
+                            item.set_should_replace(i, false);
+                        }
+                    }
+                }
+
+                let idx = self.list.len();
+                if matches!(item.kind, RgMessageKind::Begin) {
+                    self.last_begin_idx = Some(idx);
+                }
+                self.list.push(item);
+                self.filter_item(idx, self.last_begin_idx);
+                self.tree_state.invalidate();
+            }
+        }
+    }
+
+    /// Applies a filter to the list, hiding the items that don't match it. An empty filter shows
+    /// all the items again.
+    pub(crate) fn apply_filter(&mut self, filter: &str) {
+        self.list_filter = ListFilter::new(filter);
+        let mut begin_idx = None;
+        for idx in 0..self.list.len() {
+            if matches!(self.list[idx].kind, RgMessageKind::Begin) {
+                begin_idx = Some(idx);
+            }
+            self.filter_item(idx, begin_idx);
+        }
+        self.tree_state.invalidate();
+    }
+
+    /// Hides or shows the item at `idx` depending on the current filter. The `Begin` and `End` items
+    /// of a file are shown if anything in that file matches, where `begin_idx` is the index of the
+    /// `Begin` item of the file the item is in.
+    fn filter_item(&mut self, idx: usize, begin_idx: Option<usize>) {
+        let filter = match &self.list_filter {
+            Some(filter) => filter,
+            None => {
+                self.list[idx].set_hidden(false);
+                return;
+            }
+        };
+
+        let hidden = match self.list[idx].kind {
+            RgMessageKind::Begin => true,
+            RgMessageKind::End => begin_idx.map(|i| self.list[i].is_hidden()).unwrap_or(true),
+            _ => !filter.is_match(&self.list[idx]),
+        };
+
+        self.list[idx].set_hidden(hidden);
+        if !hidden && !matches!(self.list[idx].kind, RgMessageKind::End) {
+            if let Some(begin_idx) = begin_idx {
+                self.list[begin_idx].set_hidden(false);
+            }
+        }
+    }
+
+    pub fn set_literal(&mut self, literal: bool) {
+        self.literal = literal;
+    }
+
+    /// Sets the encoding passed to ripgrep (if any).
+    pub fn set_encoding(&mut self, encoding: &Option<String>) {
+        self.rg_encoding = RgEncoding::from(encoding);
+        self.preview_file = None;
+    }
+
+    pub fn set_search_cmdline(&mut self, search_cmdline: String) {
+        self.search_cmdline = search_cmdline;
+    }
+
+    pub fn search_cmdline(&self) -> &str {
+        &self.search_cmdline
+    }
+
+    /// Returns the args the user entered if they've asked for the search to be run again.
+    pub fn take_search_request(&mut self) -> Option<String> {
+        self.search_request.take()
+    }
+
+    /// Returns to editing the search args, e.g., if the args entered couldn't be used.
+    pub fn edit_search(&mut self, search_cmdline: String) {
+        let pos = search_cmdline.chars().count();
+        self.ui_state = AppUiState::EditSearch(search_cmdline, pos);
+    }
+
+    /// Clears the results so that a new search can be started. Matches that were deselected will
+    /// stay deselected if they're found again by the new search.
+    pub fn restart_search(
+        &mut self,
+        capture_pattern: Option<CaptureResolver>,
+        literal: bool,
+        rg_cmdline: String,
+        search_cmdline: String,
+    ) {
+        // If the last search was interrupted, then keep what was deselected before it too.
+        if !self.is_searching() {
+            self.deselected.clear();
+        }
+        for item in &self.list {
+            if !matches!(item.kind, RgMessageKind::Match) {
+                continue;
+            }
+
+            let path = item.path().unwrap();
+            let offset = item.offset().unwrap();
+            for sub_item in item.sub_items().iter().filter(|s| !s.should_replace) {
+                self.deselected
+                    .insert((path.clone(), offset + sub_item.sub_match.range.start));
+            }
+        }
+
+        let previous_search = PreviousSearch {
+            capture_pattern: std::mem::replace(&mut self.capture_pattern, capture_pattern),
+            literal: std::mem::replace(&mut self.literal, literal),
+            rg_cmdline: std::mem::replace(&mut self.rg_cmdline, rg_cmdline),
+            search_cmdline: std::mem::replace(&mut self.search_cmdline, search_cmdline),
+            stats: self.stats.take(),
+            list: std::mem::take(&mut self.list),
+            fingerprints: std::mem::take(&mut self.fingerprints),
+        };
+        // Only keep the results of complete searches.
+        if previous_search.stats.is_some() {
+            self.previous_search = Some(previous_search);
+        }
+
+        self.last_begin_idx = None;
+        self.list_state = AppListState::new();
+        self.tree_state.invalidate();
+        self.preview_file = None;
+        self.ui_state = AppUiState::SelectMatches;
+    }
+
+    /// Restores the results of the last complete search, if there was one. This is used when a new
+    /// search fails.
+    pub fn restore_previous_search(&mut self) -> bool {
+        match self.previous_search.take() {
+            Some(previous_search) => {
+                self.capture_pattern = previous_search.capture_pattern;
+                self.literal = previous_search.literal;
+                self.rg_cmdline = previous_search.rg_cmdline;
+                self.search_cmdline = previous_search.search_cmdline;
+                self.stats = previous_search.stats;
+                self.list = previous_search.list;
+                self.fingerprints = previous_search.fingerprints;
+                self.list_state = AppListState::new();
+                self.apply_filter(&self.filter.clone());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns true if ripgrep hasn't finished searching yet.
+    pub fn is_searching(&self) -> bool {
+        self.stats.is_none()
+    }
+
+    /// Returns the index of the match shown in the preview pane: the selected item if it's a match,
+    /// otherwise the first match after it.
+    fn preview_item_idx(&self) -> Option<usize> {
+        let selected_item = self.list_state.selected_item().min(self.list.len());
+        self.list[selected_item..]
+            .iter()
+            .position(|i| matches!(i.kind, RgMessageKind::Match))
+            .map(|i| i + selected_item)
+    }
+
+    /// Reads the lines around the match shown in the preview pane, if it's shown and they haven't
+    /// been read already. This is called before drawing, so that files are only read when the
+    /// previewed match changes.
+    pub fn update_preview(&mut self) {
+        if !self.ui_state.is_replacing() {
+            return;
+        }
+        let item_idx = match self.preview_item_idx() {
+            Some(item_idx) => item_idx,
+            None => return,
+        };
+        if matches!(&self.preview_file, Some((idx, _)) if *idx == item_idx) {
+            return;
+        }
+
+        let item = &self.list[item_idx];
+        let file_lines = match (item.path_buf(), item.line_number(), item.lines()) {
+            (Some(path), Some(&line_number), Some(lines)) => FileLines::read(
+                &path,
+                &self.rg_encoding,
+                line_number,
+                match_line_count(&lines.to_vec()),
+            )
+            .map_err(|e| log::debug!("Failed to read {} for the preview: {}", path.display(), e))
+            .ok(),
+            _ => None,
+        };
+        self.preview_file = Some((item_idx, file_lines));
+    }
+
+    pub fn set_keymap(&mut self, keymap: Keymap) {
+        self.keymap = keymap;
+    }
+
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.theme = theme;
+    }
+
+    pub fn set_highlight(&mut self, highlight: bool) {
+        self.highlighter = highlight.then(Highlighter::new);
+    }
+
+    pub fn set_dry_run(&mut self, dry_run: bool) {
+        self.dry_run = dry_run;
+    }
+
+    /// Returns the messages received from ripgrep, or `None` if it hasn't finished searching yet.
+    pub fn rg_messages(&self) -> Option<Vec<RgMessage>> {
+        let stats = self.stats.as_ref()?;
+        let mut rg_messages = self
+            .list
+            .iter()
+            .map(|item| item.rg_message().clone())
+            .collect::<Vec<_>>();
+        rg_messages.push(RgMessage::Summary {
+            elapsed_total: stats.elapsed.clone(),
+            stats: stats.clone(),
+        });
+
+        Some(rg_messages)
+    }
+
+    /// Returns the state of each file with matches when its results were read.
+    pub fn fingerprints(&self) -> &HashMap<ArbitraryData, FileFingerprint> {
+        &self.fingerprints
+    }
+
+    /// Returns what the user has selected and entered, so it can be saved in a session.
+    pub fn session_state(&self) -> SessionState {
+        let mut overrides = vec![];
+        for (item_idx, item) in self.list.iter().enumerate() {
+            for (sub_idx, sub_item) in item.sub_items().iter().enumerate() {
+                if let Some(replacement) = &sub_item.replacement {
+                    overrides.push((item_idx, sub_idx, replacement.clone()));
+                }
+            }
+        }
+
+        SessionState {
+            should_replace: self
+                .list
+                .iter()
+                .map(|item| item.sub_items().iter().map(|s| s.should_replace).collect())
+                .collect(),
+            overrides,
+            replacement: self.ui_state.user_replacement_text().map(String::from),
+            preserve_case: self.preserve_case,
+            filter: self.filter.clone(),
+            cursor: (
+                self.list_state.selected_item(),
+                self.list_state.selected_submatch(),
+            ),
+        }
+    }
+
+    /// Restores the state saved in a session. This should be called once all of the session's
+    /// messages have been added.
+    pub fn restore_session_state(&mut self, state: &SessionState, term_size: Rect) {
+        for (item, should_replace) in self.list.iter_mut().zip(&state.should_replace) {
+            let sub_item_count = item.sub_items().len();
+            for (sub_idx, &should_replace) in should_replace.iter().take(sub_item_count).enumerate()
+            {
+                item.set_should_replace(sub_idx, should_replace);
+            }
+        }
+        for (item_idx, sub_idx, replacement) in &state.overrides {
+            if let Some(item) = self.list.get_mut(*item_idx) {
+                if *sub_idx < item.sub_items().len() {
+                    item.set_replacement(*sub_idx, Some(replacement.clone()));
+                }
+            }
+        }
+
+        self.preserve_case = state.preserve_case;
+        self.filter = state.filter.clone();
+        self.apply_filter(&state.filter);
+        if let Some(replacement) = &state.replacement {
+            let pos = replacement.chars().count();
+            self.ui_state = AppUiState::InputReplacement(replacement.clone(), pos);
+        }
+
+        let (item_idx, sub_idx) = state.cursor;
+        if let Some(item) = self.list.get(item_idx) {
+            if item.is_selectable() && (sub_idx == 0 || sub_idx < item.sub_items().len()) {
+                self.list_state.set_selected_item(item_idx);
+                self.list_state.set_selected_submatch(sub_idx);
+            }
+        }
+        self.update_indicator(term_size);
     }
 
     /// Consume the app and return `ReplacementCriteria`. This will return an `Err` if the app wasn't
@@ -76,11 +481,15 @@ impl App {
     pub fn get_replacement_criteria(self) -> Result<ReplacementCriteria> {
         match self.ui_state {
             AppUiState::InputReplacement(user_replacement, _)
-            | AppUiState::ConfirmReplacement(user_replacement, _) => Ok(ReplacementCriteria::new(
-                self.capture_pattern,
-                user_replacement,
-                self.list,
-            )),
+            | AppUiState::ConfirmReplacement(user_replacement, _) => {
+                let mut criteria =
+                    ReplacementCriteria::new(self.capture_pattern, user_replacement, self.list);
+                criteria.literal = self.literal;
+                criteria.preserve_case = self.preserve_case;
+                criteria.dry_run = self.dry_run;
+                criteria.fingerprints = self.fingerprints;
+                Ok(criteria)
+            }
             other => bail!(
                 "unexpected app ui state when calling App::get_replacement_criteria: {:?}",
                 other
@@ -1,7 +1,14 @@
-use ratatui::style::{Color, Style};
+use std::collections::HashSet;
+use std::path::PathBuf;
+
 use ratatui::text::Span;
 use ratatui::widgets::ListState;
 
+use crate::model::FileTree;
+use crate::ui::keymap::KeymapMode;
+use crate::ui::line::Item;
+use crate::ui::theme::Theme;
+
 #[derive(Debug)]
 pub struct AppListState {
     /// The selected "item" in the list of items received from rg
@@ -61,6 +68,68 @@ impl AppListState {
     }
 }
 
+/// State for the tree view, which groups the matches by directory and file.
+#[derive(Debug, Default)]
+pub struct AppTreeState {
+    /// The tree built from the list, or `None` if the list has changed since it was built.
+    tree: Option<FileTree>,
+    /// The paths of the directories which are folded.
+    folded: HashSet<PathBuf>,
+    /// The selected row, and the scroll position of the tree view.
+    rows: ListState,
+}
+
+impl AppTreeState {
+    /// Marks the tree as out of date, so it's built again the next time it's used.
+    pub fn invalidate(&mut self) {
+        self.tree = None;
+    }
+
+    /// Returns the tree, which must have been built by calling `AppTreeState::visible` first.
+    pub fn tree(&self) -> &FileTree {
+        self.tree
+            .as_ref()
+            .expect("tree view was used before it was built")
+    }
+
+    /// Returns the indices of the nodes which are shown, building the tree if it's out of date.
+    pub fn visible(&mut self, items: &[Item]) -> Vec<usize> {
+        let folded = &self.folded;
+        self.tree
+            .get_or_insert_with(|| FileTree::new(items))
+            .visible(folded)
+    }
+
+    pub fn is_folded(&self, path: &PathBuf) -> bool {
+        self.folded.contains(path)
+    }
+
+    pub fn set_folded(&mut self, path: PathBuf, folded: bool) {
+        if folded {
+            self.folded.insert(path);
+        } else {
+            self.folded.remove(&path);
+        }
+    }
+
+    pub fn rows_mut(&mut self) -> &mut ListState {
+        &mut self.rows
+    }
+
+    pub fn selected_row(&self) -> usize {
+        self.rows.selected().unwrap_or(0)
+    }
+
+    /// The index of the first row that was drawn.
+    pub fn rows_offset(&self) -> usize {
+        self.rows.offset()
+    }
+
+    pub fn set_selected_row(&mut self, idx: usize) {
+        self.rows.select(Some(idx));
+    }
+}
+
 #[derive(Debug)]
 pub enum AppState {
     Running,
@@ -81,6 +150,20 @@ pub enum AppUiState {
     /// Ask the user to confirm the replacement.
     /// (ReplacementText, CharPosition)
     ConfirmReplacement(String, usize),
+    /// Edit the arguments passed to ripgrep, so the search can be run again.
+    /// (SearchArgs, CharPosition)
+    EditSearch(String, usize),
+    /// Prompt the user for replacement text for only the selected match.
+    /// (ReplacementText, CharPosition)
+    InputOverride(String, usize),
+    /// Narrow down the matches list to the items matching a filter, which is applied as it's typed.
+    /// (FilterText, CharPosition)
+    EditFilter(String, usize),
+    /// Prompt the user for a command which selects or deselects matches in bulk.
+    /// (Command, CharPosition)
+    EditCommand(String, usize),
+    /// The matches grouped by directory and file, which can be folded and (de)selected together.
+    SelectTree,
 }
 
 impl AppUiState {
@@ -99,15 +182,34 @@ impl AppUiState {
         }
     }
 
+    /// The mode whose keybindings are used in this state.
+    pub fn keymap_mode(&self) -> KeymapMode {
+        match self {
+            AppUiState::Help => KeymapMode::Help,
+            AppUiState::SelectMatches => KeymapMode::Select,
+            AppUiState::InputReplacement(_, _) => KeymapMode::Replace,
+            AppUiState::ConfirmReplacement(_, _) => KeymapMode::Confirm,
+            AppUiState::EditSearch(_, _) => KeymapMode::Search,
+            AppUiState::InputOverride(_, _) => KeymapMode::Override,
+            AppUiState::EditFilter(_, _) => KeymapMode::Filter,
+            AppUiState::EditCommand(_, _) => KeymapMode::Command,
+            AppUiState::SelectTree => KeymapMode::Tree,
+        }
+    }
+
     /// Represent the `AppUiState` as a `Text`.
     /// This is displayed as the "mode" in the stats line.
-    pub fn to_span(&self) -> Span {
-        let style = Style::default().fg(Color::Black);
+    pub fn to_span(&self, theme: &Theme) -> Span {
         match self {
-            AppUiState::Help => Span::styled(" HELP ", style.bg(Color::Green)),
-            AppUiState::SelectMatches => Span::styled(" SELECT ", style.bg(Color::Cyan)),
-            AppUiState::InputReplacement(_, _) => Span::styled(" REPLACE ", style.bg(Color::White)),
-            AppUiState::ConfirmReplacement(_, _) => Span::styled(" CONFIRM ", style.bg(Color::Red)),
+            AppUiState::Help => Span::styled(" HELP ", theme.mode_help),
+            AppUiState::SelectMatches => Span::styled(" SELECT ", theme.mode_select),
+            AppUiState::InputReplacement(_, _) => Span::styled(" REPLACE ", theme.mode_replace),
+            AppUiState::ConfirmReplacement(_, _) => Span::styled(" CONFIRM ", theme.mode_confirm),
+            AppUiState::EditSearch(_, _) => Span::styled(" SEARCH ", theme.mode_search),
+            AppUiState::InputOverride(_, _) => Span::styled(" OVERRIDE ", theme.mode_override),
+            AppUiState::EditFilter(_, _) => Span::styled(" FILTER ", theme.mode_filter),
+            AppUiState::EditCommand(_, _) => Span::styled(" COMMAND ", theme.mode_command),
+            AppUiState::SelectTree => Span::styled(" TREE ", theme.mode_tree),
         }
     }
 }
@@ -0,0 +1,86 @@
+use std::ops::Range;
+use std::path::Path;
+
+use ratatui::style::Color;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::parsing::SyntaxSet;
+use syntect::util::LinesWithEndings;
+
+/// The colour of a range of bytes in some highlighted text.
+pub type Highlight = (Range<usize>, Color);
+
+/// Syntax highlighting for the lines shown in the matches list.
+pub struct Highlighter {
+    syntaxes: SyntaxSet,
+    theme: Theme,
+}
+
+impl Highlighter {
+    pub fn new() -> Highlighter {
+        let mut themes = ThemeSet::load_defaults().themes;
+        Highlighter {
+            syntaxes: SyntaxSet::load_defaults_newlines(),
+            theme: themes.remove("base16-ocean.dark").unwrap_or_default(),
+        }
+    }
+
+    /// Highlights `text` in the language of the file at `path`, which is picked from its extension
+    /// (or its name, e.g., `Makefile`). Returns `None` if the language isn't known.
+    ///
+    /// Each piece of text is highlighted on its own, so a line in the middle of a multi-line
+    /// comment or string may not be highlighted correctly.
+    pub fn highlight(&self, path: &Path, text: &str) -> Option<Vec<Highlight>> {
+        let name = path
+            .extension()
+            .or_else(|| path.file_name())?
+            .to_string_lossy();
+        let syntax = self.syntaxes.find_syntax_by_extension(&name)?;
+
+        let mut highlighter = HighlightLines::new(syntax, &self.theme);
+        let mut highlights = vec![];
+        let mut offset = 0;
+        for line in LinesWithEndings::from(text) {
+            for (style, piece) in highlighter.highlight_line(line, &self.syntaxes).ok()? {
+                let fg = style.foreground;
+                highlights.push((offset..offset + piece.len(), Color::Rgb(fg.r, fg.g, fg.b)));
+                offset += piece.len();
+            }
+        }
+
+        Some(highlights)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn it_highlights_by_file_extension() {
+        let highlighter = Highlighter::new();
+        let text = "let x = \"foo\"; // bar\n";
+        let highlights = highlighter
+            .highlight(Path::new("src/main.rs"), text)
+            .unwrap();
+
+        // the highlights cover all the text
+        assert_eq!(highlights.first().unwrap().0.start, 0);
+        assert_eq!(highlights.last().unwrap().0.end, text.len());
+        assert!(highlights.windows(2).all(|w| w[0].0.end == w[1].0.start));
+
+        // and the string and the comment have different colours to the keyword
+        let color_at = |i| highlights.iter().find(|(r, _)| r.contains(&i)).unwrap().1;
+        assert_ne!(color_at(0), color_at(9));
+        assert_ne!(color_at(0), color_at(18));
+
+        assert!(highlighter
+            .highlight(Path::new("foo.unknown"), text)
+            .is_none());
+        assert!(highlighter
+            .highlight(Path::new("Makefile"), "all:\n")
+            .is_some());
+    }
+}
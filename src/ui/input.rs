@@ -0,0 +1,42 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::util::byte_pos_from_char_pos;
+
+/// Applies a line editing key to `input`, where `pos` is the position of the cursor (in chars).
+/// Returns the new input and cursor position if the key was a line editing key, or `None` if the
+/// key should be handled elsewhere.
+pub fn edit_line(input: &str, pos: usize, key: &KeyEvent) -> Option<(String, usize)> {
+    let control_pressed = key.modifiers.contains(KeyModifiers::CONTROL);
+    let char_count = input.chars().count();
+    match key.code {
+        // clear input
+        KeyCode::Char('u') if control_pressed => Some((String::new(), 0)),
+        // input char
+        KeyCode::Char(ch) => {
+            let mut new_input = input.to_owned();
+            new_input.insert(byte_pos_from_char_pos(input, pos), ch);
+            Some((new_input, pos + 1))
+        }
+        // remove character behind cursor
+        KeyCode::Backspace if !input.is_empty() && pos > 0 => {
+            let mut new_input = input.to_owned();
+            new_input.remove(byte_pos_from_char_pos(input, pos - 1));
+            Some((new_input, pos - 1))
+        }
+        // remove character at cursor
+        KeyCode::Delete if !input.is_empty() && pos < char_count => {
+            let mut new_input = input.to_owned();
+            new_input.remove(byte_pos_from_char_pos(input, pos));
+            Some((new_input, pos))
+        }
+        // move cursor back
+        KeyCode::Left => Some((input.to_owned(), pos.saturating_sub(1))),
+        // move cursor forward
+        KeyCode::Right => Some((input.to_owned(), (pos + 1).clamp(0, char_count))),
+        // move to start
+        KeyCode::Home | KeyCode::PageUp => Some((input.to_owned(), 0)),
+        // move to end
+        KeyCode::End | KeyCode::PageDown => Some((input.to_owned(), char_count)),
+        _ => None,
+    }
+}
@@ -0,0 +1,667 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Result};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// The modes which have their own keybindings. The bindings in `All` apply to the modes which show
+/// the matches list (see `Keymap::action`).
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum KeymapMode {
+    All,
+    Select,
+    Tree,
+    Help,
+    Replace,
+    Confirm,
+    Search,
+    Filter,
+    Command,
+    Override,
+}
+
+impl KeymapMode {
+    /// All the modes, in the order they're shown in the help view.
+    pub const ALL: [KeymapMode; 10] = [
+        KeymapMode::All,
+        KeymapMode::Select,
+        KeymapMode::Tree,
+        KeymapMode::Replace,
+        KeymapMode::Search,
+        KeymapMode::Filter,
+        KeymapMode::Command,
+        KeymapMode::Override,
+        KeymapMode::Confirm,
+        KeymapMode::Help,
+    ];
+
+    /// The name of the mode in the config file.
+    pub fn name(&self) -> &'static str {
+        match self {
+            KeymapMode::All => "all",
+            KeymapMode::Select => "select",
+            KeymapMode::Tree => "tree",
+            KeymapMode::Help => "help",
+            KeymapMode::Replace => "replace",
+            KeymapMode::Confirm => "confirm",
+            KeymapMode::Search => "search",
+            KeymapMode::Filter => "filter",
+            KeymapMode::Command => "command",
+            KeymapMode::Override => "override",
+        }
+    }
+
+    /// Returns true if a key can't be bound to different actions in both modes, i.e., if they're
+    /// the same mode or one of them is `All` and the other shows the matches list.
+    fn overlaps(&self, other: KeymapMode) -> bool {
+        let shows_list = |mode| {
+            matches!(
+                mode,
+                KeymapMode::Select | KeymapMode::Replace | KeymapMode::Confirm
+            )
+        };
+        match (*self, other) {
+            (a, b) if a == b => true,
+            (KeymapMode::All, mode) | (mode, KeymapMode::All) => shows_list(mode),
+            _ => false,
+        }
+    }
+}
+
+/// Everything that can be bound to a key.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Action {
+    PageUp,
+    PageDown,
+    CyclePrintableStyle,
+    PrevLine,
+    NextLine,
+    PrevFile,
+    NextFile,
+    PrevMatch,
+    NextMatch,
+    Toggle,
+    ToggleLine,
+    ToggleAll,
+    Invert,
+    InvertAll,
+    Replace,
+    EditSearch,
+    Filter,
+    Command,
+    Tree,
+    Override,
+    ClearOverride,
+    Fold,
+    Unfold,
+    Open,
+    ScrollUp,
+    ScrollDown,
+    PreserveCase,
+    Newline,
+    DryRun,
+    Help,
+    Accept,
+    Cancel,
+    Quit,
+}
+
+/// A key that can be bound to an action, e.g., `j`, `ctrl+b` or `shift+up`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Key {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl Key {
+    fn new(code: KeyCode, modifiers: KeyModifiers) -> Key {
+        let mut modifiers =
+            modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+        // Terminals don't agree on whether shift is reported with upper case characters, so it's
+        // ignored, and `shift+j` is the same as `J`.
+        let code = match code {
+            KeyCode::Char(ch) if modifiers.contains(KeyModifiers::SHIFT) => {
+                modifiers.remove(KeyModifiers::SHIFT);
+                KeyCode::Char(ch.to_ascii_uppercase())
+            }
+            code => code,
+        };
+
+        Key { code, modifiers }
+    }
+
+    pub fn matches(&self, event: &KeyEvent) -> bool {
+        *self == Key::new(event.code, event.modifiers)
+    }
+}
+
+impl FromStr for Key {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Key> {
+        // the last `+` separates the modifiers from the key, unless the key is `+` itself
+        let (modifiers, key) = match s.strip_suffix("++") {
+            Some(modifiers) => (Some(modifiers), "+"),
+            None => match s.rsplit_once('+') {
+                Some((modifiers, key)) if !key.is_empty() => (Some(modifiers), key),
+                _ => (None, s),
+            },
+        };
+
+        let mut key_modifiers = KeyModifiers::empty();
+        for modifier in modifiers.into_iter().flat_map(|m| m.split('+')) {
+            key_modifiers |= match modifier.to_lowercase().as_str() {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => bail!("unknown modifier '{}' in key '{}'", modifier, s),
+            };
+        }
+
+        let mut chars = key.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(ch), None) => KeyCode::Char(ch),
+            _ => match key.to_lowercase().as_str() {
+                "space" => KeyCode::Char(' '),
+                "enter" | "return" => KeyCode::Enter,
+                "esc" | "escape" => KeyCode::Esc,
+                "tab" => KeyCode::Tab,
+                "backspace" => KeyCode::Backspace,
+                "delete" | "del" => KeyCode::Delete,
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" => KeyCode::PageUp,
+                "pagedown" => KeyCode::PageDown,
+                f => match f.strip_prefix('f').and_then(|n| n.parse().ok()) {
+                    Some(n) if (1..=12).contains(&n) => KeyCode::F(n),
+                    _ => bail!("unknown key '{}'", s),
+                },
+            },
+        };
+
+        Ok(Key::new(code, key_modifiers))
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (modifier, name) in [
+            (KeyModifiers::CONTROL, "control"),
+            (KeyModifiers::ALT, "alt"),
+            (KeyModifiers::SHIFT, "shift"),
+        ] {
+            if self.modifiers.contains(modifier) {
+                write!(f, "{} + ", name)?;
+            }
+        }
+
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "space"),
+            KeyCode::Char(ch) => write!(f, "{}", ch),
+            KeyCode::Enter => write!(f, "enter"),
+            KeyCode::Esc => write!(f, "esc"),
+            KeyCode::Tab => write!(f, "tab"),
+            KeyCode::Backspace => write!(f, "backspace"),
+            KeyCode::Delete => write!(f, "delete"),
+            KeyCode::Up => write!(f, "up"),
+            KeyCode::Down => write!(f, "down"),
+            KeyCode::Left => write!(f, "left"),
+            KeyCode::Right => write!(f, "right"),
+            KeyCode::Home => write!(f, "home"),
+            KeyCode::End => write!(f, "end"),
+            KeyCode::PageUp => write!(f, "page up"),
+            KeyCode::PageDown => write!(f, "page down"),
+            KeyCode::F(n) => write!(f, "f{}", n),
+            other => write!(f, "{:?}", other),
+        }
+    }
+}
+
+/// An action bound to keys in a mode.
+#[derive(Debug, Clone)]
+pub struct Binding {
+    pub mode: KeymapMode,
+    pub action: Action,
+    /// The name of the action in the config file.
+    pub name: &'static str,
+    /// What the action does, which is shown in the help view.
+    pub description: &'static str,
+    pub keys: Vec<Key>,
+}
+
+/// The default keybindings: (mode, action, name, description, keys).
+#[rustfmt::skip]
+const DEFAULT_BINDINGS: &[(KeymapMode, Action, &str, &str, &[&str])] = &[
+    (KeymapMode::All, Action::PageUp, "page_up", "move backward one page", &["ctrl+b"]),
+    (KeymapMode::All, Action::PageDown, "page_down", "move forward one page", &["ctrl+f"]),
+    (KeymapMode::All, Action::CyclePrintableStyle, "cycle_control_chars", "toggle how control characters are rendered", &["ctrl+v"]),
+
+    (KeymapMode::Select, Action::PrevLine, "prev_line", "move to previous match", &["k", "up"]),
+    (KeymapMode::Select, Action::NextLine, "next_line", "move to next match", &["j", "down"]),
+    (KeymapMode::Select, Action::PrevFile, "prev_file", "move to previous file", &["K", "shift+up", "h", "H", "left"]),
+    (KeymapMode::Select, Action::NextFile, "next_file", "move to next file", &["J", "shift+down", "l", "L", "right"]),
+    (KeymapMode::Select, Action::PrevMatch, "prev_match", "move to previous match, including those on the same line", &["b"]),
+    (KeymapMode::Select, Action::NextMatch, "next_match", "move to next match, including those on the same line", &["w"]),
+    (KeymapMode::Select, Action::Toggle, "toggle", "toggle selection", &["space", ";"]),
+    (KeymapMode::Select, Action::ToggleAll, "toggle_all", "toggle selection for all matches", &["a", "A"]),
+    (KeymapMode::Select, Action::ToggleLine, "toggle_line", "toggle selection for whole line", &["s", "S"]),
+    (KeymapMode::Select, Action::Invert, "invert", "invert selection for the current item", &["v"]),
+    (KeymapMode::Select, Action::InvertAll, "invert_all", "invert selection for all items", &["V"]),
+    (KeymapMode::Select, Action::Replace, "replace", "accept selection", &["enter", "r", "R"]),
+    (KeymapMode::Select, Action::EditSearch, "edit_search", "edit the search and run it again", &["e"]),
+    (KeymapMode::Select, Action::Filter, "filter", "filter the matches by path or line text", &["/"]),
+    (KeymapMode::Select, Action::Command, "command", "select or deselect matches by path glob", &[":"]),
+    (KeymapMode::Select, Action::Tree, "tree", "show the matches as a tree of files", &["t"]),
+    (KeymapMode::Select, Action::Override, "override", "set the replacement for only this match", &["o"]),
+    (KeymapMode::Select, Action::ClearOverride, "clear_override", "remove the replacement for this match", &["O"]),
+    (KeymapMode::Select, Action::Quit, "quit", "quit", &["q", "esc"]),
+    (KeymapMode::Select, Action::Help, "help", "show help and keybindings", &["?"]),
+
+    (KeymapMode::Tree, Action::PrevLine, "prev_line", "move to previous directory or file", &["k", "up"]),
+    (KeymapMode::Tree, Action::NextLine, "next_line", "move to next directory or file", &["j", "down"]),
+    (KeymapMode::Tree, Action::Fold, "fold", "fold directory, or move to its parent", &["h", "left"]),
+    (KeymapMode::Tree, Action::Unfold, "unfold", "unfold directory", &["l", "right"]),
+    (KeymapMode::Tree, Action::Toggle, "toggle", "toggle selection for everything in it", &["space", ";"]),
+    (KeymapMode::Tree, Action::ToggleAll, "toggle_all", "toggle selection for all matches", &["a", "A"]),
+    (KeymapMode::Tree, Action::Open, "open", "fold or unfold directory, or show file", &["enter"]),
+    (KeymapMode::Tree, Action::Replace, "replace", "accept selection", &["r", "R"]),
+    (KeymapMode::Tree, Action::Help, "help", "show help and keybindings", &["?"]),
+    (KeymapMode::Tree, Action::Cancel, "back", "return to the list", &["t", "q", "esc"]),
+
+    (KeymapMode::Help, Action::ScrollUp, "scroll_up", "scroll the help text up", &["k", "up"]),
+    (KeymapMode::Help, Action::ScrollDown, "scroll_down", "scroll the help text down", &["j", "down"]),
+    (KeymapMode::Help, Action::Cancel, "back", "return to the list", &["q", "esc"]),
+
+    (KeymapMode::Replace, Action::Accept, "accept", "accept replacement text", &["ctrl+s", "alt+;", "enter"]),
+    (KeymapMode::Replace, Action::PreserveCase, "preserve_case", "toggle preserving the case of each match", &["alt+p"]),
+    (KeymapMode::Replace, Action::Newline, "newline", "insert a new line", &["alt+enter"]),
+    (KeymapMode::Replace, Action::Cancel, "back", "previous mode", &["esc"]),
+
+    (KeymapMode::Search, Action::Accept, "accept", "run the search again", &["enter"]),
+    (KeymapMode::Search, Action::Cancel, "back", "previous mode", &["esc"]),
+
+    (KeymapMode::Filter, Action::Accept, "accept", "keep the filter", &["enter"]),
+    (KeymapMode::Filter, Action::Cancel, "back", "go back to the previous filter", &["esc"]),
+
+    (KeymapMode::Command, Action::Accept, "accept", "run the command", &["enter"]),
+    (KeymapMode::Command, Action::Cancel, "back", "previous mode", &["esc"]),
+
+    (KeymapMode::Override, Action::Accept, "accept", "set the replacement for the match", &["enter"]),
+    (KeymapMode::Override, Action::Cancel, "back", "previous mode", &["esc"]),
+
+    (KeymapMode::Confirm, Action::Accept, "accept", "write replacements to disk", &["enter"]),
+    (KeymapMode::Confirm, Action::DryRun, "dry_run", "print a diff of the replacements (dry run)", &["d"]),
+    (KeymapMode::Confirm, Action::Cancel, "back", "previous mode", &["q", "esc"]),
+];
+
+/// The keys bound to each action, in each mode.
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: Vec<Binding>,
+}
+
+impl Default for Keymap {
+    fn default() -> Keymap {
+        let bindings = DEFAULT_BINDINGS
+            .iter()
+            .map(|&(mode, action, name, description, keys)| Binding {
+                mode,
+                action,
+                name,
+                description,
+                keys: keys
+                    .iter()
+                    .map(|k| k.parse().expect("invalid default key"))
+                    .collect(),
+            })
+            .collect();
+
+        Keymap { bindings }
+    }
+}
+
+impl Keymap {
+    /// Returns the default keymap, with the keys of some actions replaced. `overrides` maps the name
+    /// of each mode to the names of its actions and their new keys, e.g.,
+    /// `{ "select": { "next_line": ["n", "down"] } }`.
+    ///
+    /// A key given to an action is removed from any default binding it would clash with, and it's
+    /// an error to give the same key to two actions which clash.
+    pub fn with_overrides(
+        overrides: &HashMap<String, HashMap<String, Vec<String>>>,
+    ) -> Result<Keymap> {
+        let mut keymap = Keymap::default();
+        let mut overridden = vec![false; keymap.bindings.len()];
+        for (mode_name, actions) in overrides {
+            let mode = KeymapMode::ALL
+                .iter()
+                .find(|m| m.name() == mode_name)
+                .ok_or_else(|| {
+                    anyhow!(
+                        "unknown mode '{}', expected one of: {}",
+                        mode_name,
+                        KeymapMode::ALL.map(|m| m.name()).join(", ")
+                    )
+                })?;
+
+            for (action_name, keys) in actions {
+                let index = keymap
+                    .bindings
+                    .iter()
+                    .position(|b| b.mode == *mode && b.name == action_name);
+                let index = match index {
+                    Some(index) => index,
+                    None => bail!(
+                        "unknown action '{}' in mode '{}', expected one of: {}",
+                        action_name,
+                        mode_name,
+                        keymap.names(*mode).join(", ")
+                    ),
+                };
+
+                keymap.bindings[index].keys =
+                    keys.iter().map(|k| k.parse()).collect::<Result<_>>()?;
+                overridden[index] = true;
+            }
+        }
+
+        for i in (0..keymap.bindings.len()).filter(|&i| overridden[i]) {
+            let (mode, name, keys) = {
+                let b = &keymap.bindings[i];
+                (b.mode, b.name, b.keys.clone())
+            };
+            for j in (0..keymap.bindings.len()).filter(|&j| j != i) {
+                let other = &mut keymap.bindings[j];
+                if !mode.overlaps(other.mode) {
+                    continue;
+                }
+
+                for key in &keys {
+                    if !other.keys.contains(key) {
+                        continue;
+                    }
+                    if overridden[j] {
+                        bail!(
+                            "key '{}' is bound to both '{}' in mode '{}' and '{}' in mode '{}'",
+                            key,
+                            name,
+                            mode.name(),
+                            other.name,
+                            other.mode.name()
+                        );
+                    }
+                    other.keys.retain(|k| k != key);
+                }
+            }
+        }
+
+        Ok(keymap)
+    }
+
+    fn names(&self, mode: KeymapMode) -> Vec<&'static str> {
+        self.bindings(mode).map(|b| b.name).collect()
+    }
+
+    /// Returns the bindings of `mode`, in the order they're shown in the help view.
+    pub fn bindings(&self, mode: KeymapMode) -> impl Iterator<Item = &Binding> {
+        self.bindings.iter().filter(move |b| b.mode == mode)
+    }
+
+    /// Returns the first key bound to `action` in `mode`, formatted for the hints in the input line,
+    /// e.g., `<control + s>`.
+    pub fn hint(&self, mode: KeymapMode, action: Action) -> String {
+        match self
+            .bindings(mode)
+            .find(|b| b.action == action)
+            .and_then(|b| b.keys.first())
+        {
+            Some(key) => format!("<{}>", key),
+            None => "<unbound>".to_string(),
+        }
+    }
+
+    /// Returns the action bound to `key` in `mode`, if there is one.
+    pub fn action(&self, mode: KeymapMode, key: &KeyEvent) -> Option<Action> {
+        self.bindings(mode)
+            .find(|b| b.keys.iter().any(|k| k.matches(key)))
+            .map(|b| b.action)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[test]
+    fn it_parses_keys() {
+        let cases = vec![
+            ("j", KeyCode::Char('j'), KeyModifiers::empty(), "j"),
+            ("J", KeyCode::Char('J'), KeyModifiers::empty(), "J"),
+            ("shift+j", KeyCode::Char('J'), KeyModifiers::empty(), "J"),
+            (
+                "ctrl+b",
+                KeyCode::Char('b'),
+                KeyModifiers::CONTROL,
+                "control + b",
+            ),
+            (
+                "Control+Alt+x",
+                KeyCode::Char('x'),
+                KeyModifiers::CONTROL | KeyModifiers::ALT,
+                "control + alt + x",
+            ),
+            ("shift+up", KeyCode::Up, KeyModifiers::SHIFT, "shift + up"),
+            ("space", KeyCode::Char(' '), KeyModifiers::empty(), "space"),
+            ("+", KeyCode::Char('+'), KeyModifiers::empty(), "+"),
+            ("alt++", KeyCode::Char('+'), KeyModifiers::ALT, "alt + +"),
+            (
+                "PageDown",
+                KeyCode::PageDown,
+                KeyModifiers::empty(),
+                "page down",
+            ),
+            ("f5", KeyCode::F(5), KeyModifiers::empty(), "f5"),
+        ];
+        for (s, code, modifiers, display) in cases {
+            let key: Key = s.parse().unwrap();
+            assert_eq!(key, Key { code, modifiers }, "{}", s);
+            assert_eq!(key.to_string(), display);
+        }
+
+        assert!("hyper+j".parse::<Key>().is_err());
+        assert!("jk".parse::<Key>().is_err());
+        assert!("f13".parse::<Key>().is_err());
+        assert!("".parse::<Key>().is_err());
+    }
+
+    #[test]
+    fn it_formats_hints_from_the_bound_keys() {
+        let keymap = Keymap::default();
+        assert_eq!(
+            keymap.hint(KeymapMode::Replace, Action::Accept),
+            "<control + s>"
+        );
+        assert_eq!(keymap.hint(KeymapMode::Select, Action::Help), "<?>");
+        assert_eq!(keymap.hint(KeymapMode::Select, Action::Fold), "<unbound>");
+
+        let overrides = vec![(
+            "replace".to_string(),
+            vec![("accept".to_string(), vec!["alt+a".to_string()])]
+                .into_iter()
+                .collect(),
+        )]
+        .into_iter()
+        .collect();
+        let keymap = Keymap::with_overrides(&overrides).unwrap();
+        assert_eq!(
+            keymap.hint(KeymapMode::Replace, Action::Accept),
+            "<alt + a>"
+        );
+    }
+
+    #[test]
+    fn it_finds_actions() {
+        let keymap = Keymap::default();
+        let action = |mode, code, modifiers| keymap.action(mode, &key(code, modifiers));
+
+        assert_eq!(
+            action(
+                KeymapMode::Select,
+                KeyCode::Char('j'),
+                KeyModifiers::empty()
+            ),
+            Some(Action::NextLine)
+        );
+        assert_eq!(
+            action(KeymapMode::Select, KeyCode::Down, KeyModifiers::SHIFT),
+            Some(Action::NextFile)
+        );
+        assert_eq!(
+            action(KeymapMode::Select, KeyCode::Char('K'), KeyModifiers::SHIFT),
+            Some(Action::PrevFile)
+        );
+        assert_eq!(
+            action(
+                KeymapMode::Select,
+                KeyCode::Char('j'),
+                KeyModifiers::CONTROL
+            ),
+            None
+        );
+        assert_eq!(
+            action(KeymapMode::Tree, KeyCode::Char('t'), KeyModifiers::empty()),
+            Some(Action::Cancel)
+        );
+        assert_eq!(
+            action(KeymapMode::Replace, KeyCode::Enter, KeyModifiers::ALT),
+            Some(Action::Newline)
+        );
+        assert_eq!(
+            action(KeymapMode::Replace, KeyCode::Enter, KeyModifiers::empty()),
+            Some(Action::Accept)
+        );
+    }
+
+    #[test]
+    fn it_overrides_keys() {
+        let overrides = vec![(
+            "select".to_string(),
+            vec![
+                (
+                    "next_line".to_string(),
+                    vec!["n".to_string(), "down".to_string()],
+                ),
+                ("prev_line".to_string(), vec!["p".to_string()]),
+            ]
+            .into_iter()
+            .collect(),
+        )]
+        .into_iter()
+        .collect();
+        let keymap = Keymap::with_overrides(&overrides).unwrap();
+        let action = |mode, ch| keymap.action(mode, &key(KeyCode::Char(ch), KeyModifiers::empty()));
+
+        assert_eq!(action(KeymapMode::Select, 'n'), Some(Action::NextLine));
+        assert_eq!(action(KeymapMode::Select, 'p'), Some(Action::PrevLine));
+        assert_eq!(action(KeymapMode::Select, 'j'), None);
+        assert_eq!(action(KeymapMode::Select, 'k'), None);
+        // other modes are unchanged
+        assert_eq!(action(KeymapMode::Tree, 'j'), Some(Action::NextLine));
+
+        let override_one = |mode: &str, action: &str, key: &str| {
+            let actions = vec![(action.to_string(), vec![key.to_string()])]
+                .into_iter()
+                .collect();
+            Keymap::with_overrides(&vec![(mode.to_string(), actions)].into_iter().collect())
+        };
+        assert!(override_one("select", "next_line", "n").is_ok());
+        assert!(override_one("selcet", "next_line", "n").is_err());
+        assert!(override_one("select", "next", "n").is_err());
+        assert!(override_one("select", "next_line", "nn").is_err());
+    }
+
+    #[test]
+    fn it_resolves_clashing_keys() {
+        let overrides = |mode: &str, actions: &[(&str, &str)]| {
+            let actions = actions
+                .iter()
+                .map(|(action, key)| (action.to_string(), vec![key.to_string()]))
+                .collect();
+            Keymap::with_overrides(&vec![(mode.to_string(), actions)].into_iter().collect())
+        };
+        let action =
+            |keymap: &Keymap, mode, code, modifiers| keymap.action(mode, &key(code, modifiers));
+
+        // a key that's bound by the user is taken from the default binding
+        let keymap = overrides("select", &[("prev_line", "e")]).unwrap();
+        assert_eq!(
+            action(
+                &keymap,
+                KeymapMode::Select,
+                KeyCode::Char('e'),
+                KeyModifiers::empty()
+            ),
+            Some(Action::PrevLine)
+        );
+        assert_eq!(
+            keymap.hint(KeymapMode::Select, Action::EditSearch),
+            "<unbound>"
+        );
+        // but only in the modes where it would clash
+        let keymap = overrides("select", &[("toggle_all", "t")]).unwrap();
+        assert_eq!(
+            action(
+                &keymap,
+                KeymapMode::Select,
+                KeyCode::Char('t'),
+                KeyModifiers::empty()
+            ),
+            Some(Action::ToggleAll)
+        );
+        assert_eq!(
+            action(
+                &keymap,
+                KeymapMode::Tree,
+                KeyCode::Char('t'),
+                KeyModifiers::empty()
+            ),
+            Some(Action::Cancel)
+        );
+
+        // the bindings in `all` clash with the modes they apply to
+        let keymap = overrides("select", &[("toggle", "ctrl+b")]).unwrap();
+        assert_eq!(
+            action(
+                &keymap,
+                KeymapMode::All,
+                KeyCode::Char('b'),
+                KeyModifiers::CONTROL
+            ),
+            None
+        );
+        assert_eq!(
+            action(
+                &keymap,
+                KeymapMode::Select,
+                KeyCode::Char('b'),
+                KeyModifiers::CONTROL
+            ),
+            Some(Action::Toggle)
+        );
+
+        // the same key can't be bound to two actions
+        assert!(overrides("select", &[("prev_line", "x"), ("next_line", "x")]).is_err());
+        assert!(overrides("tree", &[("prev_line", "x"), ("next_line", "y")]).is_ok());
+    }
+}
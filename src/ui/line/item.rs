@@ -6,9 +6,10 @@ use ratatui::text::{Line, Span};
 use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
 use crate::format_line_number;
-use crate::model::{Printable, PrintableStyle};
+use crate::model::{expand_replacement, MatchContext, Printable, PrintableStyle};
 use crate::rg::de::{ArbitraryData, RgMessage, RgMessageKind};
 use crate::ui::app::AppUiState;
+use crate::ui::highlight::Highlight;
 use crate::ui::line::SubItem;
 use crate::ui::render::UiItemContext;
 
@@ -25,6 +26,8 @@ pub struct Item {
     rg_message: RgMessage,
 
     sub_items: Vec<SubItem>,
+    /// Set when the item doesn't match the filter the user entered, so it isn't shown.
+    hidden: bool,
 
     cached_line_count: Option<CachedLineCount>,
 }
@@ -37,6 +40,7 @@ impl PartialEq for Item {
             && self.kind == other.kind
             && self.rg_message == other.rg_message
             && self.sub_items == other.sub_items
+            && self.hidden == other.hidden
     }
 }
 impl Eq for Item {}
@@ -65,6 +69,7 @@ impl Item {
             kind,
             rg_message,
             sub_items,
+            hidden: false,
             cached_line_count: None,
         }
     }
@@ -77,6 +82,14 @@ impl Item {
         self.sub_items[idx].should_replace = should_replace
     }
 
+    pub fn get_replacement(&self, idx: usize) -> Option<&str> {
+        self.sub_items[idx].replacement.as_deref()
+    }
+
+    pub fn set_replacement(&mut self, idx: usize, replacement: Option<String>) {
+        self.sub_items[idx].replacement = replacement
+    }
+
     pub fn get_should_replace_all(&self) -> bool {
         self.sub_items.iter().all(|s| s.should_replace)
     }
@@ -94,7 +107,15 @@ impl Item {
     }
 
     pub fn is_selectable(&self) -> bool {
-        matches!(self.kind, RgMessageKind::Begin | RgMessageKind::Match)
+        !self.hidden && matches!(self.kind, RgMessageKind::Begin | RgMessageKind::Match)
+    }
+
+    pub fn is_hidden(&self) -> bool {
+        self.hidden
+    }
+
+    pub fn set_hidden(&mut self, hidden: bool) {
+        self.hidden = hidden
     }
 
     pub fn line_number(&self) -> Option<&usize> {
@@ -105,6 +126,26 @@ impl Item {
         }
     }
 
+    /// Returns the line number of the start of the submatch at `sub_idx`, since a match may span
+    /// multiple lines.
+    pub fn line_number_at(&self, sub_idx: usize) -> Option<usize> {
+        match &self.rg_message {
+            RgMessage::Match {
+                lines,
+                line_number: Some(line_number),
+                ..
+            } => {
+                let start = self.sub_items.get(sub_idx)?.sub_match.range.start;
+                let newlines = lines.to_vec()[..start]
+                    .iter()
+                    .filter(|&&b| b == b'\n')
+                    .count();
+                Some(line_number + newlines)
+            }
+            _ => self.line_number().copied(),
+        }
+    }
+
     pub fn offset(&self) -> Option<usize> {
         match &self.rg_message {
             RgMessage::End { binary_offset, .. } => *binary_offset,
@@ -119,6 +160,10 @@ impl Item {
         self.sub_items.iter().filter(|s| s.should_replace).count()
     }
 
+    pub fn rg_message(&self) -> &RgMessage {
+        &self.rg_message
+    }
+
     pub fn sub_items(&self) -> &[SubItem] {
         &self.sub_items
     }
@@ -133,6 +178,15 @@ impl Item {
         }
     }
 
+    /// Returns the text of the line(s) of a match or context item.
+    pub fn lines(&self) -> Option<&ArbitraryData> {
+        match &self.rg_message {
+            RgMessage::Match { lines, .. } => Some(lines),
+            RgMessage::Context { lines, .. } => Some(lines),
+            _ => None,
+        }
+    }
+
     pub fn path_buf(&self) -> Option<PathBuf> {
         self.path().and_then(|data| data.to_path_buf().ok())
     }
@@ -222,13 +276,75 @@ impl Item {
         count
     }
 
+    /// Returns the index of the submatch drawn at `row` and `col` (relative to the start of the
+    /// item), i.e., the last submatch which starts at or before that position. Rows are counted the
+    /// same way as in `line_count_at`, so they line up with the list indicator.
+    pub fn sub_item_at(
+        &mut self,
+        row: usize,
+        col: usize,
+        list_width: u16,
+        style: PrintableStyle,
+    ) -> usize {
+        let mut sub_idx = 0;
+        for idx in 0..self.sub_items.len() {
+            let start_row = self.line_count_at(idx, list_width, style).saturating_sub(1);
+            let start_col = self.column_at(idx, list_width, style);
+            if (start_row, start_col) > (row, col) {
+                break;
+            }
+            sub_idx = idx;
+        }
+
+        sub_idx
+    }
+
+    /// Returns the column at which the submatch at `match_idx` is drawn, including the width of the
+    /// line number.
+    fn column_at(&self, match_idx: usize, list_width: u16, style: PrintableStyle) -> usize {
+        let (lines, start_of_match) = match (&self.rg_message, self.sub_items.get(match_idx)) {
+            (RgMessage::Match { lines, .. }, Some(sub_item)) => {
+                (lines.to_vec(), sub_item.sub_match.range.start)
+            }
+            _ => return 0,
+        };
+
+        let line_number = self.line_number().unwrap();
+        let before = String::from_utf8_lossy(&lines[0..start_of_match]).to_printable(style);
+        let (i, line) = before.split('\n').enumerate().last().unwrap_or_default();
+        let line_number_width = format_line_number!(line_number + i).width();
+        let available_width = (list_width as usize).saturating_sub(line_number_width);
+        if available_width == 0 {
+            return line_number_width;
+        }
+
+        line_number_width + line.width() % available_width
+    }
+
+    /// Returns the text the submatch at `sub_idx` is replaced with, or `None` if the user isn't
+    /// entering a replacement.
+    pub fn replacement_at(&self, sub_idx: usize, ctx: &UiItemContext) -> Option<Vec<u8>> {
+        let sub_item = &self.sub_items[sub_idx];
+        ctx.replacement_text.map(|user| {
+            expand_replacement(
+                ctx.capture_pattern,
+                ctx.literal,
+                ctx.preserve_case && sub_item.replacement.is_none(),
+                &sub_item.sub_match.text.to_vec(),
+                sub_item.replacement.as_deref().unwrap_or(user).as_bytes(),
+                &MatchContext::new(self, sub_idx, ctx.match_counts),
+            )
+            .into_owned()
+        })
+    }
+
     pub fn to_span_lines(&self, ctx: &UiItemContext) -> Vec<Line> {
         let is_replacing = ctx.app_ui_state.is_replacing();
         let is_selected = ctx.app_list_state.selected_item() == self.index;
 
         let mut base_style = Style::default();
         if !is_replacing && is_selected {
-            base_style = base_style.fg(Color::Yellow);
+            base_style = base_style.patch(ctx.theme.selected_line);
         }
 
         // pushes a span to `spans` which contains the given line number content
@@ -236,7 +352,7 @@ impl Item {
             ($spans:expr, $content:expr) => {{
                 let mut line_number_style = base_style;
                 if !is_selected || is_replacing {
-                    line_number_style = line_number_style.fg(Color::DarkGray);
+                    line_number_style = line_number_style.patch(ctx.theme.line_number);
                 }
 
                 $spans.push(Span::styled(
@@ -246,30 +362,61 @@ impl Item {
             }};
         }
 
+        // The selected item is already shown in a different colour, so it isn't highlighted.
+        let highlights = match (ctx.highlighter, self.lines(), self.path_buf()) {
+            (Some(highlighter), Some(lines), Some(path)) if is_replacing || !is_selected => {
+                std::str::from_utf8(&lines.to_vec())
+                    .ok()
+                    .and_then(|text| highlighter.highlight(&path, text))
+            }
+            _ => None,
+        };
+
         let span_lines = match &self.rg_message {
             RgMessage::Begin { .. } => vec![vec![Span::styled(
                 format!("{}", self.path_buf().unwrap().display()).to_printable(ctx.printable_style),
                 if !is_replacing && is_selected {
-                    base_style.fg(Color::Black).bg(Color::Yellow)
+                    base_style.patch(ctx.theme.path_selected)
                 } else {
-                    base_style.fg(Color::Magenta)
+                    base_style.patch(ctx.theme.path)
                 },
             )]],
 
             RgMessage::Context {
                 lines, line_number, ..
             } => {
-                let mut span_lines = vec![];
-                for (i, line) in lines.to_printable(ctx.printable_style).lines().enumerate() {
-                    let mut spans = vec![];
-                    if i == 0 {
-                        if let Some(n) = line_number {
-                            push_line_number_span!(spans, n);
+                let lines_bytes = lines.to_vec();
+                let pieces = printable_pieces(
+                    &lines_bytes,
+                    0..lines_bytes.len(),
+                    highlights.as_deref(),
+                    ctx.printable_style,
+                );
+
+                // split the pieces into lines, in the same way as `str::lines`
+                let mut span_lines = vec![vec![]];
+                for (text, color) in pieces {
+                    let style = color.map_or(base_style, |c| base_style.fg(c));
+                    for (i, part) in text.split('\n').enumerate() {
+                        if i > 0 {
+                            span_lines.push(vec![]);
+                        }
+                        if !part.is_empty() {
+                            span_lines
+                                .last_mut()
+                                .unwrap()
+                                .push(Span::styled(part.to_string(), style));
                         }
                     }
+                }
+                if span_lines.len() > 1 && span_lines.last().unwrap().is_empty() {
+                    span_lines.pop();
+                }
 
-                    spans.push(Span::styled(line.to_string(), base_style));
-                    span_lines.push(spans);
+                if let Some(n) = line_number {
+                    let mut spans = vec![];
+                    push_line_number_span!(spans, n);
+                    span_lines[0].splice(0..0, spans);
                 }
 
                 span_lines
@@ -282,46 +429,49 @@ impl Item {
 
                 // Read the lines as bytes since we split it at the byte ranges that ripgrep gives us in each of the submatches.
                 let lines_bytes = lines.to_vec();
-                let replacement_spans = ctx.replacement_text.map(|user| {
-                    let user = user.as_bytes().to_vec();
-                    let text = match ctx.capture_pattern.and_then(|re| re.captures(&lines_bytes)) {
-                        Some(captures) => {
-                            let mut s = Vec::new();
-                            captures.expand(&user, &mut s);
-                            s
+                // Compute the replacement for each submatch in the same way it's done when writing
+                // the replacements to disk.
+                let replacement_spans = |sub_item: &SubItem| {
+                    self.replacement_at(sub_item.index, ctx).map(|text| {
+                        // matches with their own replacement are shown in a different colour
+                        let replacement_style = if sub_item.replacement.is_some() {
+                            base_style.patch(ctx.theme.replacement_override)
+                        } else {
+                            base_style.patch(ctx.theme.replacement)
+                        };
+                        let mut spans = text
+                            .to_printable(ctx.printable_style)
+                            .lines()
+                            .map(|line| Span::styled(line.to_owned(), replacement_style))
+                            .collect::<Vec<_>>();
+
+                        // NOTE: since `"foo\n".lines().collect()` == `vec!["foo"]` we need to make sure the
+                        // last newline isn't trimmed.
+                        if !ctx.printable_style.is_one_line() && text.ends_with(&[/* \n */ 10]) {
+                            spans.push(Span::from(""));
                         }
-                        None => user,
-                    };
-
-                    let replacement_style = base_style.fg(Color::Green);
-                    let mut spans = text
-                        .to_printable(ctx.printable_style)
-                        .lines()
-                        .map(|line| Span::styled(line.to_owned(), replacement_style))
-                        .collect::<Vec<_>>();
-
-                    // NOTE: since `"foo\n".lines().collect()` == `vec!["foo"]` we need to make sure the
-                    // last newline isn't trimmed.
-                    if !ctx.printable_style.is_one_line() && text.ends_with(&[/* \n */ 10]) {
-                        spans.push(Span::from(""));
-                    }
 
-                    spans
-                });
+                        spans
+                    })
+                };
 
                 let mut span_lines = vec![];
                 let mut spans = vec![]; // filled and emptied for each line
 
                 macro_rules! push_utf8_slice {
                     ($range:ident) => {{
-                        let mut content = String::from_utf8_lossy(&lines_bytes[$range]).to_printable(ctx.printable_style);
+                        let mut pieces = printable_pieces(&lines_bytes, $range, highlights.as_deref(), ctx.printable_style);
                         // remove trailing new line if one exists since lines are already handled
-                        if content.ends_with("\n") {
-                            content.pop();
+                        if let Some((content, _)) = pieces.last_mut() {
+                            if content.ends_with("\n") {
+                                content.pop();
+                            }
                         }
                         // NOTE: don't handle multiple lines in the match because AFAICT ripgrep doesn't return multiline
                         // text in between submatches in a "match" item.
-                        spans.push(Span::styled(content, base_style));
+                        for (content, color) in pieces {
+                            spans.push(Span::styled(content, color.map_or(base_style, |c| base_style.fg(c))));
+                        }
                     }}
                 }
 
@@ -376,7 +526,7 @@ impl Item {
 
                     // Replacement text.
                     if sub_item.should_replace {
-                        if let Some(replacement_span_lines) = replacement_spans.as_ref() {
+                        if let Some(replacement_span_lines) = replacement_spans(sub_item) {
                             for (i, span) in replacement_span_lines.iter().enumerate() {
                                 if i == 0 {
                                     // reset the line number
@@ -459,6 +609,41 @@ impl Item {
     }
 }
 
+/// Converts the `range` of `bytes` into printable text. If there are `highlights`, the text is split
+/// into pieces at their boundaries, along with the colour of each piece.
+fn printable_pieces(
+    bytes: &[u8],
+    range: Range<usize>,
+    highlights: Option<&[Highlight]>,
+    style: PrintableStyle,
+) -> Vec<(String, Option<Color>)> {
+    let printable = |r: Range<usize>| String::from_utf8_lossy(&bytes[r]).to_printable(style);
+    let highlights = match highlights {
+        Some(highlights) => highlights,
+        None => return vec![(printable(range), None)],
+    };
+
+    let mut pieces = vec![];
+    let mut offset = range.start;
+    for (r, color) in highlights {
+        let start = r.start.max(offset);
+        let end = r.end.min(range.end);
+        if start >= end {
+            continue;
+        }
+        if offset < start {
+            pieces.push((printable(offset..start), None));
+        }
+        pieces.push((printable(start..end), Some(*color)));
+        offset = end;
+    }
+    if offset < range.end {
+        pieces.push((printable(offset..range.end), None));
+    }
+
+    pieces
+}
+
 #[cfg(test)]
 mod tests {
     use std::path::PathBuf;
@@ -467,14 +652,18 @@ mod tests {
     use insta::assert_debug_snapshot;
     use pretty_assertions::assert_eq;
     use ratatui::layout::Rect;
+    use ratatui::style::Color;
+    use ratatui::text::Line;
     use regex::bytes::Regex;
 
     use crate::model::*;
     use crate::rg::de::test_utilities::*;
     use crate::rg::de::*;
     use crate::ui::app::{AppListState, AppUiState};
+    use crate::ui::highlight::Highlighter;
     use crate::ui::line::*;
     use crate::ui::render::UiItemContext;
+    use crate::ui::theme::Theme;
 
     pub fn new_item(raw_json: &str) -> Item {
         Item::new(0, RgMessage::from_str(raw_json))
@@ -609,6 +798,8 @@ mod tests {
         );
     }
 
+    static THEME: Theme = Theme::dark();
+
     fn new_ui_item_ctx<'a>(
         replacement_text: Option<&'a str>,
         app_list_state: &'a AppListState,
@@ -621,10 +812,15 @@ mod tests {
 
         UiItemContext {
             capture_pattern: None,
+            literal: false,
+            preserve_case: false,
+            match_counts: None,
             printable_style: PrintableStyle::Hidden,
             replacement_text,
             app_list_state,
             app_ui_state,
+            highlighter: None,
+            theme: &THEME,
             list_rect: Rect::new(0, 0, 80, 24),
         }
     }
@@ -681,7 +877,7 @@ mod tests {
         let app_list_state = new_app_list_state();
         let app_ui_state = AppUiState::InputReplacement(String::from(replacement), 0);
         let mut ctx = new_ui_item_ctx(Some(replacement), &app_list_state, &app_ui_state);
-        let re = Regex::new(r"(new)\((rg_msg)\)").unwrap();
+        let re = CaptureResolver::from(Regex::new(r"(new)\((rg_msg)\)").unwrap());
         ctx.capture_pattern = Some(&re);
 
         assert_debug_snapshot!(new_item(RG_JSON_BEGIN).to_span_lines(&ctx));
@@ -696,7 +892,7 @@ mod tests {
         let app_list_state = new_app_list_state();
         let app_ui_state = AppUiState::ConfirmReplacement(String::from(replacement), 0);
         let mut ctx = new_ui_item_ctx(Some(replacement), &app_list_state, &app_ui_state);
-        let re = Regex::new(r"(new)\((rg_msg)\)").unwrap();
+        let re = CaptureResolver::from(Regex::new(r"(new)\((rg_msg)\)").unwrap());
         ctx.capture_pattern = Some(&re);
 
         assert_debug_snapshot!(new_item(RG_JSON_BEGIN).to_span_lines(&ctx));
@@ -705,6 +901,111 @@ mod tests {
         assert_debug_snapshot!(new_item(RG_JSON_END).to_span_lines(&ctx));
     }
 
+    #[test]
+    fn to_span_lines_with_text_input_replacement_and_capture_pattern_per_match() {
+        let replacement = "${2}($1)";
+        let app_list_state = new_app_list_state();
+        let app_ui_state = AppUiState::InputReplacement(String::from(replacement), 0);
+        let mut ctx = new_ui_item_ctx(Some(replacement), &app_list_state, &app_ui_state);
+        let re = CaptureResolver::from(Regex::new(r"(\w)(\w+)").unwrap());
+        ctx.capture_pattern = Some(&re);
+
+        let replaced_text = new_item(RG_JSON_MATCH)
+            .to_span_lines(&ctx)
+            .iter()
+            .flat_map(|line| line.spans.iter())
+            .filter(|span| span.style.fg == Some(Color::Green))
+            .map(|span| span.content.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(replaced_text, ["tem(I)", "g_msg(r)"]);
+    }
+
+    #[test]
+    fn to_span_lines_with_text_input_replacement_and_many_capture_patterns() {
+        let replacement = "<$1>";
+        let app_list_state = new_app_list_state();
+        let app_ui_state = AppUiState::InputReplacement(String::from(replacement), 0);
+        let mut ctx = new_ui_item_ctx(Some(replacement), &app_list_state, &app_ui_state);
+        let resolver = CaptureResolver::new(&[r"I(tem)", r"rg_(\w+)"]).unwrap();
+        ctx.capture_pattern = Some(&resolver);
+
+        let replaced_text = new_item(RG_JSON_MATCH)
+            .to_span_lines(&ctx)
+            .iter()
+            .flat_map(|line| line.spans.iter())
+            .filter(|span| span.style.fg == Some(Color::Green))
+            .map(|span| span.content.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(replaced_text, ["<tem>", "<msg>"]);
+    }
+
+    #[test]
+    fn to_span_lines_with_text_input_replacement_and_override() {
+        let replacement = "NEW";
+        let app_list_state = new_app_list_state();
+        let app_ui_state = AppUiState::InputReplacement(String::from(replacement), 0);
+        let ctx = new_ui_item_ctx(Some(replacement), &app_list_state, &app_ui_state);
+
+        let mut item = new_item(RG_JSON_MATCH);
+        item.set_replacement(1, Some("OVERRIDE".into()));
+        let replaced_text = item
+            .to_span_lines(&ctx)
+            .iter()
+            .flat_map(|line| line.spans.iter())
+            .filter_map(|span| match span.style.fg {
+                Some(Color::Green) | Some(Color::Cyan) => {
+                    Some((span.content.to_string(), span.style.fg.unwrap()))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            replaced_text,
+            [
+                ("NEW".to_string(), Color::Green),
+                ("OVERRIDE".to_string(), Color::Cyan)
+            ]
+        );
+    }
+
+    #[test]
+    fn to_span_lines_with_text_input_replacement_preserving_case() {
+        let replacement = "new_value";
+        let app_list_state = new_app_list_state();
+        let app_ui_state = AppUiState::InputReplacement(String::from(replacement), 0);
+        let mut ctx = new_ui_item_ctx(Some(replacement), &app_list_state, &app_ui_state);
+        ctx.preserve_case = true;
+
+        let replaced_text = new_item(RG_JSON_MATCH)
+            .to_span_lines(&ctx)
+            .iter()
+            .flat_map(|line| line.spans.iter())
+            .filter(|span| span.style.fg == Some(Color::Green))
+            .map(|span| span.content.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(replaced_text, ["New_value", "new_value"]);
+    }
+
+    #[test]
+    fn to_span_lines_with_text_input_replacement_literal() {
+        let replacement = "${2}($1)";
+        let app_list_state = new_app_list_state();
+        let app_ui_state = AppUiState::InputReplacement(String::from(replacement), 0);
+        let mut ctx = new_ui_item_ctx(Some(replacement), &app_list_state, &app_ui_state);
+        let re = CaptureResolver::from(Regex::new(r"(\w)(\w+)").unwrap());
+        ctx.capture_pattern = Some(&re);
+        ctx.literal = true;
+
+        let replaced_text = new_item(RG_JSON_MATCH)
+            .to_span_lines(&ctx)
+            .iter()
+            .flat_map(|line| line.spans.iter())
+            .filter(|span| span.style.fg == Some(Color::Green))
+            .map(|span| span.content.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(replaced_text, ["${2}($1)", "${2}($1)"]);
+    }
+
     #[test]
     fn to_span_lines_with_text_selected() {
         let mut app_list_state = new_app_list_state();
@@ -1022,4 +1323,43 @@ mod tests {
         ctx.printable_style = ctx.printable_style.as_one_line();
         assert_debug_snapshot!(new_item(RG_JSON_MATCH).to_span_lines(&ctx));
     }
+
+    #[test]
+    fn to_span_lines_with_syntax_highlighting() {
+        let highlighter = Highlighter::new();
+        let app_list_state = new_app_list_state();
+        let app_ui_state = AppUiState::SelectMatches;
+        let mut ctx = new_ui_item_ctx(None, &app_list_state, &app_ui_state);
+        let plain_item = new_item(RG_JSON_MATCH);
+        let plain_lines = plain_item.to_span_lines(&ctx);
+        ctx.highlighter = Some(&highlighter);
+        let item = new_item(RG_JSON_MATCH);
+        let lines = item.to_span_lines(&ctx);
+
+        // the text is the same, but the parts which aren't matches are highlighted
+        let text = |lines: &[Line]| {
+            lines
+                .iter()
+                .flat_map(|l| l.spans.iter().map(|s| s.content.to_string()))
+                .collect::<String>()
+        };
+        assert_eq!(text(&lines), text(&plain_lines));
+        for span in &lines[0].spans {
+            match span.content.as_ref() {
+                "Item" | "rg_msg" => assert_eq!(span.style.bg, Some(Color::Red)),
+                "197:" => assert_eq!(span.style.fg, Some(Color::DarkGray)),
+                _ => assert!(matches!(span.style.fg, Some(Color::Rgb(_, _, _)))),
+            }
+        }
+
+        let item = new_item(RG_JSON_CONTEXT);
+        assert_eq!(
+            text(&item.to_span_lines(&ctx)),
+            text(&new_item(RG_JSON_CONTEXT).to_span_lines(&new_ui_item_ctx(
+                None,
+                &app_list_state,
+                &app_ui_state
+            )))
+        );
+    }
 }
@@ -1,4 +1,4 @@
-use ratatui::style::{Color, Modifier, Style};
+use ratatui::style::Style;
 use ratatui::text::Span;
 
 use crate::model::Printable;
@@ -10,6 +10,9 @@ pub struct SubItem {
     pub index: usize,
     pub sub_match: SubMatch,
     pub should_replace: bool,
+    /// If set, this match is replaced with this text rather than the text the user entered for
+    /// all the matches.
+    pub replacement: Option<String>,
 }
 
 impl SubItem {
@@ -18,6 +21,7 @@ impl SubItem {
             index,
             sub_match,
             should_replace: true,
+            replacement: None,
         }
     }
 }
@@ -26,21 +30,27 @@ impl SubItem {
     /// A SubItem contains the "match". A match _may_ be over multiple lines, but there will only ever
     /// be a single span on each line. So this returns a list of "lines": one span for each line.
     pub fn to_span_lines(&self, ctx: &UiItemContext, is_item_selected: bool) -> Vec<Span> {
+        let theme = ctx.theme;
         let mut s = Style::default();
         if ctx.app_ui_state.is_replacing() {
             if self.should_replace {
-                s = s.fg(Color::Red).add_modifier(Modifier::CROSSED_OUT);
+                s = theme.match_replaced;
             }
         } else if is_item_selected && ctx.app_list_state.selected_submatch() == self.index {
             if self.should_replace {
-                s = s.fg(Color::Black).bg(Color::Yellow);
+                s = theme.cursor_selected;
             } else {
-                s = s.fg(Color::Yellow).bg(Color::DarkGray);
+                s = theme.cursor_deselected;
             }
         } else if self.should_replace {
-            s = s.fg(Color::Black).bg(Color::Red);
+            s = theme.match_selected;
         } else {
-            s = s.fg(Color::Red).bg(Color::DarkGray);
+            s = theme.match_deselected;
+        }
+
+        // mark matches which have their own replacement
+        if !ctx.app_ui_state.is_replacing() && self.replacement.is_some() {
+            s = s.patch(theme.override_marker);
         }
 
         self.sub_match
@@ -1,4 +1,9 @@
 pub mod app;
+pub mod highlight;
+pub mod input;
+pub mod keymap;
 pub mod line;
+pub mod preview;
 pub mod render;
+pub mod theme;
 pub mod tui;
@@ -0,0 +1,354 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::ops::Range;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use ratatui::style::Style;
+use ratatui::text::{Line, Span};
+
+use crate::encoding::get_encoder;
+use crate::format_line_number;
+use crate::model::Printable;
+use crate::rg::RgEncoding;
+use crate::ui::line::Item;
+use crate::ui::render::UiItemContext;
+
+/// How many lines from the file are shown before and after the match in the preview.
+pub const PREVIEW_CONTEXT_LINES: usize = 3;
+
+/// How much of a file is read to find the lines around a match. Matches further into the file than
+/// this are shown without any context, so the interface doesn't stall on very large files.
+const PREVIEW_READ_LIMIT: u64 = 16 * 1024 * 1024;
+
+const BUF_SIZE: usize = 64 * 1024;
+
+/// Lines read from a file to show around a match in the preview.
+#[derive(Debug, PartialEq, Eq)]
+pub struct FileLines {
+    /// The line number of the first line.
+    pub first_line_number: usize,
+    pub lines: Vec<String>,
+}
+
+impl FileLines {
+    /// Reads the lines of the file at `path` which are shown around a match that starts on line
+    /// `line_number` and spans `line_count` lines.
+    ///
+    /// Only the file up to the last of those lines is read, and it's decoded in the same way as it
+    /// is when the replacements are made (see `encoding::get_encoder`), except that the encoding
+    /// is detected from the start of the file rather than all of it.
+    pub fn read(
+        path: &Path,
+        rg_encoding: &RgEncoding,
+        line_number: usize,
+        line_count: usize,
+    ) -> Result<FileLines> {
+        let first_line_number = line_number.saturating_sub(PREVIEW_CONTEXT_LINES).max(1);
+        let last_line_number = line_number + line_count.max(1) - 1 + PREVIEW_CONTEXT_LINES;
+
+        let mut reader =
+            BufReader::with_capacity(BUF_SIZE, File::open(path)?.take(PREVIEW_READ_LIMIT));
+        let (bom, encoder) = get_encoder(reader.fill_buf()?, rg_encoding);
+        // unlike when replacing, a UTF8 BOM is stripped too since it's never shown
+        reader.consume(bom.map_or(0, |bom| bom.len()));
+
+        let mut decoder = encoder.raw_decoder();
+        let mut decoded = String::new();
+        let mut lines = vec![];
+        let mut current_line_number = 1;
+        let mut push_lines = |decoded: &mut String, lines: &mut Vec<String>, eof: bool| {
+            while current_line_number <= last_line_number {
+                let line = match decoded.find('\n') {
+                    Some(end) => decoded.drain(..=end).collect::<String>(),
+                    None if eof && !decoded.is_empty() => std::mem::take(decoded),
+                    None => break,
+                };
+                if current_line_number >= first_line_number {
+                    lines.push(line.trim_end_matches(&['\r', '\n'][..]).to_owned());
+                }
+                current_line_number += 1;
+            }
+            // don't keep the start of a line that isn't shown, since it may be very long
+            if current_line_number < first_line_number {
+                decoded.clear();
+            }
+            current_line_number > last_line_number
+        };
+
+        loop {
+            let buf = reader.fill_buf()?;
+            if buf.is_empty() {
+                if let Some(e) = decoder.raw_finish(&mut decoded) {
+                    return Err(anyhow!("Failed to decode file: {}", e.cause));
+                }
+                push_lines(&mut decoded, &mut lines, true);
+                break;
+            }
+
+            let (len, err) = decoder.raw_feed(buf, &mut decoded);
+            if let Some(e) = err {
+                return Err(anyhow!("Failed to decode file: {}", e.cause));
+            }
+            reader.consume(len);
+            if push_lines(&mut decoded, &mut lines, false) {
+                break;
+            }
+        }
+
+        Ok(FileLines {
+            first_line_number,
+            lines,
+        })
+    }
+}
+
+/// A piece of text with a style, which may contain newlines.
+type Segment = (Vec<u8>, Style);
+
+/// Builds the preview of a match for the preview pane: its lines before and after the replacement,
+/// surrounded by a few lines of context.
+///
+/// `file_lines` are the lines of the file around the match, which are used for the context. If the
+/// file couldn't be read, only the lines of the match itself are shown.
+pub fn preview_lines<'a>(
+    item: &Item,
+    ctx: &UiItemContext,
+    file_lines: Option<&FileLines>,
+) -> (Vec<Line<'a>>, Vec<Line<'a>>) {
+    let lines = match item.lines() {
+        Some(lines) => lines.to_vec(),
+        None => return (vec![], vec![]),
+    };
+
+    let theme = ctx.theme;
+    let mut before = vec![];
+    let mut after = vec![];
+    let mut offset = 0;
+    for (idx, sub_item) in item.sub_items().iter().enumerate() {
+        let Range { start, end } = sub_item.sub_match.range;
+        let leading = (lines[offset..start].to_vec(), Style::default());
+        before.push(leading.clone());
+        after.push(leading);
+
+        let matched = lines[start..end].to_vec();
+        match item.replacement_at(idx, ctx) {
+            Some(replacement) if sub_item.should_replace => {
+                before.push((matched, theme.preview_match));
+                let style = if sub_item.replacement.is_some() {
+                    theme.replacement_override
+                } else {
+                    theme.replacement
+                };
+                after.push((replacement, style));
+            }
+            _ => {
+                before.push((matched.clone(), theme.preview_deselected));
+                after.push((matched, theme.preview_deselected));
+            }
+        }
+
+        offset = end;
+    }
+    let trailing = (lines[offset..].to_vec(), Style::default());
+    before.push(trailing.clone());
+    after.push(trailing);
+
+    // surround the match with the lines around it in the file
+    let first_line_number = item.line_number().copied();
+    let (context_before, context_after, first_line_number) = match (file_lines, first_line_number) {
+        (Some(file_lines), Some(n))
+            if n >= file_lines.first_line_number
+                && n - file_lines.first_line_number < file_lines.lines.len() =>
+        {
+            let file_lines_start = file_lines.first_line_number;
+            let file_lines = &file_lines.lines;
+            let match_start = n - file_lines_start;
+            let start = match_start.saturating_sub(PREVIEW_CONTEXT_LINES);
+            let match_end = (match_start + match_line_count(&lines)).min(file_lines.len());
+            let end = (match_end + PREVIEW_CONTEXT_LINES).min(file_lines.len());
+            (
+                &file_lines[start..match_start],
+                &file_lines[match_end..end],
+                Some(file_lines_start + start),
+            )
+        }
+        _ => (&[][..], &[][..], first_line_number),
+    };
+
+    let context = |lines: &[String]| -> Vec<Segment> {
+        lines
+            .iter()
+            .map(|l| (format!("{}\n", l).into_bytes(), theme.preview_context))
+            .collect()
+    };
+    let mut before_segments = context(context_before);
+    before_segments.extend(before);
+    before_segments.extend(context(context_after));
+    let mut after_segments = context(context_before);
+    after_segments.extend(after);
+    after_segments.extend(context(context_after));
+
+    (
+        to_lines(before_segments, first_line_number, ctx),
+        to_lines(after_segments, first_line_number, ctx),
+    )
+}
+
+/// Returns the number of lines the text of a match spans.
+pub fn match_line_count(lines: &[u8]) -> usize {
+    lines.iter().filter(|&&b| b == b'\n').count() + usize::from(!lines.ends_with(b"\n"))
+}
+
+/// Splits the segments into lines, which are numbered from `first_line_number` if it's set.
+fn to_lines<'a>(
+    segments: Vec<Segment>,
+    first_line_number: Option<usize>,
+    ctx: &UiItemContext,
+) -> Vec<Line<'a>> {
+    let mut lines = vec![];
+    let mut spans = vec![];
+    for (text, style) in segments {
+        let text = String::from_utf8_lossy(&text).into_owned();
+        let mut parts = text.split('\n').peekable();
+        while let Some(part) = parts.next() {
+            if !part.is_empty() {
+                spans.push(Span::styled(part.to_printable(ctx.printable_style), style));
+            }
+            if parts.peek().is_some() {
+                lines.push(std::mem::take(&mut spans));
+            }
+        }
+    }
+    if !spans.is_empty() {
+        lines.push(spans);
+    }
+
+    lines
+        .into_iter()
+        .enumerate()
+        .map(|(i, mut spans)| {
+            if let Some(n) = first_line_number {
+                spans.insert(
+                    0,
+                    Span::styled(format_line_number!(n + i), ctx.theme.line_number),
+                );
+            }
+            Line::from(spans)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use ratatui::layout::Rect;
+
+    use super::*;
+    use crate::model::PrintableStyle;
+    use crate::rg::de::test_utilities::*;
+    use crate::rg::de::*;
+    use crate::temp_file;
+    use crate::ui::app::{AppListState, AppUiState};
+    use crate::ui::theme::Theme;
+
+    fn to_strings(lines: Vec<Line>) -> Vec<String> {
+        lines
+            .into_iter()
+            .map(|l| l.spans.iter().map(|s| s.content.as_ref()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn it_shows_the_match_before_and_after_with_context() {
+        let mut item = Item::new(
+            0,
+            RgMessageBuilder::new(RgMessageKind::Match)
+                .with_path_text("file")
+                .with_offset(8)
+                .with_line_number(3)
+                .with_lines_text("foo bar\nbar\n")
+                .with_submatches(vec![
+                    SubMatch::new_text("foo", 0..3),
+                    SubMatch::new_text("bar\nbar", 4..11),
+                ])
+                .build(),
+        );
+        item.set_should_replace(0, false);
+
+        let file_lines = FileLines {
+            first_line_number: 1,
+            lines: ["1", "2", "foo bar", "bar", "5", "6", "7"]
+                .iter()
+                .map(|l| l.to_string())
+                .collect(),
+        };
+        let app_list_state = AppListState::new();
+        let app_ui_state = AppUiState::InputReplacement("baz".into(), 3);
+        let ctx = UiItemContext {
+            capture_pattern: None,
+            literal: false,
+            preserve_case: false,
+            match_counts: None,
+            printable_style: PrintableStyle::Hidden,
+            replacement_text: Some("baz"),
+            app_list_state: &app_list_state,
+            app_ui_state: &app_ui_state,
+            highlighter: None,
+            theme: &Theme::default(),
+            list_rect: Rect::new(0, 0, 80, 24),
+        };
+
+        let (before, after) = preview_lines(&item, &ctx, Some(&file_lines));
+        assert_eq!(
+            to_strings(before),
+            vec!["1:1", "2:2", "3:foo bar", "4:bar", "5:5", "6:6", "7:7"]
+        );
+        assert_eq!(
+            to_strings(after),
+            vec!["1:1", "2:2", "3:foo baz", "4:5", "5:6", "6:7"]
+        );
+
+        // without the file, only the match is shown
+        let (before, _) = preview_lines(&item, &ctx, None);
+        assert_eq!(to_strings(before), vec!["3:foo bar", "4:bar"]);
+    }
+
+    #[test]
+    fn it_only_reads_the_lines_around_the_match() {
+        let p = temp_file!("1\n2\n3\n4\r\n5\n6\n7\n8\n9\n10\n11\n");
+        let lines = |first: usize, lines: &[&str]| FileLines {
+            first_line_number: first,
+            lines: lines.iter().map(|l| l.to_string()).collect(),
+        };
+
+        assert_eq!(
+            FileLines::read(&p, &RgEncoding::None, 5, 2).unwrap(),
+            lines(2, &["2", "3", "4", "5", "6", "7", "8", "9"])
+        );
+        assert_eq!(
+            FileLines::read(&p, &RgEncoding::None, 1, 1).unwrap(),
+            lines(1, &["1", "2", "3", "4"])
+        );
+        assert_eq!(
+            FileLines::read(&p, &RgEncoding::None, 10, 1).unwrap(),
+            lines(7, &["7", "8", "9", "10", "11"])
+        );
+    }
+
+    #[test]
+    fn it_decodes_the_lines_around_the_match() {
+        let text = "foo\nbär\nbaz\n";
+        let utf16: Vec<u8> = text.encode_utf16().flat_map(|c| c.to_le_bytes()).collect();
+        let p = temp_file!(bytes, &[&b"\xFF\xFE"[..], &utf16].concat());
+        let expected = ["foo", "bär", "baz"].iter().map(|l| l.to_string());
+
+        let file_lines = FileLines::read(&p, &RgEncoding::None, 2, 1).unwrap();
+        assert_eq!(file_lines.lines, expected.clone().collect::<Vec<_>>());
+
+        // without a BOM, the encoding passed to ripgrep is used
+        let p = temp_file!(bytes, &utf16);
+        let file_lines = FileLines::read(&p, &RgEncoding::from("utf-16le"), 2, 1).unwrap();
+        assert_eq!(file_lines.lines, expected.collect::<Vec<_>>());
+    }
+}
@@ -1,14 +1,21 @@
-use ratatui::layout::Rect;
-use regex::bytes::Regex;
-
-use crate::model::PrintableStyle;
+use crate::model::{CaptureResolver, MatchCounts, PrintableStyle};
 use crate::ui::app::{AppListState, AppUiState};
+use crate::ui::highlight::Highlighter;
+use crate::ui::theme::Theme;
+use ratatui::layout::Rect;
 
 /// Used when building the UI from the App's state.
 pub struct UiItemContext<'a> {
-    /// Regex to use for capturing groups. If it's not provided, the user didn't
+    /// Used to resolve capturing groups. If it's not provided, the user didn't
     /// pass any capturing groups.
-    pub capture_pattern: Option<&'a Regex>,
+    pub capture_pattern: Option<&'a CaptureResolver>,
+    /// If true, the replacement text is used literally and capturing groups are never expanded.
+    pub literal: bool,
+    /// If true, the replacement text is converted into the casing style of each match.
+    pub preserve_case: bool,
+    /// The number of each match, used when the replacement text refers to it. This is only set
+    /// when the user is entering the replacement.
+    pub match_counts: Option<&'a MatchCounts>,
     /// The replacement text the user has entered.
     pub replacement_text: Option<&'a str>,
     /// The current state of the matches list.
@@ -17,6 +24,10 @@ pub struct UiItemContext<'a> {
     pub app_ui_state: &'a AppUiState,
     /// The `PrintableStyle` with which the UI should be built.
     pub printable_style: PrintableStyle,
+    /// Used to highlight the syntax of the lines, if the user asked for it.
+    pub highlighter: Option<&'a Highlighter>,
+    /// The styles with which the UI should be built.
+    pub theme: &'a Theme,
     /// The `Rect` that the items will be rendered into.
     pub list_rect: Rect,
 }
@@ -0,0 +1,429 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail, Result};
+use ratatui::style::{Color, Modifier, Style};
+
+/// Defines `Theme` with a `Style` for each of `$name`, and the names of the styles in the config.
+macro_rules! theme {
+    ($( $(#[doc = $doc:literal])* $name:ident ),* $(,)?) => {
+        /// Every style used by the interface.
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        pub struct Theme {
+            $( $(#[doc = $doc])* pub $name: Style, )*
+        }
+
+        impl Theme {
+            /// The names of the styles, as they're written in the config file.
+            pub const NAMES: &'static [&'static str] = &[$( stringify!($name) ),*];
+
+            fn style_mut(&mut self, name: &str) -> Option<&mut Style> {
+                match name {
+                    $( stringify!($name) => Some(&mut self.$name), )*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+theme! {
+    /// The text in the matches list and the tree view.
+    text,
+    /// Added to the lines of the selected item.
+    selected_line,
+    line_number,
+    path,
+    path_selected,
+    /// A match which will be replaced.
+    match_selected,
+    /// A match which won't be replaced.
+    match_deselected,
+    /// The match under the cursor, if it will be replaced.
+    cursor_selected,
+    /// The match under the cursor, if it won't be replaced.
+    cursor_deselected,
+    /// A match which is being replaced, while the replacement is entered.
+    match_replaced,
+    replacement,
+    /// The replacement of a match which has its own replacement.
+    replacement_override,
+    /// Added to matches which have their own replacement.
+    override_marker,
+    /// The lines around the match in the preview pane.
+    preview_context,
+    /// A match which will be replaced, in the preview pane.
+    preview_match,
+    /// A match which won't be replaced, in the preview pane.
+    preview_deselected,
+    /// The titles of the help and preview panes.
+    title,
+    help_header,
+    /// The hints shown after the text being entered.
+    hint,
+    error,
+    tree_dir,
+    tree_file,
+    tree_cursor,
+    /// The count of a directory or file in the tree view when none of its matches are selected.
+    tree_count_none,
+    /// The count of a directory or file in the tree view when some of its matches are selected.
+    tree_count_some,
+    /// The count of a directory or file in the tree view when all of its matches are selected.
+    tree_count_all,
+    status_line,
+    status_searching,
+    status_filtered,
+    status_cmdline,
+    status_control_chars,
+    status_count,
+    mode_help,
+    mode_select,
+    mode_replace,
+    mode_confirm,
+    mode_search,
+    mode_override,
+    mode_filter,
+    mode_command,
+    mode_tree,
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme::dark()
+    }
+}
+
+const fn fg(color: Color) -> Style {
+    Style::new().fg(color)
+}
+
+const fn fg_bg(fg: Color, bg: Color) -> Style {
+    Style::new().fg(fg).bg(bg)
+}
+
+const fn modifier(modifier: Modifier) -> Style {
+    Style::new().add_modifier(modifier)
+}
+
+impl Theme {
+    /// The names of the presets, as they're written in the config file.
+    pub const PRESETS: &'static [&'static str] = &["dark", "light", "high-contrast", "no-color"];
+
+    /// Returns the preset named `name`.
+    pub fn preset(name: &str) -> Result<Theme> {
+        match name {
+            "dark" => Ok(Theme::dark()),
+            "light" => Ok(Theme::light()),
+            "high-contrast" => Ok(Theme::high_contrast()),
+            "no-color" => Ok(Theme::no_color()),
+            _ => bail!(
+                "unknown theme '{}', expected one of: {}",
+                name,
+                Theme::PRESETS.join(", ")
+            ),
+        }
+    }
+
+    /// The preset used when none is given in the config: `no-color` if `NO_COLOR` is set (see
+    /// https://no-color.org), and `dark` otherwise.
+    pub fn default_preset_name(no_color: bool) -> &'static str {
+        if no_color {
+            "no-color"
+        } else {
+            "dark"
+        }
+    }
+
+    /// Suits terminals with a dark background. This is the default.
+    pub const fn dark() -> Theme {
+        Theme {
+            text: fg(Color::White),
+            selected_line: fg(Color::Yellow),
+            line_number: fg(Color::DarkGray),
+            path: fg(Color::Magenta),
+            path_selected: fg_bg(Color::Black, Color::Yellow),
+            match_selected: fg_bg(Color::Black, Color::Red),
+            match_deselected: fg_bg(Color::Red, Color::DarkGray),
+            cursor_selected: fg_bg(Color::Black, Color::Yellow),
+            cursor_deselected: fg_bg(Color::Yellow, Color::DarkGray),
+            match_replaced: fg(Color::Red).add_modifier(Modifier::CROSSED_OUT),
+            replacement: fg(Color::Green),
+            replacement_override: fg(Color::Cyan),
+            override_marker: modifier(Modifier::UNDERLINED),
+            preview_context: fg(Color::DarkGray),
+            preview_match: fg(Color::Red),
+            preview_deselected: modifier(Modifier::UNDERLINED),
+            title: fg(Color::Magenta),
+            help_header: fg(Color::Yellow).add_modifier(Modifier::BOLD),
+            hint: fg(Color::DarkGray),
+            error: fg(Color::Red),
+            tree_dir: fg(Color::Blue),
+            tree_file: fg(Color::Magenta),
+            tree_cursor: modifier(Modifier::REVERSED),
+            tree_count_none: fg(Color::DarkGray),
+            tree_count_some: fg(Color::Yellow),
+            tree_count_all: fg(Color::Green),
+            status_line: fg_bg(Color::White, Color::DarkGray),
+            status_searching: fg_bg(Color::Black, Color::Yellow),
+            status_filtered: fg_bg(Color::Black, Color::Green),
+            status_cmdline: fg_bg(Color::Black, Color::Blue),
+            status_control_chars: fg_bg(Color::Black, Color::Cyan),
+            status_count: fg_bg(Color::Black, Color::Magenta),
+            mode_help: fg_bg(Color::Black, Color::Green),
+            mode_select: fg_bg(Color::Black, Color::Cyan),
+            mode_replace: fg_bg(Color::Black, Color::White),
+            mode_confirm: fg_bg(Color::Black, Color::Red),
+            mode_search: fg_bg(Color::Black, Color::Yellow),
+            mode_override: fg_bg(Color::Black, Color::Cyan),
+            mode_filter: fg_bg(Color::Black, Color::Green),
+            mode_command: fg_bg(Color::Black, Color::Magenta),
+            mode_tree: fg_bg(Color::Black, Color::Cyan),
+        }
+    }
+
+    /// Suits terminals with a light background.
+    pub fn light() -> Theme {
+        Theme {
+            text: fg(Color::Reset),
+            selected_line: fg(Color::Blue),
+            line_number: fg(Color::DarkGray),
+            path: fg(Color::Magenta),
+            path_selected: fg_bg(Color::White, Color::Blue),
+            match_selected: fg_bg(Color::White, Color::Red),
+            match_deselected: fg_bg(Color::Red, Color::Gray),
+            cursor_selected: fg_bg(Color::White, Color::Blue),
+            cursor_deselected: fg_bg(Color::Blue, Color::Gray),
+            replacement: fg(Color::Green).add_modifier(Modifier::BOLD),
+            replacement_override: fg(Color::Blue).add_modifier(Modifier::BOLD),
+            preview_context: fg(Color::DarkGray),
+            help_header: fg(Color::Blue).add_modifier(Modifier::BOLD),
+            hint: fg(Color::DarkGray),
+            tree_count_some: fg(Color::Blue),
+            status_line: fg_bg(Color::Black, Color::Gray),
+            status_searching: fg_bg(Color::Black, Color::LightYellow),
+            mode_replace: fg_bg(Color::White, Color::Black),
+            ..Theme::dark()
+        }
+    }
+
+    /// Bold colours which are told apart by more than their hue, and don't rely on red and green.
+    pub fn high_contrast() -> Theme {
+        let bold = |style: Style| style.add_modifier(Modifier::BOLD);
+        let mode = bold(fg_bg(Color::Black, Color::White));
+        Theme {
+            text: fg(Color::White),
+            selected_line: bold(fg(Color::LightYellow)),
+            line_number: fg(Color::Gray),
+            path: bold(fg(Color::LightCyan)),
+            path_selected: bold(fg_bg(Color::Black, Color::LightYellow)),
+            match_selected: bold(fg_bg(Color::Black, Color::White)),
+            match_deselected: fg(Color::Gray).add_modifier(Modifier::CROSSED_OUT),
+            cursor_selected: bold(fg_bg(Color::Black, Color::LightYellow)),
+            cursor_deselected: bold(fg(Color::LightYellow)).add_modifier(Modifier::CROSSED_OUT),
+            match_replaced: fg(Color::Gray).add_modifier(Modifier::CROSSED_OUT),
+            replacement: bold(fg(Color::LightBlue)).add_modifier(Modifier::UNDERLINED),
+            replacement_override: bold(fg(Color::LightYellow)).add_modifier(Modifier::UNDERLINED),
+            override_marker: modifier(Modifier::UNDERLINED),
+            preview_context: fg(Color::Gray),
+            preview_match: bold(fg(Color::White)).add_modifier(Modifier::CROSSED_OUT),
+            preview_deselected: modifier(Modifier::UNDERLINED),
+            title: bold(fg(Color::LightCyan)),
+            help_header: bold(fg(Color::LightYellow)),
+            hint: fg(Color::Gray),
+            error: bold(fg(Color::LightYellow)).add_modifier(Modifier::REVERSED),
+            tree_dir: bold(fg(Color::LightBlue)),
+            tree_file: bold(fg(Color::LightCyan)),
+            tree_cursor: modifier(Modifier::REVERSED),
+            tree_count_none: fg(Color::Gray),
+            tree_count_some: bold(fg(Color::LightYellow)),
+            tree_count_all: bold(fg(Color::White)),
+            status_line: fg_bg(Color::White, Color::Black),
+            status_searching: mode,
+            status_filtered: mode,
+            status_cmdline: bold(fg_bg(Color::White, Color::Black)),
+            status_control_chars: mode,
+            status_count: mode,
+            mode_help: mode,
+            mode_select: mode,
+            mode_replace: mode,
+            mode_confirm: bold(fg_bg(Color::Black, Color::LightYellow)),
+            mode_search: mode,
+            mode_override: mode,
+            mode_filter: mode,
+            mode_command: mode,
+            mode_tree: mode,
+        }
+    }
+
+    /// No colours at all, only bold, reversed, underlined and crossed out text.
+    pub fn no_color() -> Theme {
+        let none = Style::default();
+        let reversed = modifier(Modifier::REVERSED);
+        let bold = modifier(Modifier::BOLD);
+        Theme {
+            text: none,
+            selected_line: bold,
+            line_number: none,
+            path: bold,
+            path_selected: reversed,
+            match_selected: reversed,
+            match_deselected: modifier(Modifier::CROSSED_OUT),
+            cursor_selected: reversed.add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+            cursor_deselected: modifier(Modifier::CROSSED_OUT | Modifier::UNDERLINED),
+            match_replaced: modifier(Modifier::CROSSED_OUT),
+            replacement: bold,
+            replacement_override: modifier(Modifier::BOLD | Modifier::ITALIC),
+            override_marker: modifier(Modifier::UNDERLINED),
+            preview_context: none,
+            preview_match: modifier(Modifier::CROSSED_OUT),
+            preview_deselected: modifier(Modifier::UNDERLINED),
+            title: bold,
+            help_header: bold,
+            hint: none,
+            error: bold,
+            tree_dir: bold,
+            tree_file: none,
+            tree_cursor: reversed,
+            tree_count_none: none,
+            tree_count_some: none,
+            tree_count_all: bold,
+            status_line: reversed,
+            status_searching: bold,
+            status_filtered: bold,
+            status_cmdline: none,
+            status_control_chars: none,
+            status_count: bold,
+            mode_help: bold,
+            mode_select: bold,
+            mode_replace: bold,
+            mode_confirm: bold,
+            mode_search: bold,
+            mode_override: bold,
+            mode_filter: bold,
+            mode_command: bold,
+            mode_tree: bold,
+        }
+    }
+
+    /// Replaces the styles named in `styles`.
+    pub fn with_styles(mut self, styles: &HashMap<String, StyleConfig>) -> Result<Theme> {
+        for (name, config) in styles {
+            let style = self.style_mut(name).ok_or_else(|| {
+                anyhow!(
+                    "unknown style '{}', expected one of: {}",
+                    name,
+                    Theme::NAMES.join(", ")
+                )
+            })?;
+            *style = config.to_style()?;
+        }
+
+        Ok(self)
+    }
+}
+
+/// A style as it's written in the config file, e.g.,
+/// `{ fg = "black", bg = "#ff8800", modifiers = ["bold"] }`.
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StyleConfig {
+    fg: Option<String>,
+    bg: Option<String>,
+    modifiers: Vec<String>,
+}
+
+impl StyleConfig {
+    fn to_style(&self) -> Result<Style> {
+        let color = |name: &String| {
+            name.parse::<Color>()
+                .map_err(|_| anyhow!("unknown color '{}'", name))
+        };
+
+        let mut style = Style::default();
+        if let Some(fg) = &self.fg {
+            style = style.fg(color(fg)?);
+        }
+        if let Some(bg) = &self.bg {
+            style = style.bg(color(bg)?);
+        }
+        for name in &self.modifiers {
+            style = style.add_modifier(match name.as_str() {
+                "bold" => Modifier::BOLD,
+                "dim" => Modifier::DIM,
+                "italic" => Modifier::ITALIC,
+                "underlined" => Modifier::UNDERLINED,
+                "reversed" => Modifier::REVERSED,
+                "crossed_out" => Modifier::CROSSED_OUT,
+                _ => bail!(
+                    "unknown modifier '{}', expected one of: bold, dim, italic, underlined, reversed, crossed_out",
+                    name
+                ),
+            });
+        }
+
+        Ok(style)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn it_loads_presets() {
+        for name in Theme::PRESETS {
+            assert!(Theme::preset(name).is_ok(), "{}", name);
+        }
+        assert!(Theme::preset("solarized").is_err());
+
+        assert_eq!(Theme::default_preset_name(false), "dark");
+        assert_eq!(Theme::default_preset_name(true), "no-color");
+
+        // the no-color theme doesn't set any colours
+        let theme = Theme::no_color();
+        let mut names = Theme::NAMES.to_vec();
+        names.retain(|name| {
+            let style = theme.clone().style_mut(name).copied().unwrap();
+            style.fg.is_some() || style.bg.is_some()
+        });
+        assert_eq!(names, Vec::<&str>::new());
+    }
+
+    #[test]
+    fn it_replaces_styles() {
+        let style = |fg: Option<&str>, bg: Option<&str>, modifiers: &[&str]| StyleConfig {
+            fg: fg.map(str::to_owned),
+            bg: bg.map(str::to_owned),
+            modifiers: modifiers.iter().map(|m| m.to_string()).collect(),
+        };
+
+        let styles = vec![
+            (
+                "match_selected".to_string(),
+                style(Some("black"), Some("#ff8800"), &["bold"]),
+            ),
+            ("hint".to_string(), style(None, None, &[])),
+        ]
+        .into_iter()
+        .collect();
+        let theme = Theme::light().with_styles(&styles).unwrap();
+        assert_eq!(
+            theme.match_selected,
+            Style::default()
+                .fg(Color::Black)
+                .bg(Color::Rgb(0xff, 0x88, 0x00))
+                .add_modifier(Modifier::BOLD)
+        );
+        assert_eq!(theme.hint, Style::default());
+        assert_eq!(theme.path_selected, Theme::light().path_selected);
+
+        let with_style = |name: &str, style| {
+            Theme::dark().with_styles(&vec![(name.to_string(), style)].into_iter().collect())
+        };
+        assert!(with_style("matches", style(None, None, &[])).is_err());
+        assert!(with_style("hint", style(Some("blurple"), None, &[])).is_err());
+        assert!(with_style("hint", style(None, None, &["blink"])).is_err());
+    }
+}
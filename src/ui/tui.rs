@@ -1,22 +1,49 @@
 use std::io::{self, Stdout};
-use std::sync::mpsc::{self, Receiver};
+use std::mem;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, TryRecvError};
 use std::thread;
 use std::time::{Duration, Instant};
 
 use anyhow::Result;
-use crossterm::event::{self, Event, KeyCode};
+use crossterm::event::{
+    self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, MouseEventKind,
+};
 use crossterm::execute;
 use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
 use ratatui::backend::CrosstermBackend;
 use ratatui::layout::Rect;
-use ratatui::style::{Color, Style};
 use ratatui::widgets::{Block, Borders, Paragraph, Wrap};
 use ratatui::Terminal;
-use regex::bytes::Regex;
 
-use crate::model::ReplacementCriteria;
-use crate::rg::de::RgMessage;
+use crate::cli::RgArgs;
+use crate::config::Config;
+use crate::model::{CaptureResolver, ReplacementCriteria};
+use crate::rg::exec::run_ripgrep;
+use crate::rg::read::RgMessageReceiver;
+use crate::session::SessionSaver;
 use crate::ui::app::{App, AppState};
+use crate::ui::theme::Theme;
+
+/// How often the UI is redrawn while ripgrep is still sending results.
+const SEARCHING_REDRAW_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The most messages from ripgrep that are added to the app before it's redrawn, so that a fast
+/// search doesn't stop the interface from updating or responding to input.
+const MAX_RG_MESSAGES_PER_DRAW: usize = 2000;
+
+/// The most time spent adding messages from ripgrep to the app before it's redrawn.
+const MAX_RG_MESSAGES_DURATION: Duration = Duration::from_millis(20);
+
+/// How far ripgrep's messages have been received.
+#[derive(Debug, PartialEq, Eq)]
+enum SearchProgress {
+    /// Every message sent so far has been received, and ripgrep is still running.
+    Waiting,
+    /// Some messages are still waiting to be received, since the limit for this draw was reached.
+    Backlogged,
+    /// Ripgrep has finished and every message has been received.
+    Finished,
+}
 
 const FALLBACK_MESSAGE: &str = r#"
 You may continue to use repgrep, however capturing groups will be ignored for this session."#;
@@ -24,6 +51,8 @@ You may continue to use repgrep, however capturing groups will be ignored for th
 pub struct Tui {
     term: Terminal<CrosstermBackend<Stdout>>,
     rx: Receiver<Event>,
+    /// The styles used to draw the interface.
+    theme: Theme,
 }
 
 impl Tui {
@@ -32,7 +61,7 @@ impl Tui {
 
         let mut stdout = io::stdout();
         // NOTE: must match options in `Self::restore_terminal()`
-        execute!(stdout, EnterAlternateScreen)?;
+        execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
 
         let backend = CrosstermBackend::new(stdout);
         let mut term = Terminal::new(backend)?;
@@ -50,14 +79,19 @@ impl Tui {
 
         term.clear()?;
 
-        Ok(Tui { term, rx })
+        Ok(Tui {
+            term,
+            rx,
+            theme: Theme::default(),
+        })
     }
 
     fn draw_message_box(&mut self, title: impl AsRef<str>, body: impl AsRef<str>) -> Result<()> {
         self.term.clear()?;
+        let theme = &self.theme;
         self.term.draw(|f| {
             let block = Block::default()
-                .style(Style::default().fg(Color::Red))
+                .style(theme.error)
                 .borders(Borders::ALL)
                 .title(title.as_ref());
 
@@ -91,7 +125,7 @@ impl Tui {
             f.render_widget(
                 Paragraph::new(body)
                     .wrap(Wrap { trim: true })
-                    .style(Style::default().fg(Color::White)),
+                    .style(theme.text),
                 p_frame,
             );
         })?;
@@ -113,46 +147,11 @@ impl Tui {
         Ok(())
     }
 
-    pub fn start(
-        mut self,
-        rg_cmdline: String,
-        rg_messages: Vec<RgMessage>,
-        patterns: &[String],
-    ) -> Result<Option<ReplacementCriteria>> {
-        // Parse patterns into `Regex` structs
-        let patterns = patterns
-            .into_iter()
-            .map(|p| Regex::new(p))
-            .collect::<Result<Vec<_>, _>>();
-
-        // Check if we should be performing replacements with capturing groups.
-        let capture_pattern = match patterns {
-            // pattern with capturing group passed, and we only have one
-            Ok(mut one) if one.len() == 1 => {
-                // SAFETY: we just checked for length in this match
-                (one[0].captures_len() > 1).then_some(one.pop().unwrap())
-            }
-            // many patterns passed, and one had a capturing group
-            // all regex's have at least one capturing group, see: https://docs.rs/regex/1.8.4/regex/struct.Captures.html#method.len
-            Ok(many) if many.iter().any(|re| re.captures_len() > 1) => {
-                self.draw_message_box(
-                    "Unsupported Arguments!",
-                    format!(
-                        "{}\n\nPatterns:\n\n{patterns}\n\n{fallback}",
-                        "Either pass a single pattern with capturing groups, or many patterns without capturing groups.",
-                        patterns = many
-                            .iter()
-                            .map(|re| format!("  - {}", re.as_str()))
-                            .collect::<Vec<_>>()
-                            .join("\n"),
-                            fallback = FALLBACK_MESSAGE
-                    ),
-                )?;
-
-                None
-            }
-            // many patterns passed, none had capturing groups
-            Ok(_) => None,
+    /// Parses the patterns into a `CaptureResolver` if they should be used for capturing groups
+    /// when replacing, see `RgArgs::capture_pattern`.
+    fn capture_pattern(&mut self, args: &RgArgs) -> Result<Option<CaptureResolver>> {
+        Ok(match args.capture_pattern() {
+            Ok(resolver) => resolver,
             // failed to parse patterns
             Err(e) => {
                 self.draw_message_box(
@@ -167,40 +166,192 @@ impl Tui {
 
                 None
             }
-        };
+        })
+    }
+
+    /// Runs the app until the user either cancels or accepts the replacements. If the user runs
+    /// the search again with new args, `args` is updated to match.
+    pub fn start(
+        mut self,
+        args: &mut RgArgs,
+        config: Config,
+        rg_messages: RgMessageReceiver,
+        mut session: SessionSaver,
+    ) -> Result<Option<ReplacementCriteria>> {
+        self.theme = config.theme.clone();
+        let capture_pattern = self.capture_pattern(args)?;
 
         // main app event loop
-        let mut app = App::new(capture_pattern, rg_cmdline, rg_messages);
-        let mut term = self.term;
+        let mut app = App::new(capture_pattern, args.rg_cmdline(), vec![]);
+        app.set_literal(args.fixed_strings);
+        app.set_encoding(&args.encoding);
+        app.set_search_cmdline(args.search_cmdline());
+        app.set_dry_run(args.options.dry_run.is_some());
+        app.set_highlight(args.options.highlight);
+        app.set_keymap(config.keymap);
+        app.set_theme(config.theme);
+        let mut rg_messages = Some(rg_messages);
+        // The args of the last complete search, kept while a new search is running in case it fails.
+        let mut previous_args: Option<RgArgs> = None;
         loop {
+            // Add any results that have arrived since the last draw.
+            let mut backlogged = false;
+            if let Some(rx) = &rg_messages {
+                match Self::receive_rg_messages(rx, &mut app) {
+                    Ok(SearchProgress::Waiting) => {}
+                    Ok(SearchProgress::Backlogged) => backlogged = true,
+                    Ok(SearchProgress::Finished) => {
+                        rg_messages = None;
+                        previous_args = None;
+                        session.search_finished(&mut app, self.term.get_frame().size());
+                    }
+                    Err(e) => {
+                        rg_messages = None;
+                        match previous_args.take() {
+                            Some(mut prev) if app.restore_previous_search() => {
+                                prev.options = mem::take(&mut args.options);
+                                *args = prev;
+                                app.set_encoding(&args.encoding);
+                                session.search_finished(&mut app, self.term.get_frame().size());
+                                self.draw_message_box("Search failed!", e.to_string())?;
+                            }
+                            _ => return Err(e),
+                        }
+                    }
+                }
+            }
+
+            app.update_preview();
             let before_draw = Instant::now();
-            term.draw(|mut f| app.draw(&mut f))?;
+            self.term.draw(|f| app.draw(f))?;
 
             // If drawing to the terminal is slow, flush all keyboard events so they're not buffered.
             // (Otherwise with very slow updates, the user has to wait for all keyboard events to be processed
             // before being able to quit the app, etc).
             if before_draw.elapsed() > Duration::from_millis(20) {
-                while let Ok(_) = self.rx.try_recv() {}
+                while self.rx.try_recv().is_ok() {}
             }
 
-            let event = self.rx.recv()?;
-            let term_size = term.get_frame().size();
+            // While ripgrep is still sending results, don't block on terminal events so that we
+            // can keep drawing them as they arrive. If some are still waiting, only check for
+            // events before adding the next batch.
+            let event = if rg_messages.is_some() {
+                let timeout = if backlogged {
+                    Duration::ZERO
+                } else {
+                    SEARCHING_REDRAW_INTERVAL
+                };
+                match self.rx.recv_timeout(timeout) {
+                    Ok(event) => event,
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(e) => return Err(e.into()),
+                }
+            } else if session.is_dirty() {
+                // save the session once the user has stopped making changes for a moment
+                match self.rx.recv_timeout(session.save_delay()) {
+                    Ok(event) => event,
+                    Err(RecvTimeoutError::Timeout) => {
+                        session.save(&app);
+                        continue;
+                    }
+                    Err(e) => return Err(e.into()),
+                }
+            } else {
+                self.rx.recv()?
+            };
+            let is_input = match &event {
+                Event::Key(_) => true,
+                Event::Mouse(mouse) => mouse.kind != MouseEventKind::Moved,
+                _ => false,
+            };
+            if is_input {
+                session.changed();
+            }
+            let term_size = self.term.get_frame().size();
             app.on_event(term_size, event)?;
 
+            // Run the search again if the user asked for it.
+            if let Some(search_cmdline) = app.take_search_request() {
+                let result = RgArgs::parse_search(&search_cmdline)
+                    .and_then(|new_args| run_ripgrep(new_args.rg_args()).map(|rx| (new_args, rx)));
+                match result {
+                    Ok((mut new_args, rx)) => {
+                        // rgr's own options can only be set on the command line
+                        new_args.options = mem::take(&mut args.options);
+                        let capture_pattern = self.capture_pattern(&new_args)?;
+                        app.restart_search(
+                            capture_pattern,
+                            new_args.fixed_strings,
+                            new_args.rg_cmdline(),
+                            new_args.search_cmdline(),
+                        );
+                        app.set_encoding(&new_args.encoding);
+                        session.search_started();
+
+                        let old_args = mem::replace(args, new_args);
+                        if previous_args.is_none() {
+                            previous_args = Some(old_args);
+                        }
+                        rg_messages = Some(rx);
+                    }
+                    Err(e) => {
+                        self.draw_message_box(
+                            "Error!",
+                            format!("Failed to run the search!\n\nError: {}", e),
+                        )?;
+                        app.edit_search(search_cmdline);
+                    }
+                }
+            }
+
             match app.state {
-                AppState::Running => continue,
-                AppState::Cancelled => return Ok(None),
-                AppState::Complete => return Ok(Some(app.get_replacement_criteria()?)),
+                AppState::Running => {}
+                AppState::Cancelled => {
+                    session.save(&app);
+                    return Ok(None);
+                }
+                AppState::Complete => {
+                    session.save(&app);
+                    return Ok(Some(app.get_replacement_criteria()?));
+                }
+            }
+
+            if session.save_delay().is_zero() {
+                session.save(&app);
             }
         }
     }
 
+    /// Adds the messages that have been received from ripgrep to the app, stopping early once
+    /// `MAX_RG_MESSAGES_PER_DRAW` or `MAX_RG_MESSAGES_DURATION` is reached so the app can be redrawn.
+    fn receive_rg_messages(rx: &RgMessageReceiver, app: &mut App) -> Result<SearchProgress> {
+        let start = Instant::now();
+        for _ in 0..MAX_RG_MESSAGES_PER_DRAW {
+            match rx.try_recv() {
+                Ok(Ok(rg_message)) => app.add_rg_message(rg_message),
+                Ok(Err(e)) => return Err(e),
+                Err(TryRecvError::Empty) => return Ok(SearchProgress::Waiting),
+                Err(TryRecvError::Disconnected) => return Ok(SearchProgress::Finished),
+            }
+
+            if start.elapsed() > MAX_RG_MESSAGES_DURATION {
+                break;
+            }
+        }
+
+        Ok(SearchProgress::Backlogged)
+    }
+
     pub fn restore_terminal() -> Result<()> {
         let backend = CrosstermBackend::new(io::stdout());
         let mut term = Terminal::new(backend)?;
 
         terminal::disable_raw_mode()?;
-        execute!(term.backend_mut(), LeaveAlternateScreen)?;
+        execute!(
+            term.backend_mut(),
+            LeaveAlternateScreen,
+            DisableMouseCapture
+        )?;
         term.show_cursor()?;
         term.clear()?;
         term.set_cursor(0, 0)?;
@@ -208,3 +359,44 @@ impl Tui {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::rg::de::test_utilities::RgMessageBuilder;
+    use crate::rg::de::{RgMessageKind, SubMatch};
+
+    #[test]
+    fn it_receives_rg_messages_in_batches() {
+        let mut app = App::new(None, "TESTS".to_string(), vec![]);
+        let (tx, rx) = mpsc::channel();
+        for _ in 0..MAX_RG_MESSAGES_PER_DRAW + 5 {
+            let rg_message = RgMessageBuilder::new(RgMessageKind::Match)
+                .with_path_text("foo.txt")
+                .with_lines_text("foo bar baz")
+                .with_submatches(vec![SubMatch::new_text("foo", 0..3)])
+                .with_offset(0)
+                .build();
+            tx.send(Ok(rg_message)).unwrap();
+        }
+
+        let mut batches = 0;
+        let progress = loop {
+            batches += 1;
+            match Tui::receive_rg_messages(&rx, &mut app).unwrap() {
+                SearchProgress::Backlogged => continue,
+                progress => break progress,
+            }
+        };
+        assert!(batches > 1);
+        assert_eq!(progress, SearchProgress::Waiting);
+
+        drop(tx);
+        assert_eq!(
+            Tui::receive_rg_messages(&rx, &mut app).unwrap(),
+            SearchProgress::Finished
+        );
+    }
+}
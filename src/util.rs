@@ -1,3 +1,7 @@
+use std::io::{self, Read};
+
+use sha2::{Digest, Sha256};
+
 pub fn clamp(val: usize, min: usize, max: usize) -> usize {
     if val <= min {
         min
@@ -8,7 +12,7 @@ pub fn clamp(val: usize, min: usize, max: usize) -> usize {
     }
 }
 
-pub fn byte_pos_from_char_pos(s: &String, char_pos: usize) -> usize {
+pub fn byte_pos_from_char_pos(s: &str, char_pos: usize) -> usize {
     let mut idx = 0;
     for (i, ch) in s.chars().enumerate() {
         if i == char_pos {
@@ -20,3 +24,15 @@ pub fn byte_pos_from_char_pos(s: &String, char_pos: usize) -> usize {
 
     idx
 }
+
+/// Returns the SHA-256 hash of `bytes` as a hex string.
+pub fn hash(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+/// Returns the SHA-256 hash of everything read from `reader`, in the same form as `hash`.
+pub fn hash_reader(mut reader: impl Read) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut reader, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}